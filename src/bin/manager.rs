@@ -4,7 +4,7 @@
 use clap::Parser;
 
 use halo_lib::{
-    self, cluster,
+    self,
     commands::{self, Cli},
     manager,
 };
@@ -17,15 +17,16 @@ use halo_lib::{
 /// Otherwise, the indicated sub-command will run.
 fn main() {
     let args = Cli::parse();
+    halo_lib::init_logging(args.verbose);
 
     let res = match &args.command {
         Some(command) => commands::main(&args, command),
         None => {
             let context = manager::MgrContext::new(args);
-            let Ok(cluster) = cluster::Cluster::new(std::sync::Arc::new(context)) else {
+            let Ok(clusters) = manager::load_clusters(std::sync::Arc::new(context)) else {
                 std::process::exit(1);
             };
-            manager::main(cluster)
+            manager::main(clusters)
         }
     };
 
@@ -7,6 +7,7 @@ use halo_lib::remote::{self, Cli};
 
 fn main() {
     let args = Cli::parse();
+    halo_lib::init_logging(u8::from(args.verbose));
 
     if remote::agent_main(args).is_err() {
         std::process::exit(1);
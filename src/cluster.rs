@@ -1,11 +1,11 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
 
-use futures::future;
+use {futures::future, log::error};
 
-use crate::{host::*, manager::MgrContext, resource::*};
+use crate::{config::ConfigError, host::*, manager::MgrContext, resource::*};
 
 /// Cluster is the model used to represent the dynamic state of a cluster in memory.
 /// Unlike the persistent model which views a cluster as made up of nodes, which own services,
@@ -15,15 +15,36 @@ use crate::{host::*, manager::MgrContext, resource::*};
 /// This model is slightly more convenient for performing cluster operations.
 #[derive(Debug)]
 pub struct Cluster {
+    /// The config file that the cluster was created from, which is read again when the config is
+    /// reloaded.
+    config_path: String,
+
+    /// The name of the cluster, which tells it apart from the others when the manager manages
+    /// several.
+    name: String,
+
     resource_groups: Vec<ResourceGroup>,
     num_zpools: u32,
     num_targets: u32,
+    num_resources: u32,
 
     /// The hosts in the Cluster are mapped by their ID, a unique identifier which is the hostname
     /// normally. However, in the test environment, it is a test-defined identifier since the
     /// hostname would not be a useful unique ID in the test environment.
     hosts: HashMap<String, Arc<Host>>,
 
+    /// The colocation constraints, each given as the IDs of the resource groups holding the
+    /// colocated resources.
+    colocations: Vec<Vec<String>>,
+
+    /// The anti-affinity constraints, each given as the IDs of the resource groups holding the
+    /// anti-affine resources.
+    anti_affinities: Vec<Vec<String>>,
+
+    /// Whether the manager manages the resources, rather than only observing them, as decided by
+    /// the command line and the config.
+    manage_resources: bool,
+
     /// A reference to the shared manager context which contains the verbose output stream and a
     /// copy of the CLI arguments.
     pub context: Arc<MgrContext>,
@@ -36,12 +57,27 @@ impl Cluster {
         let futures: Vec<_> = self
             .resource_groups
             .iter()
-            .map(|r| r.main_loop(&self.context.args))
+            .map(|r| r.main_loop(self))
             .collect();
 
         let _ = future::join_all(futures).await;
     }
 
+    /// The config file that the cluster was created from.
+    pub fn config_path(&self) -> &str {
+        &self.config_path
+    }
+
+    /// The name of the cluster: the name of its config file, without the extension.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the manager manages the resources, rather than only observing them.
+    pub fn manage_resources(&self) -> bool {
+        self.manage_resources
+    }
+
     pub fn num_zpools(&self) -> u32 {
         self.num_zpools
     }
@@ -50,12 +86,234 @@ impl Cluster {
         self.num_targets
     }
 
+    /// The total number of resources in the cluster, of every kind.
+    pub fn num_resources(&self) -> u32 {
+        self.num_resources
+    }
+
     pub fn resources(&self) -> impl Iterator<Item = &Resource> {
         self.resource_groups
             .iter()
             .flat_map(|group| group.resources())
     }
 
+    pub fn resource_groups(&self) -> impl Iterator<Item = &ResourceGroup> {
+        self.resource_groups.iter()
+    }
+
+    /// Look up a resource group by the unique ID of its root resource.
+    pub fn get_resource_group(&self, id: &str) -> Option<&ResourceGroup> {
+        self.resource_groups.iter().find(|rg| rg.root.id == id)
+    }
+
+    /// Look up a resource by its unique ID.
+    pub fn get_resource(&self, id: &str) -> Option<&Resource> {
+        self.resources().find(|res| res.id == id)
+    }
+
+    /// Look up the resource group containing the resource with the given unique ID.
+    pub fn get_group_of_resource(&self, id: &str) -> Option<&ResourceGroup> {
+        self.resource_groups
+            .iter()
+            .find(|rg| rg.resources().any(|res| res.id == id))
+    }
+
+    /// Get the IDs of the resource groups holding the given resources, without repeats.
+    fn groups_of_resources(&self, ids: &[String]) -> Vec<String> {
+        let mut groups: Vec<String> = Vec::new();
+        for id in ids {
+            let group = self.get_group_of_resource(id).unwrap().root.id.clone();
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    /// Get the resource groups which must never run on the same host as the group `id`, due to an
+    /// anti-affinity constraint.
+    pub fn anti_affine_groups(&self, id: &str) -> Vec<&ResourceGroup> {
+        let mut groups: Vec<&ResourceGroup> = Vec::new();
+        for anti_affinity in self.anti_affinities.iter() {
+            if !anti_affinity.iter().any(|group| group == id) {
+                continue;
+            }
+            for other in anti_affinity.iter().filter(|group| *group != id) {
+                let other = self.get_resource_group(other).unwrap();
+                if !groups.iter().any(|g| g.root.id == other.root.id) {
+                    groups.push(other);
+                }
+            }
+        }
+        groups
+    }
+
+    /// Find a group which is anti-affine to `group` and is running on the host that `group` would
+    /// run on at `loc`, if there is one.
+    pub fn anti_affine_conflict(
+        &self,
+        group: &ResourceGroup,
+        loc: Location,
+    ) -> Option<&ResourceGroup> {
+        if matches!(loc, Location::Away) && group.root.failover_node.is_none() {
+            return None;
+        }
+        let host = group.root.host_at(loc);
+        self.anti_affine_groups(&group.root.id)
+            .into_iter()
+            .find(|other| match other.current_location() {
+                Some(other_loc) => other.root.host_at(other_loc).address() == host.address(),
+                None => false,
+            })
+    }
+
+    /// Get the resource groups which must run on the same host as the group `id`, due to a
+    /// colocation constraint, not counting the group itself.
+    pub fn colocated_groups(&self, id: &str) -> Vec<&ResourceGroup> {
+        let mut groups: Vec<&ResourceGroup> = Vec::new();
+        for colocation in self.colocations.iter() {
+            if !colocation.iter().any(|group| group == id) {
+                continue;
+            }
+            for other in colocation.iter().filter(|group| *group != id) {
+                let other = self.get_resource_group(other).unwrap();
+                if !groups.iter().any(|g| g.root.id == other.root.id) {
+                    groups.push(other);
+                }
+            }
+        }
+        groups
+    }
+
+    /// Lock the actions on `group` and on the groups colocated with it, returning those groups,
+    /// starting with `group`, and the guards that hold the locks.
+    ///
+    /// The groups are locked in the order they are in the cluster, so that two callers sharing some
+    /// groups can't each hold a lock that the other is waiting for.
+    async fn lock_colocated<'a>(
+        &'a self,
+        group: &'a ResourceGroup,
+    ) -> (Vec<&'a ResourceGroup>, Vec<tokio::sync::MutexGuard<'a, ()>>) {
+        let mut groups = vec![group];
+        groups.extend(self.colocated_groups(&group.root.id));
+
+        let mut lock_order: Vec<&ResourceGroup> = groups.clone();
+        lock_order.sort_by_key(|g| {
+            self.resource_groups
+                .iter()
+                .position(|rg| std::ptr::eq(rg, *g))
+        });
+        let mut guards = Vec::new();
+        for group in lock_order {
+            guards.push(group.lock_actions().await);
+        }
+        (groups, guards)
+    }
+
+    /// Check that `groups` may all be moved to `to`: none of them may have a host in maintenance,
+    /// and none may end up on the same host as a group it is anti-affine to, other than one of
+    /// `groups` themselves.
+    fn check_move(&self, groups: &[&ResourceGroup], to: Location) -> Result<(), Box<dyn Error>> {
+        for group in groups.iter() {
+            if let Some(host) = group.host_in_maintenance() {
+                return Err(format!(
+                    "can't move resource group \"{}\", since its host {host} is in maintenance",
+                    group.root.id
+                )
+                .into());
+            }
+            if let Some(other) = self.anti_affine_conflict(group, to) {
+                if !groups.iter().any(|g| g.root.id == other.root.id) {
+                    return Err(format!(
+                        "can't move resource group \"{}\" to {}, since anti-affine resource group \"{}\" is running there",
+                        group.root.id,
+                        group.root.host_at(to),
+                        other.root.id
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the resource group `id` to the location `to`, together with the groups colocated with
+    /// it.
+    ///
+    /// The actions on all of these groups are locked before any of them is moved. None of them is
+    /// moved if that would put one of them on the same host as a group it is anti-affine to. Each
+    /// group is moved in turn, starting with `id`; if one fails to move, the rest are still tried,
+    /// and the first error is returned.
+    pub async fn relocate_group(&self, id: &str, to: Location) -> Result<(), Box<dyn Error>> {
+        let Some(group) = self.get_resource_group(id) else {
+            return Err(format!("no such resource group \"{id}\"").into());
+        };
+        let (groups, _guards) = self.lock_colocated(group).await;
+        self.check_move(&groups, to)?;
+
+        let mut first_error = None;
+        for group in groups {
+            if let Err(e) = group.relocate(to).await {
+                if group.root.id != id {
+                    error!(
+                        "Could not move resource group \"{}\", colocated with \"{id}\": {e}",
+                        group.root.id
+                    );
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail the resource group `id` over to the location `to`, because the host at the other
+    /// location can't be reached, together with the groups colocated with it, which share that
+    /// host.
+    ///
+    /// As with relocate_group(), the groups are locked first, and none of them is moved if the
+    /// constraints on them don't allow it. Otherwise, the host is fenced, and then each group is
+    /// started at `to`. A group which is already running at `to`, having been failed over along
+    /// with a group it is colocated with, is left alone, and so is a disabled group.
+    pub async fn fail_over_group(&self, id: &str, to: Location) -> Result<(), Box<dyn Error>> {
+        let Some(group) = self.get_resource_group(id) else {
+            return Err(format!("no such resource group \"{id}\"").into());
+        };
+        let (groups, _guards) = self.lock_colocated(group).await;
+        let groups: Vec<&ResourceGroup> = groups
+            .into_iter()
+            .filter(|g| g.root.enabled && g.current_location() != Some(to))
+            .collect();
+        let Some(first) = groups.first() else {
+            return Ok(());
+        };
+        self.check_move(&groups, to)?;
+
+        // Colocated groups share their hosts, so the host only needs fencing once:
+        first.fence_for_failover(to).await?;
+
+        let mut first_error = None;
+        for group in groups {
+            if let Err(e) = group.take_over(to).await {
+                if group.root.id != id {
+                    error!(
+                        "Could not fail over resource group \"{}\", colocated with \"{id}\": {e}",
+                        group.root.id
+                    );
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     pub fn zpool_resources(&self) -> impl Iterator<Item = &Resource> {
         self.resources().filter(|res| res.kind == "heartbeat/ZFS")
     }
@@ -65,13 +323,13 @@ impl Cluster {
     }
 
     pub fn lustre_resources_no_mgs(&self) -> impl Iterator<Item = &Resource> {
-        self.lustre_resources()
-            .filter(|res| res.parameters.get("kind").unwrap() != "mgs")
+        self.lustre_resources().filter(|res| !is_mgs(res))
     }
 
+    /// Get the Lustre target which the MGS runs on: either a standalone MGS, or an MDT combined
+    /// with the MGS.
     pub fn get_mgs(&self) -> Option<&Resource> {
-        self.lustre_resources()
-            .find(|res| res.parameters.get("kind").unwrap() == "mgs")
+        self.lustre_resources().find(|res| is_mgs(res))
     }
 
     pub fn hosts(&self) -> impl Iterator<Item = &Arc<Host>> {
@@ -85,57 +343,129 @@ impl Cluster {
     /// Create a Cluster given a path to a config file.
     pub fn from_config(config: String) -> Result<Self, crate::commands::EmptyError> {
         let mut args = crate::commands::Cli::default();
-        args.config = Some(config);
+        args.config = vec![config];
         let context = Arc::new(MgrContext::new(args));
         Self::new(context)
     }
 
     /// Create a Cluster given a context. The context contains the arguments, which holds the
-    /// (optional) path to the config file.
+    /// (optional) path to the config file, or "-" to read the config from stdin. If several
+    /// config files were given, the first is used.
     pub fn new(context: Arc<MgrContext>) -> Result<Self, crate::commands::EmptyError> {
-        let path = match &context.args.config {
-            Some(path) => path,
-            None => &crate::default_config_path(),
-        };
-        let config = std::fs::read_to_string(path).inspect_err(|e| {
-            eprintln!("Could not open config file \"{path}\": {e}");
-        })?;
+        let path = context.args.config_path();
+        Self::from_path(context, &path)
+    }
+
+    /// Create a Cluster from the config file at `path`, rather than the one given in the context.
+    pub fn from_path(
+        context: Arc<MgrContext>,
+        path: &str,
+    ) -> Result<Self, crate::commands::EmptyError> {
+        let config = crate::config::Config::from_file(path)?;
+        Self::with_config_at(context, path, config)
+    }
+
+    /// Create a Cluster from a config which has already been read, for when it can't be read
+    /// again, such as from stdin. The path to the config in the context is only used in messages.
+    pub fn with_config(
+        context: Arc<MgrContext>,
+        config: crate::config::Config,
+    ) -> Result<Self, crate::commands::EmptyError> {
+        let path = context.args.config_path();
+        Self::with_config_at(context, &path, config)
+    }
 
-        let config: crate::config::Config = toml::from_str(&config).inspect_err(|e| {
-            eprintln!("Could not parse config file \"{path}\": {e}");
+    /// Create a Cluster from a config which was read from `path`.
+    fn with_config_at(
+        context: Arc<MgrContext>,
+        path: &str,
+        config: crate::config::Config,
+    ) -> Result<Self, crate::commands::EmptyError> {
+        config.validate_parameters().inspect_err(|e| {
+            error!("Invalid config file \"{path}\": {e}");
+        })?;
+        config.validate_failover_pairs().inspect_err(|e| {
+            error!("Invalid config file \"{path}\": {e}");
+        })?;
+        config.validate_colocations().inspect_err(|e| {
+            error!("Invalid config file \"{path}\": {e}");
+        })?;
+        config.validate_anti_affinities().inspect_err(|e| {
+            error!("Invalid config file \"{path}\": {e}");
         })?;
 
         let mut new = Cluster {
+            config_path: path.to_string(),
+            name: cluster_name(path),
             resource_groups: Vec::new(),
             hosts: HashMap::new(),
+            colocations: Vec::new(),
+            anti_affinities: Vec::new(),
             num_zpools: 0,
             num_targets: 0,
+            num_resources: 0,
+            manage_resources: context.args.manage_resources(config.mode),
             context: Arc::clone(&context),
         };
 
+        let defaults = config.defaults.clone().unwrap_or_default();
+
         let hosts: HashMap<String, Arc<Host>> = config
             .hosts
             .iter()
-            .map(|host| (host.hostname.clone(), Arc::new(Host::from_config(host))))
-            .collect();
+            .map(|host| {
+                Ok((
+                    host.hostname.clone(),
+                    Arc::new({
+                        let mut new_host = Host::from_config(host, &defaults)?;
+                        new_host.set_dry_run(context.args.dry_run);
+                        new_host
+                    }),
+                ))
+            })
+            .collect::<Result<_, HostAddressError>>()
+            .inspect_err(|e| {
+                error!("Invalid config file \"{path}\": {e}");
+            })?;
 
         for config_host in config.hosts.iter() {
-            let failover_host: Option<Arc<Host>> = match &config.failover_pairs {
-                Some(pairs) => {
-                    let hostname = get_failover_partner(pairs, &config_host.hostname).unwrap();
-                    // TODO: rather than unwrap() here, return an error to let the user know the
-                    // config was invalid:
-                    Some(Arc::clone(hosts.get(hostname).unwrap()))
-                }
-                None => None,
-            };
+            let failover_host: Option<Arc<Host>> = config
+                .failover_pairs
+                .as_ref()
+                .and_then(|pairs| get_failover_partner(pairs, &config_host.hostname))
+                .map(|partner| match hosts.get(partner) {
+                    Some(partner) => Ok(Arc::clone(partner)),
+                    None => Err(ConfigError::UnknownFailoverPartner {
+                        host: config_host.hostname.clone(),
+                        partner: partner.to_string(),
+                    }),
+                })
+                .transpose()
+                .inspect_err(|e| {
+                    error!("Invalid config file \"{path}\": {e}");
+                })?;
             let host = Arc::clone(hosts.get(&config_host.hostname).unwrap());
+            let settings = ResourceSettings {
+                start_retry: config.start_retry.unwrap_or_default(),
+                monitor_interval: config_host
+                    .monitor_interval_secs
+                    .or(defaults.monitor_interval_secs)
+                    .map(Duration::from_secs),
+                op_timeout: config_host
+                    .op_timeout_secs
+                    .or(defaults.op_timeout_secs)
+                    .map(Duration::from_secs),
+            };
             let mut rg = Self::one_host_resource_groups(
                 config_host,
                 host,
                 failover_host,
                 Arc::clone(&context),
-            );
+                settings,
+            )
+            .inspect_err(|e| {
+                error!("Invalid config file \"{path}\": {e}");
+            })?;
             new.resource_groups.append(&mut rg);
         }
 
@@ -146,6 +476,25 @@ impl Cluster {
 
         new.hosts = hosts;
 
+        // The manager moves whole resource groups, so colocation and anti-affinity constraints
+        // apply to the groups holding the resources they name:
+        for colocation in config.colocation.iter().flatten() {
+            new.colocations
+                .push(new.groups_of_resources(&colocation.resources));
+        }
+        for anti_affinity in config.anti_affinity.iter().flatten() {
+            new.anti_affinities
+                .push(new.groups_of_resources(&anti_affinity.resources));
+        }
+
+        new.num_zpools = new.zpool_resources().count() as u32;
+        new.num_targets = new.lustre_resources().count() as u32;
+        new.num_resources = new
+            .resource_groups
+            .iter()
+            .map(|rg| rg.num_resources())
+            .sum();
+
         Ok(new)
     }
 
@@ -156,7 +505,8 @@ impl Cluster {
         host: Arc<Host>,
         failover_host: Option<Arc<Host>>,
         context: Arc<MgrContext>,
-    ) -> Vec<ResourceGroup> {
+        settings: ResourceSettings,
+    ) -> Result<Vec<ResourceGroup>, ConfigError> {
         use std::cell::RefCell;
         use std::rc::Rc;
 
@@ -181,6 +531,7 @@ impl Cluster {
                 host: Arc<Host>,
                 failover_host: Option<Arc<Host>>,
                 context: Arc<MgrContext>,
+                settings: ResourceSettings,
             ) -> Resource {
                 let dependents = RefCell::into_inner(self.children)
                     .into_iter()
@@ -189,13 +540,31 @@ impl Cluster {
                             Arc::clone(&host),
                             failover_host.clone(),
                             Arc::clone(&context),
+                            settings,
                         )
                     })
                     .collect();
-                Resource::from_config(self.me, dependents, host, failover_host, context, self.id)
+                Resource::from_config(
+                    self.me,
+                    dependents,
+                    host,
+                    failover_host,
+                    context,
+                    self.id,
+                    settings,
+                )
             }
         }
 
+        // A dependency cycle would leave outstanding references to the TransitionalResources in
+        // the cycle, so it must be ruled out before building the trees:
+        if let Some(cycle) = config_host.find_dependency_cycle() {
+            return Err(ConfigError::DependencyCycle {
+                host: config_host.hostname.clone(),
+                cycle,
+            });
+        }
+
         let resources: HashMap<String, TransitionalResource> = config_host
             .resources
             .iter()
@@ -226,9 +595,16 @@ impl Cluster {
                     // either processed_nodes, or resources.
                     let parent = match processed_nodes.get(parent) {
                         Some(parent) => parent,
-                        // TODO: rather than unwrap here, return an error so that the program can
-                        // report to the user that the config was invalid.
-                        None => resources.get(parent).unwrap(),
+                        None => match resources.get(parent) {
+                            Some(parent) => parent,
+                            None => {
+                                return Err(ConfigError::MissingDependency {
+                                    host: config_host.hostname.clone(),
+                                    resource: id.clone(),
+                                    requires: parent.clone(),
+                                })
+                            }
+                        },
                     };
                     parent.children.borrow_mut().push(this_resource);
                 }
@@ -244,17 +620,47 @@ impl Cluster {
         std::mem::drop(processed_nodes);
         std::mem::drop(resources);
 
-        roots
+        Ok(roots
             .into_iter()
             .map(|root| {
                 let root = Rc::into_inner(root).unwrap().into_resource(
                     Arc::clone(&host),
                     failover_host.clone(),
                     Arc::clone(&context),
+                    settings,
                 );
                 ResourceGroup::new(root)
             })
-            .collect()
+            .collect())
+    }
+
+    /// After the config has been reloaded into this cluster, carry over what is known about the
+    /// state of the hosts and resources of the `old` cluster to the same ones in this cluster.
+    ///
+    /// A host is the same if it has the same address. A resource is the same if it has the same
+    /// ID, kind, parameters and home host; a resource whose definition changed starts afresh.
+    pub fn adopt_state(&self, old: &Cluster) -> ConfigChanges {
+        for host in self.hosts() {
+            if let Some(old_host) = old.hosts().find(|h| h.address() == host.address()) {
+                host.adopt_state(old_host);
+            }
+        }
+
+        let mut changes = ConfigChanges::default();
+        for res in self.resources() {
+            match old.get_resource(&res.id) {
+                Some(old_res) if res.same_definition(old_res) => res.adopt_state(old_res),
+                Some(_) => {}
+                None => changes.added_resources.push(res.id.clone()),
+            }
+        }
+        changes.removed_resources = old
+            .resources()
+            .filter(|res| self.get_resource(&res.id).is_none())
+            .map(|res| res.id.clone())
+            .collect();
+
+        changes
     }
 
     /// Print out a summary of the cluster to stdout. Mainly intended for debugging purposes.
@@ -276,11 +682,64 @@ impl Cluster {
         println!("=== Hosts ===");
         for host in self.hosts.values() {
             println!("{}", host);
-            println!("\tfence agent: {:?}", host.fence_agent());
+            println!("\tfence agents: {:?}", host.fence_agents());
+        }
+    }
+
+    /// Build a structured representation of the cluster, of the form:
+    ///
+    ///     { "resource_groups": [ { "id": "ost1_zpool", "kind": "heartbeat/ZFS", ...,
+    ///                              "dependents": [ { "id": "ost1", ... } ] } ],
+    ///       "hosts": [ { "id": "oss00", "address": "oss00:8000", "fence_agents": [ ... ] } ] }
+    ///
+    /// The fence agents are given in their Debug form, so any passwords are redacted.
+    pub fn to_json(&self) -> serde_json::Value {
+        fn resource_json(res: &Resource) -> serde_json::Value {
+            serde_json::json!({
+                "id": res.id,
+                "kind": res.kind,
+                "parameters": res.parameters,
+                "status": format!("{:?}", res.get_status()),
+                "home_node": res.home_node.id(),
+                "failover_node": res.failover_node.as_ref().map(|h| h.id()),
+                "priority": res.priority,
+                "dependents": res.dependents.iter().map(resource_json).collect::<Vec<_>>(),
+            })
         }
+
+        let mut hosts: Vec<&Arc<Host>> = self.hosts().collect();
+        hosts.sort_by_key(|h| h.id());
+
+        serde_json::json!({
+            "resource_groups": self
+                .resource_groups()
+                .map(|rg| resource_json(&rg.root))
+                .collect::<Vec<_>>(),
+            "hosts": hosts
+                .iter()
+                .map(|host| {
+                    serde_json::json!({
+                        "id": host.id(),
+                        "address": host.address(),
+                        "fence_agents": host
+                            .fence_agents()
+                            .iter()
+                            .map(|agent| format!("{agent:?}"))
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
     }
 }
 
+/// The resources added to and removed from the cluster when its config was reloaded, by ID.
+#[derive(Debug, Default)]
+pub struct ConfigChanges {
+    pub added_resources: Vec<String>,
+    pub removed_resources: Vec<String>,
+}
+
 /// Given a list `pairs` of failover pairs, and a hostname `name`, return its partner, if one
 /// exists.
 fn get_failover_partner<'pairs>(
@@ -297,3 +756,105 @@ fn get_failover_partner<'pairs>(
     }
     None
 }
+
+/// The name of the cluster described by the config file at `path`: the file's name, without its
+/// extension, so that "/etc/halo/fs1.toml" describes the cluster "fs1".
+fn cluster_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// Whether a Lustre target runs the MGS, whether by itself ("mgs") or combined with an MDT ("mgt").
+fn is_mgs(res: &Resource) -> bool {
+    matches!(
+        res.parameters.get("kind").map(String::as_str),
+        Some("mgs" | "mgt")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_counts() {
+        let config = format!(
+            "{}/tests/multiagent.toml",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap()
+        );
+        let cluster = Cluster::from_config(config).unwrap();
+
+        assert_eq!(
+            cluster.num_resources() as usize,
+            cluster.resources().count()
+        );
+        assert_eq!(cluster.num_resources(), 4);
+        assert_eq!(cluster.num_zpools(), 2);
+        assert_eq!(cluster.num_targets(), 2);
+        for rg in cluster.resource_groups.iter() {
+            assert_eq!(rg.num_resources() as usize, rg.resources().count());
+        }
+    }
+
+    #[test]
+    fn to_json() {
+        let config = format!(
+            "{}/tests/dump.toml",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap()
+        );
+        let cluster = Cluster::from_config(config).unwrap();
+        let json = cluster.to_json();
+
+        let zpool = &json["resource_groups"][0];
+        assert_eq!(zpool["id"], "test_zpool");
+        assert_eq!(zpool["parameters"]["pool"], "test_zpool");
+        assert_eq!(zpool["dependents"][0]["parameters"]["target"], "test_mgt");
+        assert_eq!(zpool["dependents"][0]["kind"], "lustre/Lustre");
+
+        let text = json.to_string();
+        assert!(text.contains("dump_user"));
+        assert!(!text.contains("dump_secret"));
+    }
+
+    #[test]
+    fn default_op_timeout() {
+        let port = crate::remote::spawn_test_agent(Default::default());
+        let config = crate::config::Config::parse(
+            &format!(
+                r#"
+                [defaults]
+                op_timeout_secs = 1
+
+                [[hosts]]
+                hostname = "127.0.0.1:{port}"
+
+                  [hosts.resources.pool]
+                  kind = "heartbeat/ZFS"
+                  parameters = {{ pool = "halo_default_op_timeout" }}
+
+                  [hosts.resources.slow]
+                  kind = "test/SlowMonitor"
+                  parameters = {{}}
+                  requires = "pool"
+                "#
+            ),
+            "default_op_timeout",
+        )
+        .unwrap();
+        let cluster = Cluster::with_config(Arc::new(MgrContext::default()), config).unwrap();
+
+        // The timeout goes from the config, through the manager, to the remote agent, which kills
+        // the monitor once it has run that long:
+        let slow = cluster.get_resource("slow").unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let begun = std::time::Instant::now();
+        assert_eq!(
+            rt.block_on(slow.monitor(Location::Home)).unwrap(),
+            crate::remote::ocf::Status::ErrTimeout
+        );
+        assert!(begun.elapsed() < Duration::from_secs(10));
+    }
+}
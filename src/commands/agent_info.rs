@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {clap::Args, log::error};
+
+use crate::{
+    commands::{self, Cli},
+    halo_capnp::do_info_request,
+    host::Host,
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct AgentInfoArgs {
+    /// The host whose remote agent to ask, given as "<address>[:<port number>]".
+    #[arg(long)]
+    host: String,
+}
+
+/// Ask the remote agent on a host what version of halo it is, how long it has been running, and
+/// where its resource agent scripts are, and print the answer.
+pub async fn agent_info(cli: &Cli, args: &AgentInfoArgs) -> commands::Result {
+    let host = Host::from_address(&args.host)
+        .inspect_err(|e| error!("Invalid host \"{}\": {e}", args.host))?;
+
+    let info = do_info_request(&host, cli)
+        .await
+        .inspect_err(|e| error!("Could not get info from agent on \"{}\": {e}", args.host))?;
+
+    println!("version: {}", info.version);
+    println!("uptime: {}s", info.uptime.as_secs());
+    println!("OCF root: {}", info.ocf_root.unwrap_or_default());
+
+    Ok(())
+}
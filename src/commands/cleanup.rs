@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {clap::Args, log::error};
+
+use crate::commands::{self, Cli};
+
+#[derive(Args, Debug, Clone)]
+pub struct CleanupArgs {
+    /// The ID of the resource to clean up, as given in the config file.
+    #[arg(long)]
+    resource: String,
+}
+
+/// Ask the manager to forget the known state of a resource--for example, after it has become
+/// stuck in an Unknown or Unrunnable state--and to monitor it again right away.
+pub async fn cleanup(cli: &Cli, args: &CleanupArgs) -> commands::Result {
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let addr = &commands::socket_path(cli);
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
+
+            let mut request = client.cleanup_request();
+            request.get().set_resource(&args.resource);
+
+            request.send().promise.await.inspect_err(|e| {
+                error!("Could not clean up resource \"{}\": {e}", args.resource)
+            })?;
+
+            Ok(())
+        })
+        .await
+}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {clap::Args, log::error};
+
+use crate::{
+    commands::{self, Cli},
+    halo_capnp, host,
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct DescribeArgs {
+    /// The resource agent to describe, given as PROVIDER/TYPE, e.g. "heartbeat/ZFS".
+    #[arg(long)]
+    kind: String,
+
+    /// The host whose remote agent runs the resource agent, given as "<address>[:<port number>]".
+    #[arg(long)]
+    host: String,
+}
+
+/// Ask the remote agent on a host for the OCF meta-data of a resource agent, and print it. The
+/// meta-data is XML describing the parameters that resources of that kind accept.
+pub async fn describe(cli: &Cli, args: &DescribeArgs) -> commands::Result {
+    let address = host::Host::from_address(&args.host)
+        .inspect_err(|e| error!("Invalid host \"{}\": {e}", args.host))?
+        .address();
+
+    let meta_data = tokio::task::LocalSet::new()
+        .run_until(halo_capnp::do_meta_data_request(&address, &args.kind, cli))
+        .await
+        .inspect_err(|e| {
+            error!(
+                "Could not get meta-data for \"{}\" from host \"{}\": {e}",
+                args.kind, args.host
+            )
+        })?;
+
+    print!("{meta_data}");
+
+    Ok(())
+}
@@ -3,58 +3,200 @@
 
 use std::{collections::HashMap, io, process::Command};
 
-use clap::Args;
+use {
+    clap::{Args, ValueEnum},
+    log::{debug, error, warn},
+};
 
-use crate::config;
+use crate::{commands::Cli, config, halo_capnp, host};
 
 #[derive(Args, Debug, Clone)]
 pub struct DiscoverArgs {
-    #[arg(short, long)]
-    verbose: bool,
+    /// How to reach the hosts to discover their resources.
+    #[arg(long, value_enum, default_value_t = DiscoverVia::Agent)]
+    via: DiscoverVia,
+
+    /// A pair of hosts which can fail over to each other, given as "HOST_A,HOST_B". This may be
+    /// given multiple times. Both hosts must be among the discovered hosts.
+    #[arg(long = "pair", value_name = "HOST_A,HOST_B")]
+    pairs: Vec<String>,
 
     #[arg()]
     hostnames: Vec<String>,
 }
 
-pub fn discover(args: &DiscoverArgs) -> crate::commands::Result {
-    let mut config = config::Config {
-        hosts: Vec::new(),
-        failover_pairs: None,
-    };
-    for hostname in args.hostnames.iter() {
-        let host = discover_one_host(hostname, args.verbose).unwrap();
-        config.hosts.push(host);
+/// The ways that `discover` can find out what is running on a host.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum DiscoverVia {
+    /// Ask the halo remote agent running on the host.
+    Agent,
+
+    /// Run commands on the host over ssh. This requires ssh keys for the host.
+    Ssh,
+}
+
+/// Discover the resources on each of the given hosts, and print a config file describing them.
+///
+/// The hosts are discovered concurrently, but appear in the config in the order they were given.
+/// If discovery fails for a host, it is reported and left out of the config, and the command exits
+/// with an error once the config for the remaining hosts has been printed.
+pub fn discover(cli: &Cli, args: &DiscoverArgs) -> crate::commands::Result {
+    let results: Vec<io::Result<config::Host>> = std::thread::scope(|s| {
+        let handles: Vec<_> = args
+            .hostnames
+            .iter()
+            .map(|hostname| s.spawn(move || discover_one_host(hostname, cli, args)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| match handle.join() {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::other("discovery thread panicked")),
+            })
+            .collect()
+    });
+
+    let mut hosts = Vec::new();
+    let mut error_seen = false;
+    for (hostname, result) in args.hostnames.iter().zip(results) {
+        match result {
+            Ok(host) => hosts.push(host),
+            Err(e) => {
+                error!("Could not discover host \"{hostname}\": {e}");
+                error_seen = true;
+            }
+        }
+    }
+
+    let config =
+        build_config(hosts, args).inspect_err(|e| error!("Invalid failover pairs: {e}"))?;
+
+    println!("{}", toml::to_string_pretty(&config)?);
+
+    if error_seen {
+        crate::commands::err()
+    } else {
+        Ok(())
     }
-    println!("{}", toml::to_string_pretty(&config).unwrap());
-    Ok(())
+}
+
+/// Assemble the discovered hosts into a Config, together with the failover pairs given on the
+/// command line.
+fn build_config(
+    hosts: Vec<config::Host>,
+    args: &DiscoverArgs,
+) -> Result<config::Config, config::ConfigError> {
+    let failover_pairs = if args.pairs.is_empty() {
+        None
+    } else {
+        Some(
+            args.pairs
+                .iter()
+                .map(|pair| pair.split(',').map(|h| h.trim().to_string()).collect())
+                .collect(),
+        )
+    };
+
+    let config = config::Config {
+        hosts,
+        failover_pairs,
+        mode: None,
+        start_retry: None,
+        defaults: None,
+        colocation: None,
+        anti_affinity: None,
+    };
+    config.validate_failover_pairs()?;
+
+    Ok(config)
 }
 
 /// Attempt to discover all of the resources (zpools and lustre targerts) running on `hostname`,
 /// and construct them into a config::Host object that owns those resources.
-fn discover_one_host(hostname: &str, verbose: bool) -> io::Result<config::Host> {
-    let zpool_output = get_zpool_output(hostname, verbose)?;
+fn discover_one_host(hostname: &str, cli: &Cli, args: &DiscoverArgs) -> io::Result<config::Host> {
+    let (zpool_output, lustre_output) = match args.via {
+        DiscoverVia::Agent => get_agent_output(hostname, cli)?,
+        DiscoverVia::Ssh => (get_zpool_output(hostname)?, get_lustre_output(hostname)?),
+    };
 
-    let mut resources = parse_zpool_output(zpool_output);
+    Ok(host_from_output(hostname, zpool_output, lustre_output))
+}
 
-    let lustre_output = get_lustre_output(hostname, verbose)?;
+/// Construct a config::Host for `hostname` from the list of its zpools and its `mount -t lustre`
+/// output.
+///
+/// Each lustre target requires the zpool that holds it. A target whose zpool was not discovered
+/// is left out with a warning, since a config naming a missing zpool would not load.
+fn host_from_output(hostname: &str, zpool_output: String, lustre_output: String) -> config::Host {
+    let mut resources = parse_zpool_output(zpool_output);
 
-    let lustre_resources = parse_lustre_output(lustre_output);
+    let mut lustre_resources = parse_lustre_output(hostname, lustre_output);
+    lustre_resources.retain(|id, res| {
+        let zpool = res.requires.as_deref().unwrap_or_default();
+        let found = resources.contains_key(zpool);
+        if !found {
+            warn!(
+                "leaving lustre target \"{id}\" on host \"{hostname}\" out of the config, since its zpool \"{zpool}\" was not discovered"
+            );
+        }
+        found
+    });
 
     resources.extend(lustre_resources);
 
-    Ok(config::Host {
+    config::Host {
         hostname: hostname.to_string(),
         resources,
         fence_agent: None,
         fence_parameters: None,
-    })
+        fence_agents: None,
+        monitor_interval_secs: None,
+        op_timeout_secs: None,
+        server_name: None,
+        fence_retry: None,
+    }
+}
+
+/// Ask the remote agent on `hostname` for its zpools and lustre targets, returning them in the
+/// same form as the output of the ssh commands.
+fn get_agent_output(hostname: &str, cli: &Cli) -> io::Result<(String, String)> {
+    let address = host::Host::from_address(hostname)
+        .map_err(io::Error::other)?
+        .address();
+    debug!("Discovering resources for host={hostname} via remote agent at {address}");
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let discovered = rt
+        .block_on(
+            tokio::task::LocalSet::new().run_until(halo_capnp::do_discover_request(&address, cli)),
+        )
+        .map_err(io::Error::other)?;
+
+    debug!("zpools: {:?}", discovered.zpools);
+    debug!("lustre mounts: {:?}", discovered.lustre_mounts);
+
+    Ok((
+        discovered.zpools.join("\n"),
+        discovered.lustre_mounts.join("\n"),
+    ))
 }
 
-fn parse_lustre_output(output: String) -> HashMap<String, config::Resource> {
+/// Parse the `mount -t lustre` output from `hostname` into its Lustre resources. A line which
+/// can't be parsed is left out with a warning, rather than failing the whole discovery.
+fn parse_lustre_output(hostname: &str, output: String) -> HashMap<String, config::Resource> {
     let mut resources = HashMap::new();
 
     for line in output.lines() {
-        let res = config::Resource::new_lustre(line);
+        let res = match config::Resource::new_lustre(line) {
+            Ok(res) => res,
+            Err(e) => {
+                warn!("leaving a lustre target on host \"{hostname}\" out of the config: {e}");
+                continue;
+            }
+        };
 
         let target = res.parameters.get("target").unwrap();
 
@@ -64,24 +206,32 @@ fn parse_lustre_output(output: String) -> HashMap<String, config::Resource> {
     resources
 }
 
-fn get_lustre_output(hostname: &str, verbose: bool) -> io::Result<String> {
+fn get_lustre_output(hostname: &str) -> io::Result<String> {
     // Get Targets and parse both Zpools and Lustre targets
-    if verbose {
-        eprintln!("Discovering lustre targets for host={hostname}");
-        eprintln!("Running command on hosggt: 'mount -t lustre'");
-    }
+    debug!("Discovering lustre targets for host={hostname}");
+    debug!("Running command on host: 'mount -t lustre'");
     let output = Command::new("ssh")
         .args([hostname, "mount", "-t", "lustre"])
         .output()?;
-    if verbose {
-        eprintln!(
-            "stdout: {}",
-            String::from_utf8(output.stdout.clone()).unwrap()
-        );
-        eprintln!("stderr: {}", String::from_utf8(output.stderr).unwrap());
+    ssh_output(output)
+}
+
+/// Check that a command run over ssh succeeded, and return its stdout.
+fn ssh_output(output: std::process::Output) -> io::Result<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    debug!("stdout: {stdout}");
+    debug!("stderr: {stderr}");
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "ssh command failed ({}): {}",
+            output.status,
+            stderr.trim()
+        )));
     }
 
-    Ok(String::from_utf8(output.stdout).unwrap())
+    Ok(stdout)
 }
 
 fn parse_zpool_output(output: String) -> HashMap<String, config::Resource> {
@@ -93,33 +243,78 @@ fn parse_zpool_output(output: String) -> HashMap<String, config::Resource> {
     }))
 }
 
-fn get_zpool_output(hostname: &str, verbose: bool) -> io::Result<String> {
+fn get_zpool_output(hostname: &str) -> io::Result<String> {
     // Get Zpools
-    if verbose {
-        eprintln!("\nDiscovering zpools for host={hostname}");
-        eprintln!("Running command on host: 'zpool list -H -o name'");
-    }
+    debug!("Discovering zpools for host={hostname}");
+    debug!("Running command on host: 'zpool list -H -o name'");
     let output = Command::new("ssh")
         .args([hostname, "zpool", "list", "-H", "-o", "name"])
         .output()?;
-    if verbose {
-        eprintln!(
-            "stdout: {}",
-            String::from_utf8(output.stdout.clone()).unwrap()
-        );
-        eprintln!("stderr: {}", String::from_utf8(output.stderr).unwrap());
-    }
-
-    Ok(String::from_utf8(output.stdout).unwrap())
+    ssh_output(output)
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use super::{parse_lustre_output, parse_zpool_output};
+    use clap::Parser;
+
+    use super::{build_config, host_from_output, parse_lustre_output, parse_zpool_output};
+    use crate::cluster::Cluster;
+    use crate::commands::{Cli, Commands};
     use crate::config::*;
 
+    /// Parse a discover command line into its arguments.
+    fn discover_args(args: &[&str]) -> super::DiscoverArgs {
+        let cli = Cli::parse_from([&["halo", "discover"], args].concat());
+        match cli.command {
+            Some(Commands::Discover(args)) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    fn empty_host(hostname: &str) -> Host {
+        Host {
+            hostname: hostname.to_string(),
+            resources: HashMap::new(),
+            fence_agent: None,
+            fence_parameters: None,
+            fence_agents: None,
+            monitor_interval_secs: None,
+            op_timeout_secs: None,
+            server_name: None,
+            fence_retry: None,
+        }
+    }
+
+    #[test]
+    fn failover_pairs_in_config() {
+        let args = discover_args(&["--pair", "mds00,mds01", "--pair", "oss00,oss01"]);
+        let hosts = ["mds00", "mds01", "oss00", "oss01"]
+            .iter()
+            .map(|h| empty_host(h))
+            .collect();
+
+        let config = build_config(hosts, &args).unwrap();
+        let config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+
+        assert_eq!(
+            config.failover_pairs,
+            Some(vec![
+                vec!["mds00".to_string(), "mds01".to_string()],
+                vec!["oss00".to_string(), "oss01".to_string()],
+            ])
+        );
+    }
+
+    #[test]
+    fn failover_pair_not_discovered() {
+        let args = discover_args(&["--pair", "mds00,mds02"]);
+        let hosts = vec![empty_host("mds00"), empty_host("mds01")];
+
+        assert!(build_config(hosts, &args).is_err());
+    }
+
     #[test]
     fn parse_zpools() {
         let output = String::from("zpool_1\nzpool_2");
@@ -145,7 +340,7 @@ mod tests {
         let output = concat!("oss01e0/ost2 on /mnt/ost2 type lustre (ro,svname=test-OST0002,mgsnode=10.0.0.1@tcp:10.0.0.2@tcp,osd=osd-zfs)\n",
                              "oss01e1/ost3 on /mnt/ost3 type lustre (ro,svname=test-OST0003,mgsnode=10.0.0.1@tcp:10.0.0.2@tcp,osd=osd-zfs)");
 
-        let resources = parse_lustre_output(output.to_string());
+        let resources = parse_lustre_output("oss01", output.to_string());
         assert_eq!(resources.len(), 2);
 
         let goal_1 = Resource {
@@ -156,6 +351,9 @@ mod tests {
                 ("kind".to_string(), "ost".to_string()),
             ]),
             requires: Some("oss01e0".to_string()),
+            priority: None,
+            enabled: None,
+            timeouts: None,
         };
         let goal_2 = Resource {
             kind: "lustre/Lustre".to_string(),
@@ -165,6 +363,9 @@ mod tests {
                 ("kind".to_string(), "ost".to_string()),
             ]),
             requires: Some("oss01e1".to_string()),
+            priority: None,
+            enabled: None,
+            timeouts: None,
         };
         let goal = HashMap::from([
             ("oss01e0/ost2".to_string(), goal_1),
@@ -173,4 +374,93 @@ mod tests {
 
         assert_eq!(resources, goal);
     }
+
+    #[test]
+    fn parse_lustre_combined_mgs() {
+        let output = concat!(
+            "mds00e0/mdt0 on /mnt/mdt0 type lustre (ro,svname=test-MDT0000,mgs,osd=osd-zfs)\n",
+            "mds00e1/mgs on /mnt/mgs type lustre (ro,svname=MGS,nosvc,mgs,osd=osd-zfs)\n",
+            "mds00e2/mdt1 on /mnt/mdt1 type lustre (ro,svname=test-MDT0001,mgsnode=10.0.0.1@tcp,osd=osd-zfs)",
+        );
+
+        let resources = parse_lustre_output("mds00", output.to_string());
+        let kind = |id: &str| resources[id].parameters["kind"].as_str();
+        assert_eq!(kind("mds00e0/mdt0"), "mgt");
+        assert_eq!(kind("mds00e1/mgs"), "mgs");
+        assert_eq!(kind("mds00e2/mdt1"), "mdt");
+    }
+
+    #[test]
+    fn parse_lustre_malformed() {
+        let output = concat!(
+            "oss01e0/ost2 on /mnt/ost2 type lustre (ro,svname=test-OST0002,osd=osd-zfs)\n",
+            "this is not a mount line\n",
+            "oss01e1/ost3 on /mnt/ost3 type lustre (ro,svname=test-OST0003,osd=osd-zfs)",
+        );
+
+        // The malformed line is skipped, and the targets around it are still discovered:
+        let resources = parse_lustre_output("oss01", output.to_string());
+        assert_eq!(resources.len(), 2);
+        assert!(resources.contains_key("oss01e0/ost2"));
+        assert!(resources.contains_key("oss01e1/ost3"));
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let zpools = "mds00e0\nmds00e1\n".to_string();
+        let mounts = concat!(
+            "mds00e0/mgt on /mnt/mgt type lustre (ro,svname=MGS,nosvc,mgs,osd=osd-zfs)\n",
+            "mds00e1/mdt0 on /mnt/mdt0 type lustre (ro,svname=test-MDT0000,mgsnode=10.0.0.1@tcp,osd=osd-zfs)\n",
+            "mds00e2/mdt1 on /mnt/mdt1 type lustre (ro,svname=test-MDT0001,mgsnode=10.0.0.1@tcp,osd=osd-zfs)\n",
+        )
+        .to_string();
+
+        let host = host_from_output("mds00", zpools, mounts);
+        // The target on the undiscovered zpool mds00e2 is left out:
+        assert_eq!(host.resources.len(), 4);
+        assert!(!host.resources.contains_key("mds00e2/mdt1"));
+
+        let args = discover_args(&[]);
+        let config = build_config(vec![host], &args).unwrap();
+        let path = std::env::temp_dir().join(format!("halo_discover_{}.toml", std::process::id()));
+        std::fs::write(&path, toml::to_string_pretty(&config).unwrap()).unwrap();
+
+        let cluster = Cluster::from_config(path.to_str().unwrap().to_string()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (zpool, target) in [("mds00e0", "mds00e0/mgt"), ("mds00e1", "mds00e1/mdt0")] {
+            let group = cluster.get_resource_group(zpool).unwrap();
+            let ids: Vec<&str> = group.resources().map(|r| r.id.as_str()).collect();
+            assert_eq!(ids, vec![zpool, target]);
+        }
+        assert_eq!(cluster.resources().count(), 4);
+    }
+
+    #[test]
+    fn piped_to_validate() {
+        let zpools = "oss00e0\n".to_string();
+        let mounts =
+            "oss00e0/ost0 on /mnt/ost0 type lustre (ro,svname=test-OST0000,mgsnode=10.0.0.1@tcp,osd=osd-zfs)\n"
+                .to_string();
+        let hosts = vec![
+            host_from_output("oss00", zpools, mounts),
+            empty_host("oss01"),
+        ];
+        let args = discover_args(&["--pair", "oss00,oss01"]);
+        let output = toml::to_string_pretty(&build_config(hosts, &args).unwrap()).unwrap();
+
+        // What `halo validate --config -` does with the output piped into it:
+        let config = Config::parse(&output, STDIN_PATH).unwrap();
+        let cli = Cli {
+            config: vec![STDIN_PATH.to_string()],
+            ..Default::default()
+        };
+        let context = std::sync::Arc::new(crate::manager::MgrContext::new(cli));
+        let cluster = Cluster::with_config(context, config).unwrap();
+
+        let group = cluster.get_resource_group("oss00e0").unwrap();
+        let ids: Vec<&str> = group.resources().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["oss00e0", "oss00e0/ost0"]);
+        assert_eq!(cluster.hosts().count(), 2);
+    }
 }
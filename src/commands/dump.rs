@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use std::sync::Arc;
+
+use {clap::Args, log::error};
+
+use crate::cluster::Cluster;
+use crate::commands::{self, Cli};
+use crate::manager::MgrContext;
+
+#[derive(Args, Debug, Clone)]
+pub struct DumpArgs {
+    /// Print the cluster as JSON, rather than as a summary.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Print the cluster described by the config file, as the manager would see it on startup.
+pub fn dump(cli: &Cli, args: &DumpArgs) -> commands::Result {
+    let cluster = Cluster::new(Arc::new(MgrContext::new(cli.clone())))?;
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&cluster.to_json()).inspect_err(|e| {
+            error!("Could not format the cluster as JSON: {e}");
+        })?;
+        println!("{json}");
+    } else {
+        cluster.print_summary();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use std::{collections::HashMap, io::Write};
+
+use {clap::Args, log::error};
+
+use crate::commands::{self, Cli};
+use crate::config::{self, STDIN_PATH};
+
+#[derive(Args, Debug, Clone)]
+pub struct InitArgs {
+    /// Where to write the config file, or "-" to print it. Defaults to $HALO_CONFIG, or
+    /// /etc/halo/halo.conf.
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Overwrite the file if it already exists.
+    #[arg(long)]
+    force: bool,
+}
+
+/// Write a skeleton config file, with comments explaining each part of it, for a new cluster to
+/// start from.
+pub fn init(_cli: &Cli, args: &InitArgs) -> commands::Result {
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(crate::default_config_path);
+    let skeleton = skeleton().inspect_err(|e| error!("Could not format the config: {e}"))?;
+
+    if output == STDIN_PATH {
+        print!("{skeleton}");
+        return Ok(());
+    }
+
+    // The file is created exclusively, so that an existing config is never overwritten by
+    // accident:
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(args.force)
+        .create_new(!args.force)
+        .open(&output)
+        .inspect_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                error!("\"{output}\" already exists; pass --force to overwrite it.")
+            }
+            _ => error!("Could not create \"{output}\": {e}"),
+        })?;
+    file.write_all(skeleton.as_bytes())
+        .inspect_err(|e| error!("Could not write \"{output}\": {e}"))?;
+
+    eprintln!("Wrote a skeleton config to \"{output}\".");
+    Ok(())
+}
+
+/// The comments to put before the lines of the skeleton config that start with each of these
+/// keys or table headers.
+const COMMENTS: &[(&str, &str)] = &[
+    (
+        "failover_pairs",
+        "# Pairs of hosts which can take over each other's resources when one of them fails.",
+    ),
+    (
+        "mode",
+        "# \"observe\" only monitors the resources; \"manage\" also starts them, and fails them\n\
+         # over when their host fails.",
+    ),
+    (
+        "[defaults]",
+        "# Settings shared by every host, unless the host gives its own. The fence agent is used\n\
+         # to power off a host which has failed, before its resources are started elsewhere.",
+    ),
+    (
+        "[defaults.fence_parameters]",
+        "# The fence agent's parameters. Replace these with the credentials of the hosts' BMCs.",
+    ),
+    (
+        "[[hosts]]",
+        "# Each host runs the halo remote agent, and lists the resources that it is home to.",
+    ),
+    (
+        "[hosts.resources.mds00e0]",
+        "# A zpool. Each resource is named by an ID, which must be unique in the cluster.",
+    ),
+    (
+        "[hosts.resources.\"mds00e0/mdt0\"]",
+        "# A Lustre target on the zpool mds00e0, which must be started before it.",
+    ),
+];
+
+/// Build the skeleton config: a failover pair of hosts, the first with a zpool and a Lustre target
+/// on it, fenced with IPMI.
+fn skeleton_config() -> config::Config {
+    let zpool = config::Resource::new_zpool("mds00e0".to_string());
+    let target = config::Resource {
+        kind: "lustre/Lustre".to_string(),
+        parameters: HashMap::from([
+            ("mountpoint".to_string(), "/mnt/mdt0".to_string()),
+            ("target".to_string(), "mds00e0/mdt0".to_string()),
+            ("kind".to_string(), "mdt".to_string()),
+        ]),
+        requires: Some("mds00e0".to_string()),
+        priority: None,
+        enabled: None,
+        timeouts: None,
+    };
+    let host = |hostname: &str, resources| config::Host {
+        hostname: hostname.to_string(),
+        resources,
+        fence_agent: None,
+        fence_parameters: None,
+        fence_retry: None,
+        fence_agents: None,
+        monitor_interval_secs: None,
+        op_timeout_secs: None,
+        server_name: None,
+    };
+
+    config::Config {
+        hosts: vec![
+            host(
+                "mds00",
+                HashMap::from([
+                    ("mds00e0".to_string(), zpool),
+                    ("mds00e0/mdt0".to_string(), target),
+                ]),
+            ),
+            host("mds01", HashMap::new()),
+        ],
+        failover_pairs: Some(vec![vec!["mds00".to_string(), "mds01".to_string()]]),
+        mode: Some(config::Mode::Observe),
+        start_retry: None,
+        defaults: Some(config::Defaults {
+            fence_agent: Some("fence_ipmilan".to_string()),
+            fence_parameters: Some(HashMap::from([
+                ("username".to_string(), "admin".to_string()),
+                ("password".to_string(), "changeme".to_string()),
+            ])),
+            ..Default::default()
+        }),
+        colocation: None,
+        anti_affinity: None,
+    }
+}
+
+/// Serialize the skeleton config, commenting its parts. Each comment is given once, before the
+/// first line that it applies to.
+fn skeleton() -> Result<String, toml::ser::Error> {
+    let toml = toml::to_string_pretty(&skeleton_config())?;
+
+    let mut commented: Vec<&str> = Vec::new();
+    let mut out = String::from(
+        "# A skeleton halo config file. Replace the hosts and resources with those of your cluster,\n\
+         # then check it with `halo validate --config <this file>`.\n\n",
+    );
+    for line in toml.lines() {
+        let comment = COMMENTS.iter().find(|(start, _)| {
+            line.starts_with(start) && !commented.contains(start) && {
+                // A key must be followed by " =", so that "mode" does not match "modes":
+                let rest = &line[start.len()..];
+                start.starts_with('[') || rest.starts_with(" =")
+            }
+        });
+        if let Some((start, comment)) = comment {
+            out.push_str(comment);
+            out.push('\n');
+            commented.push(start);
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{cluster::Cluster, manager::MgrContext};
+
+    #[test]
+    fn skeleton_builds_cluster() {
+        let path = std::env::temp_dir().join(format!("halo_init_{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let args = InitArgs {
+            output: Some(path.to_str().unwrap().to_string()),
+            force: false,
+        };
+        init(&Cli::default(), &args).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        for (_, comment) in COMMENTS {
+            assert!(contents.contains(comment), "{comment}");
+        }
+
+        let cli = Cli {
+            config: args.output.clone().into_iter().collect(),
+            ..Default::default()
+        };
+        let cluster = Cluster::new(Arc::new(MgrContext::new(cli))).unwrap();
+        let group = cluster.get_resource_group("mds00e0").unwrap();
+        let ids: Vec<&str> = group.resources().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["mds00e0", "mds00e0/mdt0"]);
+        assert!(group.root.failover_node.is_some());
+
+        // An existing file is only overwritten with --force:
+        std::fs::write(&path, "").unwrap();
+        assert!(init(&Cli::default(), &args).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        let args = InitArgs {
+            force: true,
+            ..args
+        };
+        init(&Cli::default(), &args).unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# A skeleton halo config file."));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use std::time::Duration;
+
+use {clap::Args, log::error};
+
+use crate::commands::{self, Cli};
+
+/// How often to ask the manager for new lines with --follow.
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Args, Debug, Clone)]
+pub struct LogArgs {
+    /// How many of the most recent lines to print.
+    #[arg(short = 'n', long, default_value_t = 20)]
+    lines: u32,
+
+    /// Keep printing new lines as the manager logs them, until interrupted.
+    #[arg(short, long)]
+    follow: bool,
+}
+
+/// Print the manager's recent activity, such as changes in the resources' statuses, as kept by the
+/// manager in its history; see --log-history.
+pub async fn log(cli: &Cli, args: &LogArgs) -> commands::Result {
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let addr = &commands::socket_path(cli);
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
+
+            let mut lines = args.lines;
+            let mut after = 0;
+            loop {
+                let mut request = client.tail_log_request();
+                request.get().set_lines(lines);
+                request.get().set_after(after);
+                let reply = request
+                    .send()
+                    .promise
+                    .await
+                    .inspect_err(|e| error!("Could not get the manager's log: {e}"))?;
+
+                let reply = reply.get()?;
+                for line in reply.get_lines()?.iter() {
+                    println!("{}", line?.to_str()?);
+                }
+                after = reply.get_next();
+
+                if !args.follow {
+                    return Ok(());
+                }
+                // Having caught up, every line logged from now on is new:
+                lines = u32::MAX;
+                tokio::time::sleep(FOLLOW_INTERVAL).await;
+            }
+        })
+        .await
+}
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {clap::Args, log::error};
+
+use crate::commands::{self, Cli};
+
+#[derive(Args, Debug, Clone)]
+pub struct MaintenanceArgs {
+    /// The host to put into, or take out of, maintenance mode.
+    #[arg(long)]
+    host: String,
+
+    /// Put the host into maintenance mode.
+    #[arg(long, required_unless_present = "off", conflicts_with = "off")]
+    on: bool,
+
+    /// Take the host out of maintenance mode.
+    #[arg(long)]
+    off: bool,
+}
+
+/// Ask the manager to put a host into, or take it out of, maintenance mode. While a host is in
+/// maintenance, the manager keeps reporting the status of its resources, but never starts, stops,
+/// or fences anything on it.
+pub async fn maintenance(cli: &Cli, args: &MaintenanceArgs) -> commands::Result {
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let addr = &commands::socket_path(cli);
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
+
+            let mut request = client.maintenance_request();
+            request.get().set_host(&args.host);
+            request.get().set_on(args.on);
+
+            request.send().promise.await.inspect_err(|e| {
+                error!(
+                    "Could not set maintenance mode of host \"{}\": {e}",
+                    args.host
+                )
+            })?;
+
+            Ok(())
+        })
+        .await
+}
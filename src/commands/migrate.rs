@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {
+    clap::{Args, ValueEnum},
+    log::error,
+};
+
+use crate::{
+    commands::{self, Cli},
+    halo_capnp::halo_mgmt,
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct MoveArgs {
+    /// The ID of the root resource of the resource group to move.
+    #[arg(long)]
+    group: String,
+
+    /// Where to move the resource group. By default, it moves to whichever of its hosts it is not
+    /// currently running on.
+    #[arg(long)]
+    to: Option<MoveTarget>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum MoveTarget {
+    Home,
+    Away,
+}
+
+/// Ask the manager to move a resource group from the host it is running on to its other host.
+pub async fn migrate(cli: &Cli, args: &MoveArgs) -> commands::Result {
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let addr = &commands::socket_path(cli);
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
+
+            let mut request = client.relocate_request();
+            request.get().set_group(&args.group);
+            request.get().set_to(match args.to {
+                None => halo_mgmt::Destination::Other,
+                Some(MoveTarget::Home) => halo_mgmt::Destination::Home,
+                Some(MoveTarget::Away) => halo_mgmt::Destination::Away,
+            });
+
+            request
+                .send()
+                .promise
+                .await
+                .inspect_err(|e| error!("Could not move resource group \"{}\": {e}", args.group))?;
+
+            Ok(())
+        })
+        .await
+}
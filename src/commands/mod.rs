@@ -1,20 +1,40 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
+pub mod agent_info;
+pub mod cleanup;
+pub mod describe;
 pub mod discover;
+pub mod dump;
+pub mod init;
+pub mod log;
+pub mod maintenance;
+pub mod migrate;
 pub mod power;
+pub mod resource;
 pub mod start;
 pub mod status;
 pub mod stop;
 pub mod validate;
 
+pub use self::log::LogArgs;
+pub use agent_info::AgentInfoArgs;
+pub use cleanup::CleanupArgs;
+pub use describe::DescribeArgs;
 pub use discover::DiscoverArgs;
+pub use dump::DumpArgs;
+pub use init::InitArgs;
+pub use maintenance::MaintenanceArgs;
+pub use migrate::MoveArgs;
 pub use power::PowerArgs;
+pub use resource::ResourceArgs;
 pub use status::StatusArgs;
 
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
 use clap::{Parser, Subcommand};
+use futures::AsyncReadExt;
 
-use crate::Cluster;
+use crate::{halo_capnp::halo_mgmt, Cluster};
 use validate::ValidateArgs;
 
 #[derive(Debug)]
@@ -45,40 +65,231 @@ pub fn err() -> Result {
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
+    /// The config file, or "-" to read it from stdin. The manager may be given this several times,
+    /// to manage a cluster for each config file; the other commands use the first one given.
     #[arg(long, global = true)]
-    pub config: Option<String>,
+    pub config: Vec<String>,
 
     #[arg(long, global = true)]
     pub socket: Option<String>,
 
-    #[arg(short, long, global = true)]
-    pub verbose: bool,
+    /// The group whose members may use the manager's socket. If not given, only the owner may
+    /// use it, unless the HALO_SOCKET_GROUP environment variable names a group.
+    #[arg(long)]
+    pub socket_group: Option<String>,
+
+    /// Log more: once (-v) for each change in a resource's status, twice (-vv) for the requests
+    /// made to the remote agents and their results as well.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print the actions that start, stop, and power would take--which resources they would start
+    /// or stop, in what order, and which hosts they would fence--without taking them.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
 
     #[arg(long)]
     pub mtls: bool,
 
+    /// Connect to the remote agents over TLS, verifying their certificates against the CA, but
+    /// without presenting a client certificate.
+    #[arg(long, conflicts_with = "mtls")]
+    pub tls: bool,
+
+    /// The manager's mTLS certificate. Defaults to $HALO_CLIENT_CERT, or /etc/halo/client.crt.
+    #[arg(long)]
+    pub client_cert: Option<String>,
+
+    /// The manager's mTLS private key. Defaults to $HALO_CLIENT_KEY, or /etc/halo/client.key.
+    #[arg(long)]
+    pub client_key: Option<String>,
+
+    /// The certificate of the CA that signs the agents' certificates. Defaults to $HALO_CA_CERT,
+    /// or /etc/halo/ca.crt.
+    #[arg(long)]
+    pub ca_cert: Option<String>,
+
     /// Whether to run in Observe mode (Default, only check on resource status, don't actively
-    /// start/stop resources), or Manage mode (actively manage resource state)
+    /// start/stop resources), or Manage mode (actively manage resource state). This overrides the
+    /// mode given in the config file.
     #[arg(long)]
     pub manage_resources: bool,
 
+    /// Run in Observe mode, even if the config file says to manage the resources.
+    #[arg(long, conflicts_with = "manage_resources")]
+    pub observe_resources: bool,
+
+    /// When the config is reloaded with SIGHUP, stop the resources which were removed from it,
+    /// rather than only no longer managing them.
+    #[arg(long)]
+    pub stop_removed: bool,
+
+    /// When failing a resource group over, reboot the host it failed on, so that it can rejoin the
+    /// cluster, rather than powering it off. The group stays on the failover host until it is
+    /// moved back.
+    #[arg(long)]
+    pub reboot_on_failover: bool,
+
+    /// How many seconds one of a resource group's hosts must have been unreachable before the
+    /// group is failed over, so that a host which only drops out briefly is not fenced. Set this to
+    /// 0, the default, to fail over straight away.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_secs)]
+    pub failover_debounce: f64,
+
+    /// How much to randomly vary the manager's polling intervals by, as a fraction of each
+    /// interval, so that the resources are not all monitored at the same moment. Set this to 0 to
+    /// poll at fixed intervals.
+    #[arg(long, default_value_t = DEFAULT_POLL_JITTER)]
+    pub poll_jitter: f64,
+
+    /// How many seconds to wait for a connection to a remote agent to be established, including
+    /// the TLS handshake, before treating the agent as unreachable.
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = DEFAULT_CONNECT_TIMEOUT,
+        value_parser = parse_secs
+    )]
+    pub connect_timeout: f64,
+
+    /// How many lines of its recent activity, such as changes in the resources' statuses, the
+    /// manager keeps for `halo log`. Set this to 0 to keep none.
+    #[arg(long, value_name = "LINES", default_value_t = DEFAULT_LOG_HISTORY)]
+    pub log_history: usize,
+
+    /// Where to write a JSON line for each change in a resource's status: "stdout", "stderr",
+    /// "syslog", or else the path of a file to append to.
+    #[arg(long, value_name = "DEST")]
+    pub event_log: Option<String>,
+
+    /// Serve metrics about the cluster, in the Prometheus text format, over HTTP at this address,
+    /// given as "<ip>:<port number>". Off by default.
+    #[arg(long, value_name = "ADDRESS")]
+    pub metrics_addr: Option<std::net::SocketAddr>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+/// The default for how much the manager's polling intervals vary by: up to 20% either way.
+pub const DEFAULT_POLL_JITTER: f64 = 0.2;
+
+/// The default for how long to wait for a connection to a remote agent, in seconds.
+pub const DEFAULT_CONNECT_TIMEOUT: f64 = 3.0;
+
+/// The default for how many lines of its recent activity the manager keeps.
+pub const DEFAULT_LOG_HISTORY: usize = 1000;
+
+/// Parse a number of seconds given on the command line, which must be finite and not negative so
+/// that it can be turned into a Duration.
+fn parse_secs(arg: &str) -> std::result::Result<f64, String> {
+    let secs: f64 = arg.parse().map_err(|e| format!("{e}"))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!(
+            "'{arg}' is not a finite, non-negative number of seconds"
+        ));
+    }
+    Ok(secs)
+}
+
 impl Default for Cli {
     fn default() -> Self {
         Cli {
-            config: Some(crate::default_config_path()),
+            config: vec![crate::default_config_path()],
             socket: Some(crate::default_socket()),
-            verbose: false,
+            socket_group: crate::default_socket_group(),
+            verbose: 0,
+            dry_run: false,
             mtls: false,
+            tls: false,
+            client_cert: None,
+            client_key: None,
+            ca_cert: None,
             manage_resources: false,
+            observe_resources: false,
+            stop_removed: false,
+            reboot_on_failover: false,
+            failover_debounce: 0.0,
+            poll_jitter: DEFAULT_POLL_JITTER,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            log_history: DEFAULT_LOG_HISTORY,
+            event_log: None,
+            metrics_addr: None,
             command: None,
         }
     }
 }
 
+impl Cli {
+    /// The config file of a single cluster: the first one given, or else the default.
+    pub fn config_path(&self) -> String {
+        self.config
+            .first()
+            .cloned()
+            .unwrap_or_else(crate::default_config_path)
+    }
+
+    /// The config files of the clusters for the manager to manage: those given, or else the
+    /// default.
+    pub fn config_paths(&self) -> Vec<String> {
+        match self.config.is_empty() {
+            true => vec![crate::default_config_path()],
+            false => self.config.clone(),
+        }
+    }
+
+    /// How connections to the remote agents are secured.
+    pub fn tls_mode(&self) -> crate::tls::TlsMode {
+        crate::tls::TlsMode::from_flags(self.tls, self.mtls)
+    }
+
+    /// Whether to manage the resources, rather than only observe them, given the mode in the
+    /// config file: the command line takes precedence over the config, which takes precedence
+    /// over the default of Observe mode.
+    pub fn manage_resources(&self, config_mode: Option<crate::config::Mode>) -> bool {
+        if self.manage_resources || self.observe_resources {
+            return self.manage_resources;
+        }
+        config_mode == Some(crate::config::Mode::Manage)
+    }
+
+    /// Whether to log each change in a resource's status.
+    pub fn log_transitions(&self) -> bool {
+        self.verbose >= 1
+    }
+
+    /// Whether to log each request made to a remote agent, and its result.
+    pub fn log_rpc(&self) -> bool {
+        self.verbose >= 2
+    }
+
+    /// How long a host must have been unreachable before failing over from it.
+    pub fn failover_debounce(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.failover_debounce)
+    }
+
+    /// How long to wait for a connection to a remote agent to be established.
+    pub fn connect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.connect_timeout)
+    }
+
+    /// Get the paths of the files used for TLS, falling back to the defaults for any that were
+    /// not given on the command line.
+    pub fn tls_paths(&self) -> crate::tls::TlsPaths {
+        crate::tls::TlsPaths {
+            cert: self
+                .client_cert
+                .clone()
+                .unwrap_or_else(crate::default_client_cert),
+            key: self
+                .client_key
+                .clone()
+                .unwrap_or_else(crate::default_client_key),
+            ca_cert: self.ca_cert.clone().unwrap_or_else(crate::default_ca_cert),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     Status(StatusArgs),
@@ -87,11 +298,21 @@ pub enum Commands {
     Discover(DiscoverArgs),
     Power(PowerArgs),
     Validate(ValidateArgs),
+    Cleanup(CleanupArgs),
+    #[command(visible_alias = "migrate")]
+    Move(MoveArgs),
+    Maintenance(MaintenanceArgs),
+    Resource(ResourceArgs),
+    Describe(DescribeArgs),
+    AgentInfo(AgentInfoArgs),
+    Dump(DumpArgs),
+    Log(LogArgs),
+    Init(InitArgs),
 }
 
 pub fn main(cli: &Cli, command: &Commands) -> Result {
     if let Commands::Discover(args) = command {
-        return discover::discover(args);
+        return discover::discover(cli, args);
     };
 
     if let Commands::Power(args) = command {
@@ -99,7 +320,15 @@ pub fn main(cli: &Cli, command: &Commands) -> Result {
     }
 
     if let Commands::Validate(args) = command {
-        return validate::validate(args);
+        return validate::validate(cli, args);
+    }
+
+    if let Commands::Dump(args) = command {
+        return dump::dump(cli, args);
+    }
+
+    if let Commands::Init(args) = command {
+        return init::init(cli, args);
     }
 
     let rt = tokio::runtime::Runtime::new()?;
@@ -107,6 +336,13 @@ pub fn main(cli: &Cli, command: &Commands) -> Result {
         let context_arc = std::sync::Arc::new(crate::manager::MgrContext::new(cli.clone()));
         match command {
             Commands::Status(args) => status::status(cli, args).await,
+            Commands::Cleanup(args) => cleanup::cleanup(cli, args).await,
+            Commands::Move(args) => migrate::migrate(cli, args).await,
+            Commands::Maintenance(args) => maintenance::maintenance(cli, args).await,
+            Commands::Resource(args) => resource::resource(cli, args).await,
+            Commands::Describe(args) => describe::describe(cli, args).await,
+            Commands::AgentInfo(args) => agent_info::agent_info(cli, args).await,
+            Commands::Log(args) => log::log(cli, args).await,
             Commands::Start => {
                 let cluster = Cluster::new(context_arc)?;
                 start::start(cluster).await
@@ -119,3 +355,46 @@ pub fn main(cli: &Cli, command: &Commands) -> Result {
         }
     })
 }
+
+/// Get the path to the unix socket that the manager listens on.
+pub(crate) fn socket_path(cli: &Cli) -> String {
+    match &cli.socket {
+        Some(s) => s.clone(),
+        None => crate::default_socket(),
+    }
+}
+
+/// Connect to the manager listening on the unix socket at `addr`.
+///
+/// This must be called from within a LocalSet, since the RPC system is spawned as a local task.
+pub(crate) async fn connect_to_manager(addr: &str) -> std::io::Result<halo_mgmt::Client> {
+    let stream = tokio::net::UnixStream::connect(addr).await?;
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let rpc_network = Box::new(twoparty::VatNetwork::new(
+        futures::io::BufReader::new(reader),
+        futures::io::BufWriter::new(writer),
+        rpc_twoparty_capnp::Side::Client,
+        crate::halo_capnp::RPC_READER_OPTIONS,
+    ));
+    let mut rpc_system = RpcSystem::new(rpc_network, None);
+    let client: halo_mgmt::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+
+    tokio::task::spawn_local(rpc_system);
+
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_flags_are_validated() {
+        let cli = Cli::parse_from(["halo", "--connect-timeout", "0.5"]);
+        assert_eq!(cli.connect_timeout(), std::time::Duration::from_millis(500));
+        for bad in ["-1", "inf", "NaN", "soon"] {
+            assert!(Cli::try_parse_from(["halo", "--connect-timeout", bad]).is_err());
+            assert!(Cli::try_parse_from(["halo", "--failover-debounce", bad]).is_err());
+        }
+    }
+}
@@ -3,7 +3,11 @@
 
 use std::sync::Arc;
 
-use clap::Args;
+use {
+    clap::Args,
+    futures::{stream, StreamExt},
+    log::{debug, error},
+};
 
 use crate::{
     commands::{self, Cli},
@@ -20,10 +24,7 @@ pub struct PowerArgs {
     #[arg()]
     hostnames: Vec<String>,
 
-    #[arg(short, long)]
-    verbose: bool,
-
-    /// Fence agent to use, "powerman" or "redfish", case sensitive
+    /// Fence agent to use, "powerman", "redfish", or "ipmilan", case sensitive
     #[arg(short = 'f', long)]
     fence_agent: Option<String>,
 
@@ -32,6 +33,15 @@ pub struct PowerArgs {
 
     #[arg(short = 'p', long)]
     password: Option<String>,
+
+    /// Use the IPMI v2.0 "lanplus" interface (ipmilan only).
+    #[arg(long)]
+    lanplus: bool,
+
+    /// Fence the hosts concurrently, at most N at a time (given as "--parallel=N"), rather than one
+    /// after another. Without N, every host is fenced at once.
+    #[arg(long, value_name = "N", require_equals = true, num_args = 0..=1)]
+    parallel: Option<Option<usize>>,
 }
 
 pub fn power(main_args: &Cli, args: &PowerArgs) -> commands::Result {
@@ -40,7 +50,7 @@ pub fn power(main_args: &Cli, args: &PowerArgs) -> commands::Result {
     }
 
     if let Some(fence_agent) = args.fence_agent.as_ref() {
-        return do_fence_given_agent(fence_agent, args);
+        return do_fence_given_agent(fence_agent, main_args, args);
     }
 
     // If the user has not specified a fence agent, then assume that the fence parameters for the
@@ -49,56 +59,88 @@ pub fn power(main_args: &Cli, args: &PowerArgs) -> commands::Result {
     let context = Arc::new(MgrContext::new(main_args.clone()));
     let cluster = Cluster::new(context)?;
 
-    for hostname in args.hostnames.iter() {
-        let host = cluster.get_host(hostname).unwrap();
-        match host.do_fence(args.action) {
-            Ok(()) => {
-                eprintln!("{} Fence: Success", host.name());
-            }
-            Err(e) => {
-                eprintln!("{} Fence result: Failure: {e}", host.name());
-            }
-        }
-    }
+    let hosts: Vec<Arc<Host>> = args
+        .hostnames
+        .iter()
+        .map(|hostname| Arc::clone(cluster.get_host(hostname).unwrap()))
+        .collect();
 
-    Ok(())
+    fence_hosts(hosts, args)
 }
 
 /// Perform a fence action, with the fence agent specified on the command line. In this case, the
 /// specified fence agent will override any potential fence agent found in a config file (if a
 /// config is passed as an argument.)
-fn do_fence_given_agent(fence_agent: &str, args: &PowerArgs) -> commands::Result {
+fn do_fence_given_agent(fence_agent: &str, main_args: &Cli, args: &PowerArgs) -> commands::Result {
     let fence_agent = match fence_agent {
-        "powerman" => FenceAgent::Powerman,
+        "powerman" => FenceAgent::Powerman(PowermanArgs::new(None, None)),
         "redfish" => {
             let user = args.username.clone().unwrap();
             let pass = args.password.clone().unwrap();
             FenceAgent::Redfish(RedfishArgs::new(user, pass))
         }
+        "ipmilan" => {
+            let user = args.username.clone().unwrap();
+            let pass = args.password.clone().unwrap();
+            FenceAgent::Ipmi(IpmiArgs::new(user, pass, args.lanplus, None))
+        }
         other => panic!("unsupported fence agent {other}"),
     };
 
-    let hosts: Vec<Host> = args
+    let hosts: Vec<Arc<Host>> = args
         .hostnames
         .iter()
-        .map(|host| Host::new(host, None, Some(fence_agent.clone())))
+        .map(|host| {
+            let mut host = Host::new(host, None, vec![fence_agent.clone()]);
+            host.set_dry_run(main_args.dry_run);
+            Arc::new(host)
+        })
         .collect();
 
-    let mut error_seen = false;
+    fence_hosts(hosts, args)
+}
 
-    for host in hosts {
-        if args.verbose {
-            eprintln!("Fencing Host: {}", host.name());
+/// Perform the fence action on each of `hosts`, one after another, or concurrently with
+/// --parallel. A line giving the result is printed for each host as it finishes, naming the host,
+/// since with --parallel the hosts may finish in any order.
+fn fence_hosts(hosts: Vec<Arc<Host>>, args: &PowerArgs) -> commands::Result {
+    // Print the result of fencing `host`, returning whether it succeeded:
+    let report = |host: &Host, result: Result<(), Box<dyn std::error::Error>>| match result {
+        Ok(()) => {
+            eprintln!("{host} Fence: Success");
+            true
         }
-        match host.do_fence(args.action) {
-            Ok(()) => {
-                eprintln!("{} Fence: Success", host.name());
-            }
-            Err(e) => {
-                eprintln!("{} Fence result: Failure: {e}", host.name());
-                error_seen = true;
+        Err(e) => {
+            eprintln!("{host} Fence result: Failure: {e}");
+            false
+        }
+    };
+
+    let mut error_seen = false;
+
+    match args.parallel {
+        None => {
+            for host in hosts {
+                debug!("Fencing Host: {host}");
+                error_seen |= !report(&host, host.do_fence(args.action));
             }
         }
+        Some(limit) => {
+            let limit = limit.unwrap_or(hosts.len()).max(1);
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(async {
+                let mut results = stream::iter(hosts)
+                    .map(|host| async move {
+                        debug!("Fencing Host: {host}");
+                        let result = host.do_fence_async(args.action).await;
+                        (host, result)
+                    })
+                    .buffer_unordered(limit);
+                while let Some((host, result)) = results.next().await {
+                    error_seen |= !report(&host, result);
+                }
+            });
+        }
     }
 
     if error_seen {
@@ -114,7 +156,7 @@ fn status_all_hosts_in_config(main_args: &Cli, args: &PowerArgs) -> commands::Re
     match &args.action {
         FenceCommand::Status => {}
         other => {
-            eprintln!("Must specify host names to perform action \"{other}\".");
+            error!("Must specify host names to perform action \"{other}\".");
             return commands::err();
         }
     };
@@ -132,3 +174,33 @@ fn status_all_hosts_in_config(main_args: &Cli, args: &PowerArgs) -> commands::Re
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::commands::{Cli, Commands};
+
+    /// Parse a power command line into its arguments.
+    fn power_args(args: &[&str]) -> super::PowerArgs {
+        let cli = Cli::parse_from([&["halo", "power", "off"], args].concat());
+        match cli.command {
+            Some(Commands::Power(args)) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parallel_flag() {
+        let args = power_args(&["--parallel=2", "host1"]);
+        assert_eq!(args.parallel, Some(Some(2)));
+        assert_eq!(args.hostnames, vec!["host1"]);
+
+        // Without "=N", the hostname that follows is not taken as the limit:
+        let args = power_args(&["--parallel", "host1"]);
+        assert_eq!(args.parallel, Some(None));
+        assert_eq!(args.hostnames, vec!["host1"]);
+
+        assert_eq!(power_args(&["host1"]).parallel, None);
+    }
+}
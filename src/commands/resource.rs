@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+use {
+    clap::{Args, Subcommand},
+    log::error,
+};
+
+use crate::commands::{self, Cli};
+
+#[derive(Args, Debug, Clone)]
+pub struct ResourceArgs {
+    #[command(subcommand)]
+    action: ResourceAction,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ResourceAction {
+    /// Start a resource on its home host.
+    Start {
+        /// The ID of the resource, as given in the config file.
+        id: String,
+    },
+
+    /// Stop a resource wherever it is running. In Manage mode, the manager may start it again.
+    Stop {
+        /// The ID of the resource, as given in the config file.
+        id: String,
+    },
+}
+
+/// Ask the manager to start or stop a single resource, once it is not already acting on the
+/// resource's group.
+pub async fn resource(cli: &Cli, args: &ResourceArgs) -> commands::Result {
+    tokio::task::LocalSet::new()
+        .run_until(async move {
+            let addr = &commands::socket_path(cli);
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
+
+            let (id, result) = match &args.action {
+                ResourceAction::Start { id } => {
+                    let mut request = client.start_resource_request();
+                    request.get().set_id(id);
+                    (id, request.send().promise.await.map(|_| ()))
+                }
+                ResourceAction::Stop { id } => {
+                    let mut request = client.stop_resource_request();
+                    request.get().set_id(id);
+                    (id, request.send().promise.await.map(|_| ()))
+                }
+            };
+
+            result.inspect_err(|e| error!("Could not act on resource \"{id}\": {e}"))?;
+
+            Ok(())
+        })
+        .await
+}
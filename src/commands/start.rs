@@ -1,24 +1,19 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use futures::future;
+use {futures::future, log::error};
 
 use crate::{cluster, commands, resource};
 
+/// Start every resource in the cluster on its home host.
+///
+/// The zpools are started first, then the MGS (or the MDT that it is combined with, if it isn't
+/// standalone), and finally the remaining Lustre targets. Within each of these stages, resources
+/// with a higher priority are started before those with a lower one, and resources of the same
+/// priority are started concurrently.
 pub async fn start(cluster: cluster::Cluster) -> commands::Result {
     // 1. All zpools.
-    let zpool_statuses: Vec<_> = cluster
-        .zpool_resources()
-        .map(|z| async {
-            (
-                z.parameters.clone(),
-                z.start(resource::Location::Home).await,
-            )
-        })
-        .collect();
-
-    let results = future::join_all(zpool_statuses).await;
-    results.iter().for_each(|r| println!("{:?}", r));
+    start_by_priority(cluster.zpool_resources()).await;
 
     // 2. Lustre MGS target.
     let mgs = cluster.get_mgs();
@@ -27,22 +22,30 @@ pub async fn start(cluster: cluster::Cluster) -> commands::Result {
             let status = mgs.start(resource::Location::Home).await;
             println!("{:?}", ("mgs", status));
         }
-        None => eprintln!("Could not find mgs target."),
+        None => error!("Could not find mgs target."),
     };
 
     // 3. All remaining Lustre targets.
-    let target_statuses: Vec<_> = cluster
-        .lustre_resources_no_mgs()
-        .map(|t| async {
-            (
-                t.parameters.clone(),
-                t.start(resource::Location::Home).await,
-            )
-        })
-        .collect();
-
-    let results = future::join_all(target_statuses).await;
-    results.iter().for_each(|r| println!("{:?}", r));
+    start_by_priority(cluster.lustre_resources_no_mgs()).await;
 
     Ok(())
 }
+
+/// Start `resources` in batches of the same priority, from the highest priority to the lowest,
+/// printing the outcome of each start.
+async fn start_by_priority<'a>(resources: impl IntoIterator<Item = &'a resource::Resource>) {
+    for batch in resource::by_priority(resources) {
+        let statuses: Vec<_> = batch
+            .iter()
+            .map(|r| async {
+                (
+                    r.parameters.clone(),
+                    r.start(resource::Location::Home).await,
+                )
+            })
+            .collect();
+
+        let results = future::join_all(statuses).await;
+        results.iter().for_each(|r| println!("{:?}", r));
+    }
+}
@@ -1,82 +1,761 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use clap::Args;
-use futures::AsyncReadExt;
+use std::collections::HashMap;
+
+use {clap::Args, log::error};
 
 use crate::commands::{self, Cli};
 use crate::halo_capnp::halo_mgmt;
 
-#[derive(Args, Debug, Clone)]
+#[derive(Args, Debug, Clone, Default)]
 pub struct StatusArgs {
+    /// Only show resources that are not running normally on their home node.
     #[arg(short = 'x')]
     exclude_normal: bool,
+
+    /// Print the status as a JSON array of objects, for consumption by other programs.
+    #[arg(long)]
+    json: bool,
+
+    /// Exit with a non-zero status if any resource is not running normally on its home node.
+    ///
+    /// Without this flag, the exit status is zero whenever the status could be retrieved from the
+    /// manager, regardless of the state of the resources.
+    #[arg(long)]
+    fail_on_degraded: bool,
+
+    /// Only show resources whose status changed within the last SECS seconds.
+    #[arg(long, value_name = "SECS")]
+    recently_changed: Option<u64>,
+
+    /// Only show the resources in the resource group whose root resource has this ID.
+    #[arg(long, value_name = "ID")]
+    group: Option<String>,
+
+    /// Show the resources by resource group, each group headed by its overall status, and each
+    /// resource indented beneath the resource that it depends on.
+    #[arg(long, conflicts_with = "json")]
+    tree: bool,
+
+    /// Keep refreshing the status every SECS seconds (2 by default) until interrupted.
+    #[arg(long, value_name = "SECS", num_args = 0..=1, default_missing_value = "2")]
+    watch: Option<u64>,
 }
 
 pub async fn status(cli: &Cli, args: &StatusArgs) -> commands::Result {
     tokio::task::LocalSet::new()
         .run_until(async move {
-            let addr = match &cli.socket {
-                Some(s) => s,
-                None => &crate::default_socket(),
-            };
-            let stream = tokio::net::UnixStream::connect(addr)
-                .await
-                .inspect_err(|e| eprintln!("Could not connect to socket \"{addr}\": {e}"))?;
-            let (reader, writer) =
-                tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-            let rpc_network = Box::new(twoparty::VatNetwork::new(
-                futures::io::BufReader::new(reader),
-                futures::io::BufWriter::new(writer),
-                rpc_twoparty_capnp::Side::Client,
-                Default::default(),
-            ));
-            let mut rpc_system = RpcSystem::new(rpc_network, None);
-            let client: halo_mgmt::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+            let addr = &commands::socket_path(cli);
 
-            tokio::task::spawn_local(rpc_system);
+            if let Some(interval) = args.watch {
+                return watch_status(addr, args, interval).await;
+            }
 
-            let request = client.monitor_request();
+            let client = commands::connect_to_manager(addr)
+                .await
+                .inspect_err(|e| error!("Could not connect to socket \"{addr}\": {e}"))?;
 
-            let reply = request.send().promise.await?;
+            let reply = client.monitor_request().send().promise.await?;
             let cluster_status = reply.get()?.get_status()?;
 
             if let Err(e) = print_status(cluster_status, args) {
-                eprintln!("Could not get status: {e}");
-                commands::err()
-            } else {
-                Ok(())
+                error!("Could not get status: {e}");
+                return commands::err();
             }
+
+            check_health(cluster_status, args)
         })
         .await
 }
 
-fn print_status(response: halo_mgmt::cluster::Reader, _args: &StatusArgs) -> commands::Result {
+/// Repeatedly print the status every `interval` seconds, until interrupted.
+///
+/// The connection to the manager is reused between refreshes. If the manager goes away, this
+/// keeps trying to reconnect to it rather than exiting.
+async fn watch_status(addr: &str, args: &StatusArgs, interval: u64) -> commands::Result {
+    let mut client: Option<halo_mgmt::Client> = None;
+    loop {
+        if client.is_none() {
+            client = commands::connect_to_manager(addr).await.ok();
+        }
+
+        let frame = match &client {
+            Some(c) => match c.monitor_request().send().promise.await {
+                Ok(reply) => reply
+                    .get()
+                    .and_then(|r| r.get_status())
+                    .map_err(commands::EmptyError::from)
+                    .and_then(|status| {
+                        if args.json {
+                            let status = status_json(status, args)?;
+                            Ok(serde_json::to_string_pretty(&status)? + "\n")
+                        } else {
+                            status_text(status, args)
+                        }
+                    }),
+                Err(e) => Err(e.into()),
+            },
+            None => Err(commands::EmptyError {}),
+        };
+
+        // Clear the screen and move the cursor to the top left before drawing the next frame:
+        print!("\x1B[2J\x1B[H");
+        match frame {
+            Ok(frame) => print!("{frame}"),
+            Err(_) => {
+                println!("manager unreachable, retrying");
+                client = None;
+            }
+        };
+
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+    }
+}
+
+fn print_status(response: halo_mgmt::cluster::Reader, args: &StatusArgs) -> commands::Result {
+    if args.json {
+        let status = status_json(response, args)?;
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    print!("{}", status_text(response, args)?);
+
+    Ok(())
+}
+
+/// When the user asks to fail on a degraded cluster, returns an error if any resource is not
+/// running normally on its home node. A resource disabled in the config is not running on purpose,
+/// so it doesn't count.
+fn check_health(response: halo_mgmt::cluster::Reader, args: &StatusArgs) -> commands::Result {
+    if !args.fail_on_degraded {
+        return Ok(());
+    }
+
+    for res in response.get_resources()?.iter() {
+        match res.get_status()? {
+            halo_mgmt::Status::RunningOnHome | halo_mgmt::Status::Disabled => {}
+            _ => return commands::err(),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a resource should be left out of the output. When the user asks to exclude normal
+/// resources, only the resources that are not running on their home node are shown; the other
+/// filters narrow the output down to the resources that changed recently, or to a single group.
+fn is_excluded(
+    res: halo_mgmt::resource::Reader,
+    args: &StatusArgs,
+) -> Result<bool, commands::EmptyError> {
+    if args.exclude_normal && res.get_status()? == halo_mgmt::Status::RunningOnHome {
+        return Ok(true);
+    }
+    if args
+        .recently_changed
+        .is_some_and(|secs| res.get_status_age() > secs)
+    {
+        return Ok(true);
+    }
+    if let Some(group) = &args.group {
+        if res.get_group()?.to_str()? != group {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Format how long a resource has had its status, e.g. "45s", "3m12s" or "2h05m".
+fn format_age(secs: u64) -> String {
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h{mins:02}m")
+    } else if mins > 0 {
+        format!("{mins}m{secs:02}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Whether the status reply holds resources from more than one cluster, so that the text must say
+/// which cluster each belongs to.
+fn has_several_clusters(
+    response: halo_mgmt::cluster::Reader,
+) -> Result<bool, commands::EmptyError> {
+    let mut first = None;
+    for res in response.get_resources()?.iter() {
+        let name = res.get_cluster()?.to_str()?;
+        match first {
+            None => first = Some(name),
+            Some(first) if first != name => return Ok(true),
+            Some(_) => {}
+        }
+    }
+    Ok(false)
+}
+
+/// Format a status as it is shown in text, where running normally is just "OK".
+fn status_word(status: halo_mgmt::Status) -> String {
+    match status {
+        halo_mgmt::Status::RunningOnHome => "OK".to_string(),
+        other => format!("{}", other),
+    }
+}
+
+/// Format the status reply as human-readable text, with a line for each resource of the form:
+///
+///     STATUS for AGE: [key: value, ...]
+///
+/// with "(maintenance)" after the age of resources whose home host is in maintenance mode. When the
+/// manager manages several clusters, each line starts with the name of the resource's cluster, as
+/// in "[fs1] ". With --tree, the resources are shown by group instead--see `tree_text()`.
+fn status_text(
+    response: halo_mgmt::cluster::Reader,
+    args: &StatusArgs,
+) -> Result<String, commands::EmptyError> {
+    if args.tree {
+        return tree_text(response, args);
+    }
+
+    let several_clusters = has_several_clusters(response)?;
+    let mut output = String::new();
     let resources = response.get_resources()?;
     for i in 0..resources.len() {
         let res = resources.get(i);
-        let status = match res.get_status()? {
-            halo_mgmt::Status::RunningOnHome => "OK".to_string(),
-            other => format!("{}", other),
-        };
-        print!("{}: [", status);
+        if is_excluded(res, args)? {
+            continue;
+        }
+        if several_clusters {
+            output.push_str(&format!("[{}] ", res.get_cluster()?.to_str()?));
+        }
+        output.push_str(&resource_line(res)?);
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+/// Format the line of text for a single resource, without a newline.
+fn resource_line(res: halo_mgmt::resource::Reader) -> Result<String, commands::EmptyError> {
+    let mut line = format!(
+        "{} for {}{}: [",
+        status_word(res.get_status()?),
+        format_age(res.get_status_age()),
+        if res.get_maintenance() {
+            " (maintenance)"
+        } else {
+            ""
+        }
+    );
+
+    let params = res.get_parameters()?;
+    for i in 0..params.len() {
+        if i > 0 {
+            line.push_str(", ");
+        }
+        let param = params.get(i);
+        line.push_str(&format!(
+            "{}: {}",
+            param.get_key()?.to_str()?,
+            param.get_value()?.to_str()?
+        ));
+    }
+
+    line.push(']');
+    Ok(line)
+}
+
+/// Format the status reply as a tree of text for each resource group, of the form:
+///
+///     ost1_zpool: OK
+///       OK for 3m12s: [pool: ost1_zpool]
+///         Stopped for 5s: [mountpoint: /mnt/ost1, target: ost1]
+///
+/// where the group's line gives the ID of its root resource and its overall status, and each
+/// resource is indented two spaces further than the resource it depends on. A group none of whose
+/// resources are shown is left out. As in `status_text()`, the group's line starts with the name of
+/// its cluster when there are several.
+fn tree_text(
+    response: halo_mgmt::cluster::Reader,
+    args: &StatusArgs,
+) -> Result<String, commands::EmptyError> {
+    let several_clusters = has_several_clusters(response)?;
+    let resources = response.get_resources()?;
+
+    // The resources depending on each resource, by ID, in the order they were sent:
+    let mut children: HashMap<&str, Vec<halo_mgmt::resource::Reader>> = HashMap::new();
+    for res in resources.iter() {
+        children
+            .entry(res.get_parent()?.to_str()?)
+            .or_default()
+            .push(res);
+    }
+
+    let mut output = String::new();
+    for group in response.get_groups()?.iter() {
+        let id = group.get_id()?.to_str()?;
+        let mut lines = String::new();
+        // The group's root is the resource with no parent whose ID is the group's:
+        let mut stack: Vec<(halo_mgmt::resource::Reader, usize)> = children
+            .get("")
+            .into_iter()
+            .flatten()
+            .filter(|res| res.get_id().ok().and_then(|i| i.to_str().ok()) == Some(id))
+            .map(|res| (*res, 1))
+            .collect();
+        while let Some((res, depth)) = stack.pop() {
+            if !is_excluded(res, args)? {
+                lines.push_str(&format!("{}{}\n", "  ".repeat(depth), resource_line(res)?));
+            }
+            if let Some(deps) = children.get(res.get_id()?.to_str()?) {
+                stack.extend(deps.iter().rev().map(|dep| (*dep, depth + 1)));
+            }
+        }
 
-        let params = res.get_parameters()?;
-        for i in 0..params.len() {
-            if i > 0 {
-                print!(", ");
+        if !lines.is_empty() {
+            if several_clusters {
+                output.push_str(&format!("[{}] ", group.get_cluster()?.to_str()?));
             }
-            let param = params.get(i);
-            print!(
-                "{}: {}",
-                param.get_key()?.to_str()?,
-                param.get_value()?.to_str()?
+            output.push_str(&format!(
+                "{id}: {}\n{lines}",
+                status_word(group.get_status()?)
+            ));
+        }
+    }
+
+    Ok(output)
+}
+
+/// Convert the status reply into a JSON array with an object for each resource, of the form:
+///
+///     { "status": "Home", "status_age": 192, "maintenance": false, "group": "ost1_zpool",
+///       "cluster": "fs1", "parameters": { ... } }
+///
+/// where "status_age" is how long the resource has had its status, in seconds, "maintenance" is
+/// whether its home host is in maintenance mode, "group" is the ID of the root resource of its
+/// resource group, and "cluster" is the name of the cluster it belongs to.
+fn status_json(
+    response: halo_mgmt::cluster::Reader,
+    args: &StatusArgs,
+) -> Result<serde_json::Value, commands::EmptyError> {
+    let resources = response.get_resources()?;
+    let mut output = Vec::new();
+    for res in resources.iter() {
+        if is_excluded(res, args)? {
+            continue;
+        }
+        let mut parameters = serde_json::Map::new();
+        for param in res.get_parameters()?.iter() {
+            parameters.insert(
+                param.get_key()?.to_str()?.to_string(),
+                serde_json::Value::String(param.get_value()?.to_str()?.to_string()),
             );
         }
+        output.push(serde_json::json!({
+            "status": res.get_status()?.to_string(),
+            "status_age": res.get_status_age(),
+            "maintenance": res.get_maintenance(),
+            "group": res.get_group()?.to_str()?,
+            "cluster": res.get_cluster()?.to_str()?,
+            "parameters": parameters,
+        }));
+    }
 
-        println!("]");
+    Ok(serde_json::Value::Array(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use capnp::message::{Builder, HeapAllocator};
+
+    use super::*;
+
+    /// Build a status reply like the one sent by the manager, holding a resource for each of the
+    /// given statuses and parameters.
+    fn status_message(
+        resources: &[(halo_mgmt::Status, &[(&str, &str)])],
+    ) -> Builder<HeapAllocator> {
+        let resources: Vec<_> = resources
+            .iter()
+            .map(|(status, params)| (*status, 0, *params))
+            .collect();
+        status_message_with_ages(&resources)
     }
 
-    Ok(())
+    /// Like status_message(), but also giving how long each resource has had its status.
+    fn status_message_with_ages(
+        resources: &[(halo_mgmt::Status, u64, &[(&str, &str)])],
+    ) -> Builder<HeapAllocator> {
+        let mut message = Builder::new_default();
+        let cluster = message.init_root::<halo_mgmt::cluster::Builder>();
+        let mut resource_messages = cluster.init_resources(resources.len() as u32);
+        for (i, (status, age, params)) in resources.iter().enumerate() {
+            let mut res = resource_messages.reborrow().get(i as u32);
+            res.set_status(*status);
+            res.set_status_age(*age);
+            let mut parameters = res.init_parameters(params.len() as u32);
+            for (j, (k, v)) in params.iter().enumerate() {
+                let mut param = parameters.reborrow().get(j as u32);
+                param.set_key(*k);
+                param.set_value(*v);
+            }
+        }
+        message
+    }
+
+    #[test]
+    fn json_status() {
+        let message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (
+                halo_mgmt::Status::Stopped,
+                &[("mountpoint", "/mnt/ost1"), ("target", "ost1")],
+            ),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs {
+            json: true,
+            ..Default::default()
+        };
+        let json = status_json(reader, &args).unwrap();
+        let text = serde_json::to_string(&json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+
+        let resources = parsed.as_array().unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0]["status"], "Home");
+        assert_eq!(resources[0]["parameters"]["pool"], "zpool_1");
+        assert_eq!(resources[1]["status"], "Stopped");
+        assert_eq!(resources[1]["parameters"]["target"], "ost1");
+    }
+
+    #[test]
+    fn exclude_normal() {
+        let message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::Stopped, &[("pool", "zpool_2")]),
+            (halo_mgmt::Status::RunningOnAway, &[("pool", "zpool_3")]),
+            (halo_mgmt::Status::Unknown, &[("pool", "zpool_4")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs::default();
+        let all = status_text(reader, &args).unwrap();
+        assert_eq!(all.lines().count(), 4);
+        assert!(all.contains("OK for 0s: [pool: zpool_1]"));
+
+        let args = StatusArgs {
+            exclude_normal: true,
+            ..Default::default()
+        };
+        let abnormal = status_text(reader, &args).unwrap();
+        assert_eq!(
+            abnormal,
+            "Stopped for 0s: [pool: zpool_2]\nFailed over for 0s: [pool: zpool_3]\nUnknown for 0s: [pool: zpool_4]\n"
+        );
+    }
+
+    #[test]
+    fn exclude_normal_all_ok() {
+        let message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_2")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs {
+            exclude_normal: true,
+            ..Default::default()
+        };
+        assert_eq!(status_text(reader, &args).unwrap(), "");
+    }
+
+    #[test]
+    fn status_age() {
+        assert_eq!(format_age(45), "45s");
+        assert_eq!(format_age(192), "3m12s");
+        assert_eq!(format_age(7500), "2h05m");
+
+        let message = status_message_with_ages(&[
+            (
+                halo_mgmt::Status::RunningOnHome,
+                192,
+                &[("pool", "zpool_1")],
+            ),
+            (halo_mgmt::Status::Stopped, 5, &[("pool", "zpool_2")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        assert_eq!(
+            status_text(reader, &StatusArgs::default()).unwrap(),
+            "OK for 3m12s: [pool: zpool_1]\nStopped for 5s: [pool: zpool_2]\n"
+        );
+
+        let json = status_json(reader, &StatusArgs::default()).unwrap();
+        assert_eq!(json[0]["status_age"], 192);
+    }
+
+    #[test]
+    fn maintenance() {
+        let mut message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::Stopped, &[("pool", "zpool_2")]),
+        ]);
+        message
+            .get_root::<halo_mgmt::cluster::Builder>()
+            .unwrap()
+            .get_resources()
+            .unwrap()
+            .get(1)
+            .set_maintenance(true);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        assert_eq!(
+            status_text(reader, &StatusArgs::default()).unwrap(),
+            "OK for 0s: [pool: zpool_1]\nStopped for 0s (maintenance): [pool: zpool_2]\n"
+        );
+
+        let json = status_json(reader, &StatusArgs::default()).unwrap();
+        assert_eq!(json[0]["maintenance"], false);
+        assert_eq!(json[1]["maintenance"], true);
+    }
+
+    #[test]
+    fn fail_on_degraded() {
+        let message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::Stopped, &[("pool", "zpool_2")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        assert!(check_health(reader, &StatusArgs::default()).is_ok());
+
+        let args = StatusArgs {
+            fail_on_degraded: true,
+            ..Default::default()
+        };
+        assert!(check_health(reader, &args).is_err());
+
+        let message = status_message(&[(halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")])]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+        assert!(check_health(reader, &args).is_ok());
+    }
+
+    #[test]
+    fn recently_changed() {
+        let message = status_message_with_ages(&[
+            (
+                halo_mgmt::Status::RunningOnHome,
+                7500,
+                &[("pool", "zpool_1")],
+            ),
+            (halo_mgmt::Status::Stopped, 5, &[("pool", "zpool_2")]),
+            (halo_mgmt::Status::RunningOnAway, 60, &[("pool", "zpool_3")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs {
+            recently_changed: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(
+            status_text(reader, &args).unwrap(),
+            "Stopped for 5s: [pool: zpool_2]\nFailed over for 1m00s: [pool: zpool_3]\n"
+        );
+
+        let args = StatusArgs {
+            recently_changed: Some(10),
+            json: true,
+            ..Default::default()
+        };
+        let json = status_json(reader, &args).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["parameters"]["pool"], "zpool_2");
+    }
+
+    #[test]
+    fn tree() {
+        // Two groups: a zpool with two targets on it, one of them stopped, and a zpool alone.
+        let resources: [(&str, &str, &str, halo_mgmt::Status, &str); 4] = [
+            (
+                "zpool_1",
+                "",
+                "zpool_1",
+                halo_mgmt::Status::RunningOnHome,
+                "pool",
+            ),
+            (
+                "ost1",
+                "zpool_1",
+                "zpool_1",
+                halo_mgmt::Status::Stopped,
+                "target",
+            ),
+            (
+                "ost2",
+                "zpool_1",
+                "zpool_1",
+                halo_mgmt::Status::RunningOnHome,
+                "target",
+            ),
+            (
+                "zpool_2",
+                "",
+                "zpool_2",
+                halo_mgmt::Status::RunningOnHome,
+                "pool",
+            ),
+        ];
+        let mut message = Builder::new_default();
+        let mut cluster = message.init_root::<halo_mgmt::cluster::Builder>();
+        let mut resource_messages = cluster.reborrow().init_resources(resources.len() as u32);
+        for (i, (id, parent, group, status, key)) in resources.iter().enumerate() {
+            let mut res = resource_messages.reborrow().get(i as u32);
+            res.set_id(*id);
+            res.set_parent(*parent);
+            res.set_group(*group);
+            res.set_status(*status);
+            let mut param = res.init_parameters(1).get(0);
+            param.set_key(*key);
+            param.set_value(*id);
+        }
+        let mut groups = cluster.init_groups(2);
+        groups.reborrow().get(0).set_id("zpool_1");
+        groups
+            .reborrow()
+            .get(0)
+            .set_status(halo_mgmt::Status::Stopped);
+        groups.reborrow().get(1).set_id("zpool_2");
+        groups
+            .reborrow()
+            .get(1)
+            .set_status(halo_mgmt::Status::RunningOnHome);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs {
+            tree: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            status_text(reader, &args).unwrap(),
+            concat!(
+                "zpool_1: Stopped\n",
+                "  OK for 0s: [pool: zpool_1]\n",
+                "    Stopped for 0s: [target: ost1]\n",
+                "    OK for 0s: [target: ost2]\n",
+                "zpool_2: OK\n",
+                "  OK for 0s: [pool: zpool_2]\n",
+            )
+        );
+
+        // Only the groups with resources left to show are shown:
+        let args = StatusArgs {
+            tree: true,
+            exclude_normal: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            status_text(reader, &args).unwrap(),
+            "zpool_1: Stopped\n    Stopped for 0s: [target: ost1]\n"
+        );
+    }
+
+    #[test]
+    fn several_clusters() {
+        let mut message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::Stopped, &[("pool", "zpool_2")]),
+        ]);
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        // The cluster isn't named while there is only one:
+        assert_eq!(
+            status_text(reader, &StatusArgs::default()).unwrap(),
+            "OK for 0s: [pool: zpool_1]\nStopped for 0s: [pool: zpool_2]\n"
+        );
+
+        let mut resources = message
+            .get_root::<halo_mgmt::cluster::Builder>()
+            .unwrap()
+            .get_resources()
+            .unwrap();
+        for (i, cluster) in ["fs1", "fs2"].iter().enumerate() {
+            resources.reborrow().get(i as u32).set_cluster(*cluster);
+        }
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        assert_eq!(
+            status_text(reader, &StatusArgs::default()).unwrap(),
+            "[fs1] OK for 0s: [pool: zpool_1]\n[fs2] Stopped for 0s: [pool: zpool_2]\n"
+        );
+
+        let json = status_json(reader, &StatusArgs::default()).unwrap();
+        assert_eq!(json[0]["cluster"], "fs1");
+        assert_eq!(json[1]["cluster"], "fs2");
+    }
+
+    #[test]
+    fn group() {
+        let mut message = status_message(&[
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_1")]),
+            (halo_mgmt::Status::Stopped, &[("target", "ost1")]),
+            (halo_mgmt::Status::RunningOnHome, &[("pool", "zpool_2")]),
+        ]);
+        let mut resources = message
+            .get_root::<halo_mgmt::cluster::Builder>()
+            .unwrap()
+            .get_resources()
+            .unwrap();
+        for (i, group) in ["zpool_1", "zpool_1", "zpool_2"].iter().enumerate() {
+            resources.reborrow().get(i as u32).set_group(*group);
+        }
+        let reader = message
+            .get_root_as_reader::<halo_mgmt::cluster::Reader>()
+            .unwrap();
+
+        let args = StatusArgs {
+            group: Some("zpool_1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            status_text(reader, &args).unwrap(),
+            "OK for 0s: [pool: zpool_1]\nStopped for 0s: [target: ost1]\n"
+        );
+
+        let json = status_json(reader, &args).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["group"], "zpool_1");
+
+        // A group that doesn't exist has no resources to show:
+        let args = StatusArgs {
+            group: Some("zpool_3".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(status_text(reader, &args).unwrap(), "");
+    }
 }
@@ -1,15 +1,25 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use futures::future;
+use {futures::future, log::error};
 
-use crate::{cluster, commands};
+use crate::{cluster, commands, resource};
 
+/// Stop every resource in the cluster on its home host.
+///
+/// Lustre targets other than the MGS (or the MDT that it is combined with) are stopped first, then
+/// the MGS, and finally the zpools. Each resource is stopped along with its dependents, which are
+/// stopped before it, so a zpool is only exported once the targets on it have been unmounted.
 pub async fn stop(cluster: cluster::Cluster) -> commands::Result {
     // 1. All Lustre targets but MGS.
     let target_statuses: Vec<_> = cluster
         .lustre_resources_no_mgs()
-        .map(|t| async { (t.parameters.clone(), t.stop().await) })
+        .map(|t| async {
+            (
+                t.parameters.clone(),
+                t.stop_if_needed_recursive(resource::Location::Home).await,
+            )
+        })
         .collect();
 
     let results = future::join_all(target_statuses).await;
@@ -19,16 +29,21 @@ pub async fn stop(cluster: cluster::Cluster) -> commands::Result {
     let mgs = cluster.get_mgs();
     match mgs {
         Some(mgs) => {
-            let status = mgs.stop().await;
+            let status = mgs.stop_if_needed_recursive(resource::Location::Home).await;
             println!("{:?}", ("mgs", status));
         }
-        None => eprintln!("Could not find mgs target."),
+        None => error!("Could not find mgs target."),
     };
 
-    // 1. All zpools.
+    // 3. All zpools, together with any of their targets that have not been stopped yet.
     let zpool_statuses: Vec<_> = cluster
         .zpool_resources()
-        .map(|z| async { (z.parameters.clone(), z.stop().await) })
+        .map(|z| async {
+            (
+                z.parameters.clone(),
+                z.stop_if_needed_recursive(resource::Location::Home).await,
+            )
+        })
         .collect();
 
     let results = future::join_all(zpool_statuses).await;
@@ -1,22 +1,220 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use clap::Args;
+use std::{path::Path, sync::Arc, time::Duration};
+
+use {clap::Args, futures::future};
 
 use crate::cluster::Cluster;
-use crate::commands;
+use crate::commands::{self, Cli};
+use crate::config::Config;
+use crate::halo_capnp::do_ping_request;
+use crate::manager::MgrContext;
+use crate::remote::ocf;
+use crate::resource::Location;
+
+/// How long to wait for each remote agent to reply before reporting it as unreachable.
+const CHECK_AGENT_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[derive(Args, Debug, Clone)]
 pub struct ValidateArgs {
-    /// The config file to validate.
+    /// The config file to validate, or "-" to read it from stdin.
     #[arg(long)]
     config: String,
+
+    /// Also check that the remote agent on each host can be reached, and exit with a non-zero
+    /// status if any of them can't.
+    #[arg(long)]
+    check_agents: bool,
+
+    /// Also ask the remote agent on each resource's home host to validate the resource's
+    /// parameters with the OCF validate-all action, and exit with a non-zero status if any of them
+    /// are invalid.
+    #[arg(long)]
+    validate_resources: bool,
+
+    /// A resource agent which resources may use, given as PROVIDER/TYPE. May be given more than
+    /// once. Resources of any other kind are reported.
+    #[arg(
+        long = "known-kind",
+        value_name = "KIND",
+        default_values_t = ["heartbeat/ZFS".to_string(), "lustre/Lustre".to_string()]
+    )]
+    known_kinds: Vec<String>,
+
+    /// Treat resources of an unknown kind as an error, rather than a warning.
+    #[arg(long)]
+    strict: bool,
 }
 
-pub fn validate(args: &ValidateArgs) -> commands::Result {
-    let cluster = Cluster::from_config(args.config.to_string())?;
+/// Check that a config file is valid, and print a summary of the cluster it describes.
+///
+/// Building the Cluster performs the checks for invalid configs, such as malformed failover pairs
+/// or dependency cycles, and reports any problems on stderr.
+pub fn validate(cli: &Cli, args: &ValidateArgs) -> commands::Result {
+    let config = Config::from_file(&args.config)?;
+    check_kinds(&config, args)?;
+
+    // The cluster is built with the command line's arguments, so that the agents are connected to
+    // with the TLS setup given there. It is built from the config already read, since a config
+    // piped in on stdin can't be read twice:
+    let mut context_args = cli.clone();
+    context_args.config = vec![args.config.clone()];
+    let cluster = Cluster::with_config(Arc::new(MgrContext::new(context_args)), config)?;
 
     cluster.print_summary();
 
+    if args.check_agents || args.validate_resources {
+        let rt = tokio::runtime::Runtime::new()?;
+        if args.check_agents {
+            rt.block_on(check_agents(cli, &cluster))?;
+        }
+        if args.validate_resources {
+            rt.block_on(validate_resources(&cluster))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Report the resources whose kind is not one of the known resource agents. When OCF_ROOT is set,
+/// also report the resources whose resource agent script does not exist under it.
+///
+/// These are warnings, unless the user asked for strict checking.
+fn check_kinds(config: &Config, args: &ValidateArgs) -> commands::Result {
+    let level = if args.strict { "error" } else { "warning" };
+    let ocf_root = std::env::var("OCF_ROOT").ok();
+
+    let mut problems = 0;
+    for host in config.hosts.iter() {
+        for (id, kind) in host.unknown_kinds(&args.known_kinds) {
+            eprintln!(
+                "{level}: resource \"{id}\" on host \"{}\" has unknown kind \"{kind}\"",
+                host.hostname
+            );
+            problems += 1;
+        }
+
+        let Some(ocf_root) = &ocf_root else {
+            continue;
+        };
+        let mut ids: Vec<&String> = host.resources.keys().collect();
+        ids.sort();
+        for id in ids {
+            let script = Path::new(ocf_root)
+                .join("resource.d")
+                .join(&host.resources[id].kind);
+            if !script.exists() {
+                eprintln!(
+                    "{level}: resource \"{id}\" on host \"{}\" has no resource agent at \"{}\"",
+                    host.hostname,
+                    script.display()
+                );
+                problems += 1;
+            }
+        }
+    }
+
+    if args.strict && problems > 0 {
+        return commands::err();
+    }
+
+    Ok(())
+}
+
+/// Ping the remote agent on each host in the cluster, reporting whether each one is reachable.
+///
+/// The agents are connected to in the same way as by the manager, so this also checks that the
+/// TLS setup given on the command line works.
+async fn check_agents(cli: &Cli, cluster: &Cluster) -> commands::Result {
+    let mut hosts: Vec<_> = cluster.hosts().collect();
+    hosts.sort_by_key(|host| host.id());
+
+    let pings = hosts.iter().map(|host| async move {
+        let ping = tokio::time::timeout(CHECK_AGENT_TIMEOUT, do_ping_request(host, cli)).await;
+        (host, ping)
+    });
+
+    println!();
+    println!("=== Agents ===");
+    let mut unreachable = 0;
+    for (host, ping) in future::join_all(pings).await {
+        match ping {
+            Ok(Ok(info)) => println!(
+                "{host}: reachable (version {}, up for {}s)",
+                info.version,
+                info.uptime.as_secs()
+            ),
+            Ok(Err(e)) => {
+                eprintln!("{host}: unreachable: {e}");
+                unreachable += 1;
+            }
+            Err(_) => {
+                eprintln!(
+                    "{host}: unreachable: no reply within {}s",
+                    CHECK_AGENT_TIMEOUT.as_secs()
+                );
+                unreachable += 1;
+            }
+        }
+    }
+
+    if unreachable > 0 {
+        eprintln!("{unreachable} of {} agents are unreachable", hosts.len());
+        return commands::err();
+    }
+
+    Ok(())
+}
+
+/// Ask the remote agent on each resource's home host to validate the resource's parameters,
+/// reporting the result for each one.
+///
+/// A resource agent that does not implement validate-all is not counted as a failure.
+async fn validate_resources(cluster: &Cluster) -> commands::Result {
+    let mut resources: Vec<_> = cluster.resources().collect();
+    resources.sort_by_key(|res| res.id.clone());
+
+    let checks = resources.iter().map(|res| async move {
+        let result = tokio::time::timeout(CHECK_AGENT_TIMEOUT, res.validate(Location::Home)).await;
+        (res, result)
+    });
+
+    println!();
+    println!("=== Resource validation ===");
+    let mut invalid = 0;
+    for (res, result) in future::join_all(checks).await {
+        match result {
+            Ok(Ok(ocf::Status::Success)) => println!("{}: valid", res.id),
+            Ok(Ok(ocf::Status::ErrUnimplemented)) => {
+                println!("{}: resource agent does not support validate-all", res.id)
+            }
+            Ok(Ok(status)) => {
+                eprintln!("{}: invalid: {status}", res.id);
+                invalid += 1;
+            }
+            Ok(Err(e)) => {
+                eprintln!("{}: could not validate: {e}", res.id);
+                invalid += 1;
+            }
+            Err(_) => {
+                eprintln!(
+                    "{}: could not validate: no reply within {}s",
+                    res.id,
+                    CHECK_AGENT_TIMEOUT.as_secs()
+                );
+                invalid += 1;
+            }
+        }
+    }
+
+    if invalid > 0 {
+        eprintln!(
+            "{invalid} of {} resources failed validation",
+            resources.len()
+        );
+        return commands::err();
+    }
+
     Ok(())
 }
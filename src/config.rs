@@ -1,14 +1,263 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, fmt};
 
-use serde::{Deserialize, Serialize};
+use {
+    log::error,
+    serde::{Deserialize, Serialize},
+};
+
+/// A config file that does not describe a valid cluster.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file refers to an environment variable, as `${NAME}`, which is not set.
+    UnsetVariable { name: String },
+
+    /// A resource requires another resource which does not exist on the same host.
+    MissingDependency {
+        host: String,
+        resource: String,
+        requires: String,
+    },
+
+    /// A resource lacks parameters which the resource agent for its kind needs.
+    MissingParameters {
+        host: String,
+        resource: String,
+        kind: String,
+        missing: Vec<String>,
+    },
+
+    /// A host's failover partner is not one of the hosts in the config.
+    UnknownFailoverPartner { host: String, partner: String },
+
+    /// A failover pair names a host that is not one of the hosts in the config.
+    UnknownFailoverHost { host: String },
+
+    /// A failover pair does not consist of exactly two hosts.
+    MalformedFailoverPair { pair: Vec<String> },
+
+    /// A host appears in more than one failover pair.
+    HostInMultiplePairs { host: String },
+
+    /// The `requires` links between some resources on a host form a cycle. The cycle is listed in
+    /// dependency order, starting and ending with the same resource.
+    DependencyCycle { host: String, cycle: Vec<String> },
+
+    /// A colocation constraint names a resource that is not in the config.
+    UnknownColocatedResource { resource: String },
+
+    /// Two resources in a colocation constraint have different home hosts, and so different
+    /// failover partners, so they could not always run on the same host.
+    ColocatedOnDifferentHosts { first: String, other: String },
+
+    /// An anti-affinity constraint names a resource that is not in the config.
+    UnknownAntiAffineResource { resource: String },
+
+    /// An anti-affinity constraint has more resources than there are hosts for them to run on, so
+    /// some of them would have to share a host.
+    InfeasibleAntiAffinity {
+        resources: Vec<String>,
+        hosts: usize,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnsetVariable { name } => write!(
+                f,
+                "environment variable \"{name}\" is referred to, but is not set"
+            ),
+            ConfigError::MissingDependency {
+                host,
+                resource,
+                requires,
+            } => write!(
+                f,
+                "resource \"{resource}\" on host \"{host}\" requires \"{requires}\", which is not a resource on that host"
+            ),
+            ConfigError::MissingParameters {
+                host,
+                resource,
+                kind,
+                missing,
+            } => write!(
+                f,
+                "resource \"{resource}\" on host \"{host}\" is of kind \"{kind}\", which needs the parameters {}, but is missing {}",
+                required_parameters(kind).join(", "),
+                missing.join(", ")
+            ),
+            ConfigError::UnknownFailoverPartner { host, partner } => write!(
+                f,
+                "failover partner \"{partner}\" of host \"{host}\" is not a host in the config"
+            ),
+            ConfigError::UnknownFailoverHost { host } => write!(
+                f,
+                "failover pair names host \"{host}\", which is not a host in the config"
+            ),
+            ConfigError::MalformedFailoverPair { pair } => write!(
+                f,
+                "failover pair {pair:?} must have exactly two hosts, but has {}",
+                pair.len()
+            ),
+            ConfigError::HostInMultiplePairs { host } => write!(
+                f,
+                "host \"{host}\" appears in more than one failover pair"
+            ),
+            ConfigError::DependencyCycle { host, cycle } => write!(
+                f,
+                "resources on host \"{host}\" have a dependency cycle: {}",
+                cycle.join(" -> ")
+            ),
+            ConfigError::UnknownColocatedResource { resource } => write!(
+                f,
+                "colocation names resource \"{resource}\", which is not a resource in the config"
+            ),
+            ConfigError::ColocatedOnDifferentHosts { first, other } => write!(
+                f,
+                "colocated resources \"{first}\" and \"{other}\" must be on the same host, with the same failover partner"
+            ),
+            ConfigError::UnknownAntiAffineResource { resource } => write!(
+                f,
+                "anti-affinity names resource \"{resource}\", which is not a resource in the config"
+            ),
+            ConfigError::InfeasibleAntiAffinity { resources, hosts } => write!(
+                f,
+                "anti-affine resources {resources:?} can only run on {hosts} hosts, so they can't all be kept apart"
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub hosts: Vec<Host>,
     pub failover_pairs: Option<Vec<Vec<String>>>,
+
+    /// Whether the manager observes or manages the resources, unless told otherwise on the
+    /// command line with --manage-resources or --observe-resources. If not given, it observes.
+    pub mode: Option<Mode>,
+
+    /// How to retry starting a resource which fails to start. If not given, the defaults in
+    /// StartRetry are used.
+    pub start_retry: Option<StartRetry>,
+
+    /// Settings which apply to every host, unless the host gives its own.
+    pub defaults: Option<Defaults>,
+
+    /// Sets of resources which must always run on the same host, given as `[[colocation]]`
+    /// tables. When one of them moves to the other host in its failover pair, so do the rest.
+    pub colocation: Option<Vec<Colocation>>,
+
+    /// Sets of resources which must never run on the same host, given as `[[anti_affinity]]`
+    /// tables.
+    pub anti_affinity: Option<Vec<AntiAffinity>>,
+}
+
+/// A set of resources which must never run on the same host as each other.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AntiAffinity {
+    /// The IDs of the anti-affine resources.
+    pub resources: Vec<String>,
+}
+
+/// A set of resources which must always run on the same host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Colocation {
+    /// The IDs of the colocated resources.
+    pub resources: Vec<String>,
+}
+
+/// How the manager treats the resources: in Observe mode, it only monitors them, and in Manage
+/// mode, it also starts them and fails them over to keep them running.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Observe,
+    Manage,
+}
+
+/// The parameters that the resource agent for a resource of the given kind can't do without. A
+/// kind not listed here may need parameters too, but they aren't checked.
+pub fn required_parameters(kind: &str) -> &'static [&'static str] {
+    match kind {
+        "heartbeat/ZFS" => &["pool"],
+        "lustre/Lustre" => &["mountpoint", "target"],
+        _ => &[],
+    }
+}
+
+/// Cluster-wide settings, given in the `[defaults]` table. Each of these can be overridden by a
+/// host.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Defaults {
+    /// Name of the fence agent binary to use for fencing hosts.
+    pub fence_agent: Option<String>,
+
+    /// Fence parameters shared by the hosts. A host's own fence parameters are added to these,
+    /// replacing any with the same name, unless the host uses a different fence agent, in which
+    /// case these are not used for it at all.
+    pub fence_parameters: Option<HashMap<String, String>>,
+
+    /// How often to monitor resources, in seconds.
+    pub monitor_interval_secs: Option<u64>,
+
+    /// How long a resource agent operation may run before the remote agent kills it, in seconds.
+    pub op_timeout_secs: Option<u64>,
+
+    /// How to retry a fence action that fails, for fence agents which don't give their own.
+    pub fence_retry: Option<FenceRetry>,
+}
+
+/// How to retry a fence action that fails, since a BMC may fail to respond now and then.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FenceRetry {
+    /// How many times in all to try the action with a fence agent, before moving on to the host's
+    /// next fence agent, if any. Defaults to 1, so that a failed action is not retried.
+    pub attempts: u32,
+
+    /// How long to wait between attempts, in milliseconds.
+    pub delay_ms: u64,
+}
+
+impl Default for FenceRetry {
+    fn default() -> Self {
+        FenceRetry {
+            attempts: 1,
+            delay_ms: 1000,
+        }
+    }
+}
+
+/// How to retry starting a resource whose start fails with an error that may be transient.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct StartRetry {
+    /// How many times in all to try starting the resource.
+    pub attempts: u32,
+
+    /// How long to wait before the first retry, in milliseconds. The wait doubles before each
+    /// further retry.
+    pub base_delay_ms: u64,
+
+    /// How many starts in a row may fail before the manager gives up on the resource, marking it
+    /// Unrunnable, until an operator cleans it up.
+    pub max_failed_starts: u32,
+}
+
+impl Default for StartRetry {
+    fn default() -> Self {
+        StartRetry {
+            attempts: 3,
+            base_delay_ms: 1000,
+            max_failed_starts: 5,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,6 +272,328 @@ pub struct Host {
 
     /// Fence parameters for this host.
     pub fence_parameters: Option<HashMap<String, String>>,
+
+    /// How to retry a failed fence action with `fence_agent`, overriding the default.
+    pub fence_retry: Option<FenceRetry>,
+
+    /// Further fence agents for this host. When fencing, `fence_agent` is tried first, followed by
+    /// each of these in order, until one of them succeeds.
+    pub fence_agents: Option<Vec<FenceAgentSpec>>,
+
+    /// How often to monitor this host's resources, in seconds, overriding the default.
+    pub monitor_interval_secs: Option<u64>,
+
+    /// How long an operation on this host's resources may run, in seconds, overriding the default.
+    pub op_timeout_secs: Option<u64>,
+
+    /// The name that the remote agent's TLS certificate is valid for, if it is not the hostname.
+    pub server_name: Option<String>,
+}
+
+/// One of the fence agents that can be used for fencing a host.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FenceAgentSpec {
+    /// Name of the fence agent binary.
+    pub agent: String,
+
+    /// Fence parameters for using this agent on the host.
+    pub parameters: Option<HashMap<String, String>>,
+
+    /// How to retry a failed fence action with this agent, overriding the default.
+    pub retry: Option<FenceRetry>,
+}
+
+/// Substitute each `${NAME}` in every string value of a parsed config file with the value of
+/// NAME, as given by `lookup`; see interpolate_env(). Keys are left alone, and so are comments,
+/// since they are gone by the time the file is parsed.
+pub fn interpolate_values(
+    value: &mut toml::Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<(), ConfigError> {
+    match value {
+        toml::Value::String(s) => *s = interpolate_env(s, lookup)?,
+        toml::Value::Array(values) => {
+            for value in values.iter_mut() {
+                interpolate_values(value, lookup)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for value in table.values_mut() {
+                interpolate_values(value, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Substitute each `${NAME}` in a string from a config file with the value of NAME, as given by
+/// `lookup`. This allows secrets such as fence agent passwords to be kept out of the config file.
+///
+/// `$${` is an escaped `${`, which is not substituted. Any other `$` is left alone.
+pub fn interpolate_env(
+    raw: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    let mut output = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(pos) = rest.find('$') {
+        output.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = after;
+        } else if let Some((name, after)) = rest.strip_prefix("${").and_then(|r| r.split_once('}'))
+        {
+            let value = lookup(name).ok_or_else(|| ConfigError::UnsetVariable {
+                name: name.to_string(),
+            })?;
+            output.push_str(&value);
+            rest = after;
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// The config path which stands for stdin, so that a config can be piped in.
+pub const STDIN_PATH: &str = "-";
+
+impl Config {
+    /// Read and parse the config file at `path`, or from stdin if `path` is "-", reporting any
+    /// problem on stderr.
+    ///
+    /// References to environment variables in the file's string values are substituted; see
+    /// interpolate_values().
+    pub fn from_file(path: &str) -> Result<Self, crate::commands::EmptyError> {
+        let config = if path == STDIN_PATH {
+            std::io::read_to_string(std::io::stdin())
+        } else {
+            std::fs::read_to_string(path)
+        }
+        .inspect_err(|e| {
+            error!("Could not open config file \"{path}\": {e}");
+        })?;
+
+        Self::parse(&config, path)
+    }
+
+    /// Parse the text of a config file read from `path`, which is only used in messages. This is
+    /// the part of from_file() that comes after reading the file.
+    pub fn parse(config: &str, path: &str) -> Result<Self, crate::commands::EmptyError> {
+        let mut value: toml::Value = toml::from_str(config).inspect_err(|e| {
+            error!("Could not parse config file \"{path}\": {e}");
+        })?;
+
+        interpolate_values(&mut value, &|name| std::env::var(name).ok()).inspect_err(|e| {
+            error!("Invalid config file \"{path}\": {e}");
+        })?;
+
+        let config = value.try_into().inspect_err(|e| {
+            error!("Could not parse config file \"{path}\": {e}");
+        })?;
+
+        Ok(config)
+    }
+
+    /// Check that the failover pairs, if any, are well formed: each pair must consist of exactly
+    /// two hosts, both of which are in the config, and no host may be in more than one pair.
+    pub fn validate_failover_pairs(&self) -> Result<(), ConfigError> {
+        let Some(pairs) = &self.failover_pairs else {
+            return Ok(());
+        };
+
+        let mut seen: Vec<&str> = Vec::new();
+        for pair in pairs.iter() {
+            if pair.len() != 2 {
+                return Err(ConfigError::MalformedFailoverPair { pair: pair.clone() });
+            }
+            for host in pair.iter() {
+                if !self.hosts.iter().any(|h| &h.hostname == host) {
+                    return Err(ConfigError::UnknownFailoverHost { host: host.clone() });
+                }
+                if seen.contains(&host.as_str()) {
+                    return Err(ConfigError::HostInMultiplePairs { host: host.clone() });
+                }
+                seen.push(host);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the colocation constraints, if any, can be met: each resource they name must be
+    /// in the config, and the resources in each constraint must share a home host, which also
+    /// means that they share a failover partner.
+    pub fn validate_colocations(&self) -> Result<(), ConfigError> {
+        for colocation in self.colocation.iter().flatten() {
+            let mut first: Option<(&str, &str)> = None;
+            for id in colocation.resources.iter() {
+                let Some(host) = self.hosts.iter().find(|h| h.resources.contains_key(id)) else {
+                    return Err(ConfigError::UnknownColocatedResource {
+                        resource: id.clone(),
+                    });
+                };
+                match first {
+                    None => first = Some((id, &host.hostname)),
+                    Some((first_id, first_host)) if first_host != host.hostname => {
+                        return Err(ConfigError::ColocatedOnDifferentHosts {
+                            first: first_id.to_string(),
+                            other: id.clone(),
+                        });
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that each resource of a kind with required parameters--see required_parameters()--has
+    /// all of them.
+    pub fn validate_parameters(&self) -> Result<(), ConfigError> {
+        for host in self.hosts.iter() {
+            let mut ids: Vec<&String> = host.resources.keys().collect();
+            ids.sort();
+            for id in ids {
+                let res = &host.resources[id];
+                let missing: Vec<String> = required_parameters(&res.kind)
+                    .iter()
+                    .filter(|param| !res.parameters.contains_key(**param))
+                    .map(|param| param.to_string())
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(ConfigError::MissingParameters {
+                        host: host.hostname.clone(),
+                        resource: id.clone(),
+                        kind: res.kind.clone(),
+                        missing,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the anti-affinity constraints, if any, can be met: each resource they name must
+    /// be in the config, and the resources in each constraint must have at least as many hosts
+    /// between them to run on--their home hosts and failover partners--as there are resources.
+    pub fn validate_anti_affinities(&self) -> Result<(), ConfigError> {
+        for anti_affinity in self.anti_affinity.iter().flatten() {
+            let mut hosts: Vec<&str> = Vec::new();
+            for id in anti_affinity.resources.iter() {
+                let Some(host) = self.hosts.iter().find(|h| h.resources.contains_key(id)) else {
+                    return Err(ConfigError::UnknownAntiAffineResource {
+                        resource: id.clone(),
+                    });
+                };
+                let partner = self
+                    .failover_pairs
+                    .iter()
+                    .flatten()
+                    .find(|pair| pair.contains(&host.hostname))
+                    .and_then(|pair| pair.iter().find(|h| **h != host.hostname));
+                for name in std::iter::once(&host.hostname).chain(partner) {
+                    if !hosts.contains(&name.as_str()) {
+                        hosts.push(name);
+                    }
+                }
+            }
+
+            if anti_affinity.resources.len() > hosts.len() {
+                return Err(ConfigError::InfeasibleAntiAffinity {
+                    resources: anti_affinity.resources.clone(),
+                    hosts: hosts.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Host {
+    /// Get the fence agent and fence parameters for this host, falling back to the defaults for
+    /// any that it doesn't give.
+    ///
+    /// The default fence parameters are only used when the host uses the default fence agent.
+    pub fn fence_settings(
+        &self,
+        defaults: &Defaults,
+    ) -> (Option<String>, Option<HashMap<String, String>>) {
+        let Some(agent) = self.fence_agent.as_ref().or(defaults.fence_agent.as_ref()) else {
+            return (None, self.fence_parameters.clone());
+        };
+        if defaults.fence_agent.as_ref() != Some(agent) {
+            return (Some(agent.clone()), self.fence_parameters.clone());
+        }
+
+        let parameters = match (&defaults.fence_parameters, &self.fence_parameters) {
+            (None, None) => None,
+            (default, own) => Some(
+                default
+                    .iter()
+                    .chain(own.iter())
+                    .flatten()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            ),
+        };
+        (Some(agent.clone()), parameters)
+    }
+
+    /// Look for a cycle in the `requires` links between this host's resources, including a
+    /// resource that requires itself.
+    ///
+    /// If there is a cycle, returns the IDs of the resources in it, starting and ending with the
+    /// same resource.
+    pub fn find_dependency_cycle(&self) -> Option<Vec<String>> {
+        // Visit resources in a predictable order so that the same cycle is always reported:
+        let mut ids: Vec<&String> = self.resources.keys().collect();
+        ids.sort();
+
+        for start in ids {
+            let mut chain: Vec<&str> = vec![start.as_str()];
+            let mut current = start;
+            while let Some(parent) = self
+                .resources
+                .get(current)
+                .and_then(|r| r.requires.as_ref())
+            {
+                if let Some(pos) = chain.iter().position(|id| *id == parent.as_str()) {
+                    let mut cycle: Vec<String> =
+                        chain[pos..].iter().map(|id| id.to_string()).collect();
+                    cycle.push(parent.to_string());
+                    return Some(cycle);
+                }
+                chain.push(parent.as_str());
+                current = parent;
+            }
+        }
+
+        None
+    }
+
+    /// Find the resources on this host whose kind is not one of the `known` resource agents.
+    ///
+    /// Returns the ID and kind of each such resource, ordered by ID.
+    pub fn unknown_kinds(&self, known: &[String]) -> Vec<(&str, &str)> {
+        let mut unknown: Vec<(&str, &str)> = self
+            .resources
+            .iter()
+            .filter(|(_, res)| !known.contains(&res.kind))
+            .map(|(id, res)| (id.as_str(), res.kind.as_str()))
+            .collect();
+        unknown.sort();
+        unknown
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -36,45 +607,99 @@ pub struct Resource {
     /// Each resource is allowed to specify a single dependency. The named resource must be started
     /// before this one.
     pub requires: Option<String>,
+
+    /// Among the resources that are started at the same stage, those with a higher priority are
+    /// started before those with a lower one. Defaults to 0.
+    pub priority: Option<i32>,
+
+    /// Whether the manager looks after this resource. A disabled resource is kept in the config,
+    /// but is never monitored, started, or stopped, and neither are the resources that require
+    /// it to be started first. Defaults to true.
+    pub enabled: Option<bool>,
+
+    /// How long each kind of operation on this resource may run before the remote agent kills it,
+    /// overriding the host's `op_timeout_secs` for that operation.
+    pub timeouts: Option<OpTimeouts>,
 }
 
+/// How long, in seconds, each kind of operation on a resource may run, such as:
+///
+///     timeouts = { start = 120, stop = 60, monitor = 10 }
+///
+/// An operation not given here is allowed the host's op_timeout_secs, if set, or else the one in
+/// DEFAULT_OP_TIMEOUTS.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct OpTimeouts {
+    pub start: Option<u64>,
+    pub stop: Option<u64>,
+    pub monitor: Option<u64>,
+}
+
+/// How long, in seconds, each kind of operation may run when neither the resource nor its host
+/// gives a timeout for it. A start or stop, such as importing or exporting a zpool, may take
+/// minutes, while a monitor should be quick.
+pub const DEFAULT_OP_TIMEOUTS: OpTimeouts = OpTimeouts {
+    start: Some(300),
+    stop: Some(300),
+    monitor: Some(60),
+};
+
 impl Resource {
     pub fn new_zpool(pool: String) -> Self {
         Self {
             kind: "heartbeat/ZFS".to_string(),
             parameters: HashMap::from([("pool".to_string(), pool)]),
             requires: None,
+            priority: None,
+            enabled: None,
+            timeouts: None,
         }
     }
 
     /// Given a line of output from the `mount` command, parses it into a Lustre Resource.
     ///
-    /// TODO: make this return a result instead of panicking?
-    pub fn new_lustre(mount_output: &str) -> Self {
+    /// The kind of target is "mgs", "mdt", or "ost", or "mgt" for an MDT which the MGS is combined
+    /// with, as in small filesystems. Such a target is mounted with the "mgs" option, like a
+    /// standalone MGS, but its svname is that of an MDT.
+    ///
+    /// The line is expected to be of the form:
+    ///
+    ///     <device> on <mountpoint> type lustre (<option>,...)
+    pub fn new_lustre(mount_output: &str) -> Result<Self, ParseError> {
+        let error = |reason: &str| ParseError {
+            line: mount_output.to_string(),
+            reason: reason.to_string(),
+        };
         let mut tokens = mount_output.split_whitespace();
 
-        let device = tokens.next().unwrap();
-        let zpool = device.split('/').next().unwrap();
-        let mountpoint = tokens.nth(1).unwrap();
+        let device = tokens.next().ok_or_else(|| error("the line is empty"))?;
+        let zpool = device.split('/').next().unwrap_or_default();
+        let mountpoint = tokens
+            .nth(1)
+            .ok_or_else(|| error("there is no mountpoint"))?;
 
-        let opts = tokens.nth(2).unwrap();
+        let opts = tokens
+            .nth(2)
+            .ok_or_else(|| error("there are no mount options"))?;
         let opts = opts.trim_matches(|c| c == '(' || c == ')').split(',');
-        let mut kind: Option<String> = None;
+        let mut svname = None;
+        let mut mgs = false;
         for opt in opts {
-            if opt.starts_with("svname=") {
-                if opt.contains("MDT") {
-                    kind = Some("mdt".to_string());
-                } else if opt.contains("MGS") {
-                    kind = Some("mgs".to_string());
-                } else if opt.contains("OST") {
-                    kind = Some("ost".to_string());
-                }
+            if let Some(name) = opt.strip_prefix("svname=") {
+                svname = Some(name);
+            } else if opt == "mgs" {
+                mgs = true;
             }
         }
-        let Some(kind) = kind else {
-            panic!("could not parse lustre mount line")
+        let kind = match svname {
+            Some(name) if name.contains("MDT") && mgs => "mgt",
+            Some(name) if name.contains("MDT") => "mdt",
+            Some(name) if name.contains("MGS") => "mgs",
+            Some(name) if name.contains("OST") => "ost",
+            Some(_) => return Err(error("the svname is not that of an MGS, MDT, or OST")),
+            None => return Err(error("there is no svname option")),
         };
-        Self {
+        Ok(Self {
             kind: "lustre/Lustre".to_string(),
             parameters: HashMap::from([
                 ("mountpoint".to_string(), mountpoint.to_string()),
@@ -82,6 +707,385 @@ impl Resource {
                 ("kind".to_string(), kind.to_string()),
             ]),
             requires: Some(zpool.to_string()),
+            priority: None,
+            enabled: None,
+            timeouts: None,
+        })
+    }
+}
+
+/// A line of `mount` output which could not be parsed into a Lustre resource.
+#[derive(Debug)]
+pub struct ParseError {
+    line: String,
+    reason: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "could not parse lustre mount line \"{}\": {}",
+            self.line, self.reason
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn host_with_requires(requires: &[(&str, Option<&str>)]) -> Host {
+        let resources = requires
+            .iter()
+            .map(|(id, req)| {
+                let mut res = Resource::new_zpool(id.to_string());
+                res.requires = req.map(|r| r.to_string());
+                (id.to_string(), res)
+            })
+            .collect::<HashMap<_, _>>();
+        Host {
+            hostname: "mds00".to_string(),
+            resources,
+            fence_agent: None,
+            fence_parameters: None,
+            fence_agents: None,
+            monitor_interval_secs: None,
+            op_timeout_secs: None,
+            server_name: None,
+            fence_retry: None,
+        }
+    }
+
+    fn config_with_pairs(pairs: Vec<Vec<&str>>) -> Config {
+        Config {
+            hosts: ["mds00", "mds01", "oss00", "oss01"]
+                .iter()
+                .map(|name| {
+                    let mut host = host_with_requires(&[]);
+                    host.hostname = name.to_string();
+                    host
+                })
+                .collect(),
+            failover_pairs: Some(
+                pairs
+                    .into_iter()
+                    .map(|pair| pair.into_iter().map(|h| h.to_string()).collect())
+                    .collect(),
+            ),
+            mode: None,
+            start_retry: None,
+            defaults: None,
+            colocation: None,
+            anti_affinity: None,
+        }
+    }
+
+    #[test]
+    fn valid_failover_pairs() {
+        let config = config_with_pairs(vec![vec!["mds00", "mds01"], vec!["oss00", "oss01"]]);
+        assert!(config.validate_failover_pairs().is_ok());
+    }
+
+    #[test]
+    fn failover_pair_unknown_host() {
+        let config = config_with_pairs(vec![vec!["mds00", "mds02"]]);
+        assert!(matches!(
+            config.validate_failover_pairs(),
+            Err(ConfigError::UnknownFailoverHost { host }) if host == "mds02"
+        ));
+    }
+
+    #[test]
+    fn failover_pair_wrong_length() {
+        let config = config_with_pairs(vec![vec!["mds00", "mds01", "oss00"]]);
+        assert!(matches!(
+            config.validate_failover_pairs(),
+            Err(ConfigError::MalformedFailoverPair { .. })
+        ));
+
+        let config = config_with_pairs(vec![vec!["mds00"]]);
+        assert!(matches!(
+            config.validate_failover_pairs(),
+            Err(ConfigError::MalformedFailoverPair { .. })
+        ));
+    }
+
+    #[test]
+    fn failover_host_in_multiple_pairs() {
+        let config = config_with_pairs(vec![vec!["mds00", "mds01"], vec!["mds01", "oss00"]]);
+        assert!(matches!(
+            config.validate_failover_pairs(),
+            Err(ConfigError::HostInMultiplePairs { host }) if host == "mds01"
+        ));
+    }
+
+    #[test]
+    fn colocations() {
+        let mut config = config_with_pairs(vec![vec!["mds00", "mds01"]]);
+        config.hosts[0] = host_with_requires(&[("mgs", None), ("mdt0", None)]);
+        config.hosts[1] = host_with_requires(&[("mdt1", None)]);
+        config.hosts[1].hostname = "mds01".to_string();
+        let colocate = |resources: &[&str]| {
+            Some(vec![Colocation {
+                resources: resources.iter().map(|r| r.to_string()).collect(),
+            }])
+        };
+
+        config.colocation = colocate(&["mgs", "mdt0"]);
+        assert!(config.validate_colocations().is_ok());
+
+        config.colocation = colocate(&["mgs", "mdt2"]);
+        assert!(matches!(
+            config.validate_colocations(),
+            Err(ConfigError::UnknownColocatedResource { resource }) if resource == "mdt2"
+        ));
+
+        config.colocation = colocate(&["mgs", "mdt1"]);
+        assert!(matches!(
+            config.validate_colocations(),
+            Err(ConfigError::ColocatedOnDifferentHosts { first, other })
+                if first == "mgs" && other == "mdt1"
+        ));
+    }
+
+    #[test]
+    fn anti_affinities() {
+        let mut config = config_with_pairs(vec![vec!["mds00", "mds01"]]);
+        config.hosts[0] = host_with_requires(&[("mdt0", None), ("mdt1", None), ("mdt2", None)]);
+        let anti_affine = |resources: &[&str]| {
+            Some(vec![AntiAffinity {
+                resources: resources.iter().map(|r| r.to_string()).collect(),
+            }])
+        };
+
+        // Two resources can be kept apart on a failover pair...
+        config.anti_affinity = anti_affine(&["mdt0", "mdt1"]);
+        assert!(config.validate_anti_affinities().is_ok());
+
+        // ...but three can't:
+        config.anti_affinity = anti_affine(&["mdt0", "mdt1", "mdt2"]);
+        assert!(matches!(
+            config.validate_anti_affinities(),
+            Err(ConfigError::InfeasibleAntiAffinity { hosts: 2, .. })
+        ));
+
+        config.anti_affinity = anti_affine(&["mdt0", "mdt3"]);
+        assert!(matches!(
+            config.validate_anti_affinities(),
+            Err(ConfigError::UnknownAntiAffineResource { resource }) if resource == "mdt3"
+        ));
+    }
+
+    #[test]
+    fn no_dependency_cycle() {
+        let host = host_with_requires(&[("a", None), ("b", Some("a")), ("c", Some("b"))]);
+        assert_eq!(host.find_dependency_cycle(), None);
+    }
+
+    #[test]
+    fn mutual_dependency_cycle() {
+        let host = host_with_requires(&[("a", Some("b")), ("b", Some("a")), ("c", Some("a"))]);
+        assert_eq!(
+            host.find_dependency_cycle(),
+            Some(vec!["a".to_string(), "b".to_string(), "a".to_string()])
+        );
+    }
+
+    #[test]
+    fn self_dependency_cycle() {
+        let host = host_with_requires(&[("a", Some("a"))]);
+        assert_eq!(
+            host.find_dependency_cycle(),
+            Some(vec!["a".to_string(), "a".to_string()])
+        );
+    }
+
+    #[test]
+    fn unknown_kinds() {
+        let mut host = host_with_requires(&[("a", None), ("b", Some("a")), ("c", Some("a"))]);
+        host.resources.get_mut("b").unwrap().kind = "lustre/Lustre".to_string();
+        host.resources.get_mut("c").unwrap().kind = "heatbeat/ZFS".to_string();
+
+        let known = ["heartbeat/ZFS".to_string(), "lustre/Lustre".to_string()];
+        assert_eq!(host.unknown_kinds(&known), vec![("c", "heatbeat/ZFS")]);
+        assert!(host.unknown_kinds(&[]).len() == 3);
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "REDFISH_PW" => Some("hunter2".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolate_variable() {
+        assert_eq!(
+            interpolate_env("pw: ${REDFISH_PW}.", lookup).unwrap(),
+            "pw: hunter2."
+        );
+
+        for raw in ["$5", "trailing $", "admin"] {
+            assert_eq!(interpolate_env(raw, lookup).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn interpolate_missing_variable() {
+        assert!(matches!(
+            interpolate_env("${NO_SUCH_VAR}", lookup),
+            Err(ConfigError::UnsetVariable { name }) if name == "NO_SUCH_VAR"
+        ));
+    }
+
+    #[test]
+    fn interpolate_escaped() {
+        assert_eq!(
+            interpolate_env("$${REDFISH_PW} ${REDFISH_PW}", lookup).unwrap(),
+            "${REDFISH_PW} hunter2"
+        );
+    }
+
+    #[test]
+    fn interpolate_only_values() {
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            # Set ${NO_SUCH_VAR} before starting the manager.
+            "${REDFISH_PW}" = "key"
+            password = "${REDFISH_PW}"
+            quoted = "${QUOTED}"
+            agents = [{ password = "${REDFISH_PW}" }]
+            "#,
+        )
+        .unwrap();
+        let lookup = |name: &str| match name {
+            "QUOTED" => Some("a\"b".to_string()),
+            _ => lookup(name),
+        };
+        interpolate_values(&mut value, &lookup).unwrap();
+
+        assert_eq!(value["${REDFISH_PW}"].as_str(), Some("key"));
+        assert_eq!(value["password"].as_str(), Some("hunter2"));
+        assert_eq!(value["quoted"].as_str(), Some("a\"b"));
+        assert_eq!(value["agents"][0]["password"].as_str(), Some("hunter2"));
+    }
+
+    #[test]
+    fn default_fence_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            [defaults]
+            fence_agent = "fence_ipmilan"
+            monitor_interval_secs = 10
+
+              [defaults.fence_parameters]
+              username = "admin"
+              password = "secret"
+
+            [[hosts]]
+            hostname = "mds00"
+            resources = {}
+
+              [hosts.fence_parameters]
+              port = "6230"
+
+            [[hosts]]
+            hostname = "mds01"
+            resources = {}
+            fence_agent = "powerman"
+            "#,
+        )
+        .unwrap();
+        let defaults = config.defaults.unwrap();
+        assert_eq!(defaults.monitor_interval_secs, Some(10));
+
+        // The first host inherits the default agent, adding its own parameters to the defaults:
+        let (agent, params) = config.hosts[0].fence_settings(&defaults);
+        assert_eq!(agent.as_deref(), Some("fence_ipmilan"));
+        let params = params.unwrap();
+        assert_eq!(params["username"], "admin");
+        assert_eq!(params["port"], "6230");
+
+        // The second host overrides the agent, so the default parameters don't apply to it:
+        let (agent, params) = config.hosts[1].fence_settings(&defaults);
+        assert_eq!(agent.as_deref(), Some("powerman"));
+        assert_eq!(params, None);
+    }
+
+    #[test]
+    fn resource_timeouts() {
+        let config: Config = toml::from_str(
+            r#"
+            [[hosts]]
+            hostname = "oss00"
+
+              [hosts.resources.ost0_pool]
+              kind = "heartbeat/ZFS"
+              parameters = { pool = "ost0" }
+              timeouts = { start = 120, monitor = 10 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.hosts[0].resources["ost0_pool"].timeouts,
+            Some(OpTimeouts {
+                start: Some(120),
+                stop: None,
+                monitor: Some(10),
+            })
+        );
+    }
+
+    #[test]
+    fn missing_parameters() {
+        let mut host = host_with_requires(&[("pool", None)]);
+        let mut config = config_with_pairs(Vec::new());
+        config.hosts.truncate(1);
+        assert!(config.validate_parameters().is_ok());
+
+        let mut target = Resource::new_zpool("unused".to_string());
+        target.kind = "lustre/Lustre".to_string();
+        target.parameters = HashMap::from([("target".to_string(), "test_mgt".to_string())]);
+        host.resources.insert("test_mgt".to_string(), target);
+        config.hosts = vec![host];
+
+        let e = config.validate_parameters().unwrap_err();
+        assert!(matches!(
+            &e,
+            ConfigError::MissingParameters { resource, missing, .. }
+                if resource == "test_mgt" && *missing == ["mountpoint"]
+        ));
+        let message = e.to_string();
+        assert!(message.contains("\"test_mgt\""));
+        assert!(message.contains("missing mountpoint"));
+    }
+
+    #[test]
+    fn new_lustre_malformed() {
+        let line = "mds00e0/mdt0 on /mnt/mdt0 type lustre (ro,svname=test-MDT0000,osd=osd-zfs)";
+        assert!(Resource::new_lustre(line).is_ok());
+
+        for (line, reason) in [
+            ("", "empty"),
+            ("mds00e0/mdt0 on", "no mountpoint"),
+            ("mds00e0/mdt0 on /mnt/mdt0 type lustre", "no mount options"),
+            (
+                "mds00e0/mdt0 on /mnt/mdt0 type lustre (ro,osd=osd-zfs)",
+                "no svname",
+            ),
+            (
+                "mds00e0/x on /mnt/x type lustre (ro,svname=test-XYZ0000)",
+                "not that of",
+            ),
+        ] {
+            let e = Resource::new_lustre(line).unwrap_err().to_string();
+            assert!(e.contains(&format!("\"{line}\"")));
+            assert!(e.contains(reason));
         }
     }
 }
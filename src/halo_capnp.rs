@@ -1,19 +1,37 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{env, error::Error, fmt};
+use std::{collections::HashMap, env, fmt, time::Duration};
 
-use {futures::AsyncReadExt, rustls::pki_types::ServerName};
+use {
+    futures::{future::LocalBoxFuture, AsyncReadExt},
+    log::error,
+    rustls::pki_types::ServerName,
+    tokio::sync::{mpsc, oneshot},
+};
 
 use crate::{
-    resource::{self, Location, Resource},
-    tls::get_connector,
+    commands::Cli,
+    host::Host,
+    remote::ocf,
+    resource::{self, Location, Resource, ResourceError},
+    tls::{get_connector, get_connector_server_only, TlsMode},
 };
 
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
 
 include!(concat!(env!("OUT_DIR"), "/halo_capnp.rs"));
 
+/// The limits on the messages read from the other end of an RPC connection, by the manager and the
+/// remote agent alike. A message bigger than this is refused, closing the connection, rather than
+/// read into memory. The largest legitimate messages, such as the manager's activity log, are well
+/// under a megabyte.
+pub const RPC_READER_OPTIONS: capnp::message::ReaderOptions = capnp::message::ReaderOptions {
+    // 8 MiB:
+    traversal_limit_in_words: Some(1 << 20),
+    nesting_limit: 32,
+};
+
 /// Alias for a capnp operation RPC, client side
 type OperationRequest = ::capnp::capability::Request<
     ocf_resource_agent::operation_params::Owned,
@@ -33,6 +51,9 @@ impl std::convert::From<resource::ResourceStatus> for halo_mgmt::Status {
             resource::ResourceStatus::CheckingAway => halo_mgmt::Status::CheckingAway,
             resource::ResourceStatus::RunningOnAway => halo_mgmt::Status::RunningOnAway,
             resource::ResourceStatus::Unrunnable => halo_mgmt::Status::Unrunnable,
+            resource::ResourceStatus::Degraded => halo_mgmt::Status::Degraded,
+            resource::ResourceStatus::SplitBrain => halo_mgmt::Status::SplitBrain,
+            resource::ResourceStatus::Disabled => halo_mgmt::Status::Disabled,
         }
     }
 }
@@ -50,94 +71,507 @@ impl fmt::Display for halo_mgmt::Status {
                 halo_mgmt::Status::CheckingAway => "Checking on failover",
                 halo_mgmt::Status::RunningOnAway => "Failed over",
                 halo_mgmt::Status::Unrunnable => "Can't run anywhere",
+                halo_mgmt::Status::Degraded => "Degraded",
+                halo_mgmt::Status::SplitBrain => "Running on both hosts",
+                halo_mgmt::Status::Disabled => "Disabled",
             }
         )
     }
 }
 
 /// Prepare a capnp operation RPC request.
-/// res: The resource that the operation will be performed on.
+/// kind, parameters: The resource that the operation will be performed on.
 /// op: The operation to perform.
-fn prep_request(request: &mut OperationRequest, res: &Resource, op: ocf_resource_agent::Operation) {
+fn prep_request(
+    request: &mut OperationRequest,
+    kind: &str,
+    parameters: &HashMap<String, String>,
+    op: ocf_resource_agent::Operation,
+) {
     let mut request = request.get();
 
     request.set_op(op);
 
-    request.set_resource(res.kind.clone());
-    let mut args = request.init_args(res.parameters.len() as u32);
-    for (i, param) in res.parameters.iter().enumerate() {
+    request.set_resource(kind);
+    let mut args = request.init_args(parameters.len() as u32);
+    for (i, param) in parameters.iter().enumerate() {
         let mut arg = args.reborrow().get(i as u32);
         arg.set_key(param.0.clone());
         arg.set_value(param.1.clone());
     }
 }
 
-/// Create a capnp RPC client and set up the client to perform the operation() RPC.
-async fn get_ocf_request(
-    res: &Resource,
-    loc: Location,
-    op: ocf_resource_agent::Operation,
-) -> Result<OperationRequest, Box<dyn Error>> {
-    let hostname = match loc {
-        Location::Home => res.home_node.address(),
-        Location::Away => res
-            .failover_node
-            .as_ref()
-            .expect("Called operation on failover node for resource without failover node")
-            .address(),
-    };
-    let stream = tokio::net::TcpStream::connect(hostname).await?;
+/// Connect to the remote agent listening at `address`, returning a capnp RPC client for it along
+/// with the task running its RPC system, which finishes when the connection is lost. The
+/// connection uses TLS or mTLS if `cli` asks for it, in which case the agent's certificate is
+/// checked against `server_name`--see `tls_server_name()`.
+///
+/// If the connection, including any TLS handshake, is not established within the CLI's connect
+/// timeout, this gives up with ResourceError::Timeout, rather than waiting for the OS to give up on
+/// an unreachable host, which can take minutes.
+///
+/// This must be called from within a LocalSet, since the RPC system is spawned as a local task.
+async fn connect_to_agent(
+    address: &str,
+    server_name: Option<&str>,
+    cli: &Cli,
+) -> Result<(ocf_resource_agent::Client, RpcSystemTask), ResourceError> {
+    let connect_timeout = cli.connect_timeout();
+    let stream = tokio::time::timeout(connect_timeout, tokio::net::TcpStream::connect(address))
+        .await
+        .map_err(|_| ResourceError::Timeout)??;
     stream.set_nodelay(true)?;
 
-    if res.context.args.mtls {
-        // Create mtls connector
-        let mtls_connector = get_connector();
+    // Create TLS connector
+    let connector = match cli.tls_mode() {
+        TlsMode::None => return Ok(__connect_to_agent(stream)),
+        TlsMode::Tls => get_connector_server_only(&cli.tls_paths()),
+        TlsMode::Mtls => get_connector(&cli.tls_paths()),
+    }
+    .map_err(|e| ResourceError::Tls(e.to_string()))?;
 
-        // Set domain/hostname of server we intend to connect to
-        let domain = ServerName::try_from(
-            env::var("HALO_SERVER_DOMAIN_NAME").expect("HALO_SERVER_DOMAIN_NAME not set."),
-        )
-        .unwrap();
+    // Set domain/hostname of server we intend to connect to
+    let domain = tls_server_name(address, server_name, |name| env::var(name).ok())?;
 
-        // Perform mtls handshake
-        let mtls_stream = mtls_connector.connect(domain, stream).await?;
+    // Perform TLS handshake
+    let tls_stream = tokio::time::timeout(connect_timeout, connector.connect(domain, stream))
+        .await
+        .map_err(|_| ResourceError::Timeout)??;
 
-        __get_ocf_request(mtls_stream, res, op)
-    } else {
-        __get_ocf_request(stream, res, op)
-    }
+    Ok(__connect_to_agent(tls_stream))
+}
+
+/// Decide the name that the certificate of the agent listening at `address` must be valid for.
+/// This is the host's `server_name` from the config, if it has one; otherwise the hostname in
+/// `address`, unless that is an IP address, in which case the HALO_SERVER_DOMAIN_NAME environment
+/// variable is used, as given by `lookup_env`.
+fn tls_server_name(
+    address: &str,
+    configured: Option<&str>,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<ServerName<'static>, ResourceError> {
+    let hostname = match address.rsplit_once(':') {
+        Some((name, _port)) => name.trim_start_matches('[').trim_end_matches(']'),
+        None => address,
+    };
+
+    let name = match configured {
+        Some(name) => name.to_string(),
+        None if hostname.parse::<std::net::IpAddr>().is_err() => hostname.to_string(),
+        None => lookup_env("HALO_SERVER_DOMAIN_NAME").ok_or_else(|| {
+            ResourceError::Tls(format!(
+                "no TLS server name for host \"{hostname}\": give it a server_name in the config, or set HALO_SERVER_DOMAIN_NAME"
+            ))
+        })?,
+    };
+
+    ServerName::try_from(name.clone())
+        .map_err(|e| ResourceError::Tls(format!("invalid TLS server name \"{name}\": {e}")))
 }
 
-fn __get_ocf_request<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
+type RpcSystemTask = tokio::task::JoinHandle<Result<(), capnp::Error>>;
+
+fn __connect_to_agent<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
     stream: S,
-    res: &Resource,
-    op: ocf_resource_agent::Operation,
-) -> Result<OperationRequest, Box<dyn Error>> {
+) -> (ocf_resource_agent::Client, RpcSystemTask) {
     let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
     let rpc_network = Box::new(twoparty::VatNetwork::new(
         futures::io::BufReader::new(reader),
         futures::io::BufWriter::new(writer),
         rpc_twoparty_capnp::Side::Client,
-        Default::default(),
+        RPC_READER_OPTIONS,
     ));
     let mut rpc_system = RpcSystem::new(rpc_network, None);
     let client: ocf_resource_agent::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
 
-    tokio::task::spawn_local(rpc_system);
+    let task = tokio::task::spawn_local(rpc_system);
 
-    let mut request = client.operation_request();
-    prep_request(&mut request, res, op);
+    (client, task)
+}
 
-    Ok(request)
+/// A function run with the RPC client for a remote agent, on the thread that owns the connection
+/// to it.
+type AgentCall = Box<dyn FnOnce(ocf_resource_agent::Client) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// A connection to a remote agent, which is kept open so that it can be reused for many RPCs.
+///
+/// A capnp RPC client can't be shared between threads, so the connection is owned by a thread of
+/// its own, which makes the RPCs that it is sent. That thread exits when the connection is lost,
+/// or when every handle to the connection has been dropped.
+#[derive(Debug, Clone)]
+pub struct AgentConnection {
+    calls: mpsc::UnboundedSender<AgentCall>,
+}
+
+impl AgentConnection {
+    /// Connect to the remote agent listening at `address`, whose TLS certificate, if TLS is used,
+    /// is valid for `server_name`.
+    pub async fn open(
+        address: &str,
+        server_name: Option<&str>,
+        cli: &Cli,
+    ) -> Result<Self, ResourceError> {
+        let (calls, mut call_rx) = mpsc::unbounded_channel::<AgentCall>();
+        let (connected_tx, connected_rx) = oneshot::channel();
+        let address = address.to_string();
+        let server_name = server_name.map(|name| name.to_string());
+        let cli = cli.clone();
+
+        std::thread::spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(rt) => rt,
+                Err(e) => {
+                    let _ = connected_tx.send(Err(e.into()));
+                    return;
+                }
+            };
+
+            tokio::task::LocalSet::new().block_on(&rt, async move {
+                let (client, mut rpc_system) =
+                    match connect_to_agent(&address, server_name.as_deref(), &cli).await {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            let _ = connected_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                let _ = connected_tx.send(Ok(()));
+
+                loop {
+                    tokio::select! {
+                        call = call_rx.recv() => match call {
+                            Some(call) => {
+                                tokio::task::spawn_local(call(client.clone()));
+                            }
+                            None => break,
+                        },
+                        _ = &mut rpc_system => break,
+                    }
+                }
+            });
+        });
+
+        connected_rx.await.map_err(|_| connection_lost())??;
+
+        Ok(AgentConnection { calls })
+    }
+
+    /// Whether the connection has been lost, so that no more RPCs can be made over it.
+    pub fn is_closed(&self) -> bool {
+        self.calls.is_closed()
+    }
+
+    /// Run `f` with the RPC client for the agent, and return its result.
+    pub async fn call<T, F>(&self, f: F) -> Result<T, ResourceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(ocf_resource_agent::Client) -> LocalBoxFuture<'static, Result<T, ResourceError>>
+            + Send
+            + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let call: AgentCall = Box::new(move |client| {
+            let reply = f(client);
+            Box::pin(async move {
+                let _ = reply_tx.send(reply.await);
+            })
+        });
+
+        self.calls.send(call).map_err(|_| connection_lost())?;
+        reply_rx.await.map_err(|_| connection_lost())?
+    }
+}
+
+fn connection_lost() -> ResourceError {
+    ResourceError::Connect(std::io::Error::new(
+        std::io::ErrorKind::ConnectionReset,
+        "connection to remote agent was lost",
+    ))
 }
 
+/// Perform an operation on a resource at the given location, over the connection to the remote
+/// agent on that host.
+///
+/// An error reported by the remote agent itself is returned as ResourceError::AgentError, so that
+/// callers can tell it apart from a failure to reach the agent at all.
 pub async fn do_ocf_request(
     res: &Resource,
     loc: Location,
     op: ocf_resource_agent::Operation,
-) -> Result<OcfOperationResults, Box<dyn Error>> {
-    let request = get_ocf_request(res, loc, op).await?;
+) -> Result<ocf::Status, ResourceError> {
+    let host = match loc {
+        Location::Home => &res.home_node,
+        Location::Away => res
+            .failover_node
+            .as_ref()
+            .expect("Called operation on failover node for resource without failover node"),
+    };
+
+    let kind = res.kind.clone();
+    let mut parameters = res.parameters.clone();
+    if let Some(timeout) = res.op_timeout_for(op) {
+        parameters.insert(
+            ocf::META_TIMEOUT_KEY.to_string(),
+            timeout.as_millis().to_string(),
+        );
+    }
+    let result = host
+        .call_agent(&res.context.args, move |client| {
+            Box::pin(async move {
+                let mut request = client.operation_request();
+                prep_request(&mut request, &kind, &parameters, op);
+                let reply = request.send().promise.await?;
+                operation_status(reply)
+            })
+        })
+        .await
+        .map(|(status, stderr)| {
+            // A failing agent's diagnostic output is only worth reporting when the status is a
+            // real failure, and not e.g. "not running":
+            let failed = !matches!(status, ocf::Status::Success | ocf::Status::ErrNotRunning);
+            if failed && !stderr.is_empty() {
+                error!(
+                    "Remote agent returned error for {op:?} of resource \"{}\" on {host}: {status}: {stderr}",
+                    res.id
+                );
+            }
+            status
+        });
 
+    if res.context.args.log_rpc() {
+        let outcome = match &result {
+            Ok(status) => format!("{status:?}"),
+            Err(e) => format!("error: {e}"),
+        };
+        res.context.log_activity(
+            &format!(
+                "RPC {op:?} of resource {} on {host}: {outcome}",
+                res.params_string()
+            ),
+            true,
+        );
+    }
+
+    result
+}
+
+/// Interpret the reply to an operation() RPC, returning the status together with whatever the
+/// resource agent wrote to stderr.
+fn operation_status(reply: OcfOperationResults) -> Result<(ocf::Status, String), ResourceError> {
+    let status = reply.get()?.get_result()?;
+    match status.which() {
+        Ok(ocf_resource_agent::result::Ok(st)) => {
+            let stderr = match status.has_stderr() {
+                true => status.get_stderr()?.to_str()?.to_string(),
+                false => String::new(),
+            };
+            Ok((st.into(), stderr))
+        }
+        Ok(ocf_resource_agent::result::Err(e)) => {
+            let err_str = e?.to_str()?;
+            error!("Remote agent returned error: {err_str}");
+            Err(ResourceError::AgentError(err_str.to_string()))
+        }
+        Err(e) => Err(ResourceError::Rpc(e.into())),
+    }
+}
+
+/// The resources found running on a host by the remote agent's discover() RPC.
+pub struct DiscoveredResources {
+    /// The names of the imported zpools.
+    pub zpools: Vec<String>,
+
+    /// The lines of `mount -t lustre` output, one for each mounted lustre target.
+    pub lustre_mounts: Vec<String>,
+}
+
+/// Ask the remote agent listening at `address` which resources are running on its host.
+///
+/// This must be called from within a LocalSet, since the RPC system is spawned as a local task.
+pub async fn do_discover_request(
+    address: &str,
+    cli: &Cli,
+) -> Result<DiscoveredResources, ResourceError> {
+    let (client, _) = connect_to_agent(address, None, cli).await?;
+
+    let reply = client.discover_request().send().promise.await?;
+    let reply = reply.get()?;
+
+    Ok(DiscoveredResources {
+        zpools: text_list_to_strings(reply.get_zpools()?)?,
+        lustre_mounts: text_list_to_strings(reply.get_lustre_mounts()?)?,
+    })
+}
+
+/// Ask the remote agent listening at `address` for the OCF meta-data of the resource agent `kind`,
+/// returning the meta-data XML.
+pub async fn do_meta_data_request(
+    address: &str,
+    kind: &str,
+    cli: &Cli,
+) -> Result<String, ResourceError> {
+    let (client, _) = connect_to_agent(address, None, cli).await?;
+
+    let mut request = client.operation_request();
+    prep_request(
+        &mut request,
+        kind,
+        &HashMap::new(),
+        ocf_resource_agent::Operation::MetaData,
+    );
     let reply = request.send().promise.await?;
-    Ok(reply)
+    let result = reply.get()?.get_result()?;
+    match result.which() {
+        Ok(ocf_resource_agent::result::Ok(0)) => Ok(result.get_stdout()?.to_str()?.to_string()),
+        Ok(ocf_resource_agent::result::Ok(st)) => {
+            let stderr = result.get_stderr()?.to_str()?;
+            Err(ResourceError::AgentError(format!(
+                "meta-data action failed with {}: {stderr}",
+                ocf::Status::from(st)
+            )))
+        }
+        Ok(ocf_resource_agent::result::Err(e)) => {
+            Err(ResourceError::AgentError(e?.to_str()?.to_string()))
+        }
+        Err(e) => Err(ResourceError::Rpc(e.into())),
+    }
+}
+
+fn text_list_to_strings(list: capnp::text_list::Reader) -> Result<Vec<String>, ResourceError> {
+    let mut strings = Vec::new();
+    for text in list.iter() {
+        strings.push(text?.to_str()?.to_string());
+    }
+    Ok(strings)
+}
+
+/// What a remote agent reports about itself in reply to the ping() or info() RPCs.
+pub struct AgentInfo {
+    /// How long the agent has been running.
+    pub uptime: Duration,
+
+    /// The version of the agent.
+    pub version: String,
+
+    /// The directory that holds the agent's resource agent scripts. This is only reported by the
+    /// info() RPC.
+    pub ocf_root: Option<String>,
+}
+
+/// Check that the remote agent on `host` is reachable, over the connection to it.
+pub async fn do_ping_request(host: &Host, cli: &Cli) -> Result<AgentInfo, ResourceError> {
+    host.call_agent(cli, |client| {
+        Box::pin(async move {
+            let reply = client.ping_request().send().promise.await?;
+            let reply = reply.get()?;
+
+            Ok(AgentInfo {
+                uptime: Duration::from_secs(reply.get_uptime()),
+                version: reply.get_version()?.to_str()?.to_string(),
+                ocf_root: None,
+            })
+        })
+    })
+    .await
+}
+
+/// Ask the remote agent on `host` to describe itself, over the connection to it.
+pub async fn do_info_request(host: &Host, cli: &Cli) -> Result<AgentInfo, ResourceError> {
+    host.call_agent(cli, |client| {
+        Box::pin(async move {
+            let reply = client.info_request().send().promise.await?;
+            let reply = reply.get()?;
+
+            Ok(AgentInfo {
+                uptime: Duration::from_secs(reply.get_uptime_secs()),
+                version: reply.get_version()?.to_str()?.to_string(),
+                ocf_root: Some(reply.get_ocf_root()?.to_str()?.to_string()),
+            })
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use resource::ResourceStatus;
+
+    #[test]
+    fn server_names() {
+        let env = |_: &str| Some("from-env.example.com".to_string());
+        let no_env = |_: &str| None;
+        let name = |name: &'static str| ServerName::try_from(name).unwrap();
+
+        assert_eq!(
+            tls_server_name("mds00:8000", Some("mds00.example.com"), env).unwrap(),
+            name("mds00.example.com")
+        );
+        assert_eq!(
+            tls_server_name("mds00:8000", None, env).unwrap(),
+            name("mds00")
+        );
+        assert_eq!(
+            tls_server_name("127.0.0.1:8000", None, env).unwrap(),
+            name("from-env.example.com")
+        );
+
+        // With an IP address for a hostname, and no server name in the config or the environment,
+        // there is nothing to check the certificate against:
+        let Err(ResourceError::Tls(message)) = tls_server_name("[::1]:8000", None, no_env) else {
+            panic!("expected a TLS error");
+        };
+        assert!(message.contains("server_name"));
+    }
+
+    #[test]
+    fn connect_timeout() {
+        let cli = Cli {
+            connect_timeout: 0.5,
+            ..Default::default()
+        };
+
+        // Nothing listens at these: the first refuses the connection straight away, while the
+        // second, an address reserved for documentation, typically never answers at all.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        for address in [closed.as_str(), "192.0.2.1:8000"] {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let start = std::time::Instant::now();
+            let result = rt.block_on(AgentConnection::open(address, None, &cli));
+            assert!(result.unwrap_err().is_connection_error());
+            assert!(start.elapsed() < Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn status_round_trip() {
+        let statuses = [
+            (ResourceStatus::SplitBrain, "Running on both hosts"),
+            (ResourceStatus::Unknown, "Unknown"),
+            (ResourceStatus::Unrunnable, "Can't run anywhere"),
+            (ResourceStatus::Stopped, "Stopped"),
+            (ResourceStatus::CheckingAway, "Checking on failover"),
+            (ResourceStatus::CheckingHome, "Checking on home"),
+            (ResourceStatus::Degraded, "Degraded"),
+            (ResourceStatus::RunningOnAway, "Failed over"),
+            (ResourceStatus::RunningOnHome, "Home"),
+            (ResourceStatus::Disabled, "Disabled"),
+        ];
+
+        for (status, name) in statuses {
+            let mut message = capnp::message::Builder::new_default();
+            let mut res = message.init_root::<halo_mgmt::resource::Builder>();
+            res.set_status(status.into());
+
+            let res = message
+                .get_root_as_reader::<halo_mgmt::resource::Reader>()
+                .unwrap();
+            assert_eq!(res.get_status().unwrap().to_string(), name);
+        }
+    }
 }
@@ -5,12 +5,22 @@ use std::{
     collections::HashMap,
     error::Error,
     fmt,
-    io::{Read, Write},
+    io::Write,
     process::{Command, Stdio},
     sync::Mutex,
+    time::Duration,
 };
 
-use clap::ValueEnum;
+use {
+    clap::ValueEnum,
+    futures::future::LocalBoxFuture,
+    log::{debug, warn},
+};
+
+use crate::{config::FenceRetry, halo_capnp::AgentConnection, resource::ResourceError};
+
+/// How long to wait for a remote agent to reply to a ping before considering it unreachable.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone)]
 struct HostAddress {
@@ -30,11 +40,36 @@ pub enum HostStatus {
 pub struct Host {
     address: HostAddress,
     status: Mutex<HostStatus>,
-    fence_agent: Option<FenceAgent>,
+
+    /// Whether this host is in maintenance mode, during which the manager only reports the status
+    /// of its resources, and never starts, stops, or fences anything on it.
+    maintenance: Mutex<bool>,
+
+    /// Whether this host has been powered off or rebooted since its remote agent last answered a
+    /// ping, so that it isn't fenced again, for the sake of another resource group, while it comes
+    /// back.
+    fenced: Mutex<bool>,
+
+    /// The fence agents for this host, in the order they are tried when fencing.
+    fence_agents: Vec<FenceAgent>,
+
+    /// How to retry a failed fence action with each of the fence agents, in the same order.
+    fence_retries: Vec<FenceRetry>,
+
+    /// The connection to the remote agent on this host, which is opened when it is first needed
+    /// and then reused. This is locked while connecting, so that concurrent RPCs don't each open
+    /// a connection of their own.
+    connection: tokio::sync::Mutex<Option<AgentConnection>>,
+
+    /// In a dry run, fencing this host only prints what would be done.
+    dry_run: bool,
+
+    /// The name that the remote agent's TLS certificate must be valid for, if not the hostname.
+    server_name: Option<String>,
 }
 
 impl Host {
-    pub fn new(name: &str, port: Option<u16>, fence_agent: Option<FenceAgent>) -> Self {
+    pub fn new(name: &str, port: Option<u16>, fence_agents: Vec<FenceAgent>) -> Self {
         Host {
             address: HostAddress {
                 name: name.to_string(),
@@ -44,98 +79,332 @@ impl Host {
                 },
             },
             status: Mutex::new(HostStatus::Unknown),
-            fence_agent,
+            maintenance: Mutex::new(false),
+            fenced: Mutex::new(false),
+            fence_retries: vec![FenceRetry::default(); fence_agents.len()],
+            fence_agents,
+            connection: tokio::sync::Mutex::new(None),
+            dry_run: false,
+            server_name: None,
         }
     }
 
-    /// Create a Host object from a given config::Host object.
-    pub fn from_config(config: &crate::config::Host) -> Self {
-        let (name, port) = Self::get_host_port(&config.hostname);
-        let fence_agent = config
-            .fence_agent
-            .as_ref()
-            .map(|agent| FenceAgent::from_params(agent, &config.fence_parameters));
-        Host::new(name, port, fence_agent)
+    /// Make fencing this host only print what would be done, rather than doing it.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Create a Host object from a given config::Host object, using `defaults` for any fence
+    /// settings that the host doesn't give.
+    pub fn from_config(
+        config: &crate::config::Host,
+        defaults: &crate::config::Defaults,
+    ) -> Result<Self, HostAddressError> {
+        let (name, port) = Self::get_host_port(&config.hostname)?;
+        let (fence_agent, fence_parameters) = config.fence_settings(defaults);
+        let mut fence_agents: Vec<FenceAgent> = fence_agent
+            .iter()
+            .map(|agent| FenceAgent::from_params(agent, &fence_parameters))
+            .collect();
+        fence_agents.extend(
+            config
+                .fence_agents
+                .iter()
+                .flatten()
+                .map(|spec| FenceAgent::from_params(&spec.agent, &spec.parameters)),
+        );
+        let default_retry = defaults.fence_retry.unwrap_or_default();
+        let mut fence_retries: Vec<FenceRetry> = fence_agent
+            .iter()
+            .map(|_| config.fence_retry.unwrap_or(default_retry))
+            .collect();
+        fence_retries.extend(
+            config
+                .fence_agents
+                .iter()
+                .flatten()
+                .map(|spec| spec.retry.unwrap_or(default_retry)),
+        );
+
+        let mut host = Host::new(name, port, fence_agents);
+        host.server_name = config.server_name.clone();
+        host.fence_retries = fence_retries;
+        Ok(host)
     }
 
-    /// Given a string that may be of the form "<address>:port number>", split it out into the address
-    /// and port number portions.
-    fn get_host_port(host_str: &str) -> (&str, Option<u16>) {
-        let mut split = host_str.split(':');
-        let host = split.nth(0).unwrap();
-        let port = split.nth(0).map(|port| port.parse::<u16>().unwrap());
-        (host, port)
+    /// Create a Host object with no fence agent from an address of the form
+    /// "<address>[:<port number>]".
+    pub fn from_address(address: &str) -> Result<Self, HostAddressError> {
+        let (name, port) = Self::get_host_port(address)?;
+        Ok(Host::new(name, port, Vec::new()))
     }
 
-    /// Attempt to power on or off this host.
+    /// Given a string that may be of the form "<address>:<port number>", split it out into the
+    /// address and port number portions.
     ///
-    /// If self.fence_agent is not set, then panics.
+    /// IPv6 addresses must be enclosed in brackets when a port is given, e.g. "[fe80::1]:8000".
+    /// A bare IPv6 address such as "fe80::1" is taken to have no port.
+    fn get_host_port(host_str: &str) -> Result<(&str, Option<u16>), HostAddressError> {
+        let err = |reason: &str| HostAddressError {
+            address: host_str.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (host, port) = if let Some(rest) = host_str.strip_prefix('[') {
+            let Some((host, rest)) = rest.split_once(']') else {
+                return Err(err("missing closing bracket"));
+            };
+            if rest.is_empty() {
+                (host, None)
+            } else if let Some(port) = rest.strip_prefix(':') {
+                (host, Some(port))
+            } else {
+                return Err(err("unexpected characters after closing bracket"));
+            }
+        } else if host_str.matches(':').count() > 1 {
+            (host_str, None)
+        } else {
+            match host_str.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_str, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(err("missing address"));
+        }
+
+        let port = match port {
+            Some(port) => Some(
+                port.parse::<u16>()
+                    .map_err(|_| err(&format!("invalid port number \"{port}\"")))?,
+            ),
+            None => None,
+        };
+
+        Ok((host, port))
+    }
+
+    /// Attempt to power on, power off, or reboot this host.
+    ///
+    /// Each of the host's fence agents is tried in order until one succeeds. A fence agent which
+    /// fails is retried as its FenceRetry allows before moving on to the next one. If they all
+    /// fail, the last error from each of them is returned.
+    ///
+    /// If the host has no fence agents, then panics.
     pub fn do_fence(&self, command: FenceCommand) -> Result<(), Box<dyn Error>> {
-        let agent = self.fence_agent.as_ref().unwrap();
+        self.check_fence_agents();
 
         if matches!(command, FenceCommand::Status) {
             panic!("Please use is_powered_on() for power status.");
         }
 
-        let mut child = Command::new(agent.get_executable())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
+        if self.dry_run {
+            self.print_planned_fence(command);
+            return Ok(());
+        }
+
+        let mut errors = FenceAgentsError::new();
+        for (agent, retry) in self.fence_agents.iter().zip(self.fence_retries.iter()) {
+            for attempt in 1.. {
+                let error = match run_fence_agent(agent, &self.address.name, command)
+                    .and_then(|output| self.fence_result(command, output))
+                {
+                    Ok(()) => return Ok(()),
+                    Err(e) => e,
+                };
+                match self.retry_failed_fence(agent, command, attempt, retry, error, &mut errors) {
+                    Some(delay) => std::thread::sleep(delay),
+                    None => break,
+                }
+            }
+        }
+
+        Err(Box::new(errors))
+    }
 
-        let command_bytes = agent.generate_command_bytes(&self.address.name, command);
+    /// Attempt to power on, power off, or reboot this host without blocking the async runtime.
+    ///
+    /// If the host has no fence agents, then panics.
+    pub async fn do_fence_async(&self, command: FenceCommand) -> Result<(), Box<dyn Error>> {
+        self.check_fence_agents();
 
-        child
-            .stdin
-            .as_mut()
-            .expect("stdin should have been captured")
-            .write_all(&command_bytes)?;
-        let status = child.wait()?;
+        if matches!(command, FenceCommand::Status) {
+            panic!("Please use is_powered_on_async() for power status.");
+        }
 
-        let mut out = String::new();
-        child.stdout.unwrap().read_to_string(&mut out)?;
-        eprintln!("out: {out}");
+        if self.dry_run {
+            self.print_planned_fence(command);
+            return Ok(());
+        }
 
-        if status.success() {
-            Ok(())
-        } else {
-            Err(Box::new(FenceError {}))
+        let mut errors = FenceAgentsError::new();
+        for (agent, retry) in self.fence_agents.iter().zip(self.fence_retries.iter()) {
+            for attempt in 1.. {
+                let error = match run_fence_agent_async(agent, &self.address.name, command)
+                    .await
+                    .and_then(|output| self.fence_result(command, output))
+                {
+                    Ok(()) => return Ok(()),
+                    Err(e) => e,
+                };
+                match self.retry_failed_fence(agent, command, attempt, retry, error, &mut errors) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => break,
+                }
+            }
+        }
+
+        Err(Box::new(errors))
+    }
+
+    /// Log an attempt at a fence action which failed, and decide whether to try again with the
+    /// same fence agent: returns how long to wait before the next attempt, or None once the fence
+    /// agent has used up its attempts, in which case its error is added to `errors`.
+    ///
+    /// This is shared by do_fence() and do_fence_async(), which differ only in how they run the
+    /// fence agent and wait.
+    fn retry_failed_fence(
+        &self,
+        agent: &FenceAgent,
+        command: FenceCommand,
+        attempt: u32,
+        retry: &FenceRetry,
+        error: Box<dyn Error>,
+        errors: &mut FenceAgentsError,
+    ) -> Option<Duration> {
+        warn!(
+            "Attempt {attempt} of {} to fence host {self} ({command}) using fence agent {agent} failed: {error}",
+            retry.attempts
+        );
+        if attempt >= retry.attempts {
+            errors.push(agent, error);
+            return None;
+        }
+        Some(Duration::from_millis(retry.delay_ms))
+    }
+
+    /// For a dry run, print the fence action that would be taken on this host.
+    fn print_planned_fence(&self, command: FenceCommand) {
+        match self.fence_agents.first() {
+            Some(agent) => {
+                println!("Would fence host {self} ({command}) using fence agent {agent}")
+            }
+            None => println!("Would fence host {self} ({command}), but no fence agent configured"),
         }
     }
 
     /// Attempt to check this host's power status.
     ///
-    /// If self.fence_agent is not set, then panics.
+    /// The answer comes from the first of the host's fence agents that is able to give one.
+    ///
+    /// If the host has no fence agents, then panics.
     pub fn is_powered_on(&self) -> Result<bool, Box<dyn Error>> {
-        let agent = self.fence_agent.as_ref().unwrap();
+        self.check_fence_agents();
+
+        let mut errors = FenceAgentsError::new();
+        for agent in self.fence_agents.iter() {
+            let result = run_fence_agent(agent, &self.address.name, FenceCommand::Status)
+                .and_then(parse_power_status);
+            match result {
+                Ok(status) => return Ok(status == PowerStatus::On),
+                Err(e) => errors.push(agent, e),
+            }
+        }
+
+        Err(Box::new(errors))
+    }
 
-        let mut child = Command::new(agent.get_executable())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
+    /// Attempt to check this host's power status without blocking the async runtime.
+    ///
+    /// If the host has no fence agents, then panics.
+    pub async fn is_powered_on_async(&self) -> Result<bool, Box<dyn Error>> {
+        self.check_fence_agents();
 
-        let command_bytes = agent.generate_command_bytes(&self.address.name, FenceCommand::Status);
+        let mut errors = FenceAgentsError::new();
+        for agent in self.fence_agents.iter() {
+            let result = match run_fence_agent_async(
+                agent,
+                &self.address.name,
+                FenceCommand::Status,
+            )
+            .await
+            {
+                Ok(output) => parse_power_status(output),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(status) => return Ok(status == PowerStatus::On),
+                Err(e) => errors.push(agent, e),
+            }
+        }
 
-        child
-            .stdin
-            .as_mut()
-            .expect("stdin should have been captured")
-            .write_all(&command_bytes)?;
-        let status = child.wait()?;
+        Err(Box::new(errors))
+    }
 
-        if !status.success() {
-            return Err(Box::new(FenceError {}));
+    fn check_fence_agents(&self) {
+        if self.fence_agents.is_empty() {
+            panic!("Host {} has no fence agents.", self.name());
         }
+    }
 
-        let mut out = String::new();
-        child.stdout.unwrap().read_to_string(&mut out)?;
+    /// Check whether the remote agent on this host can be reached, without running any resource
+    /// agent on it. Once it answers, the host is no longer taken to have been fenced.
+    pub async fn is_agent_reachable(&self, cli: &crate::commands::Cli) -> bool {
+        let ping = crate::halo_capnp::do_ping_request(self, cli);
 
-        if out.contains("is ON") {
-            Ok(true)
-        } else if out.contains("is OFF") {
-            Ok(false)
-        } else {
-            Err(Box::new(FenceError {}))
+        match tokio::time::timeout(PING_TIMEOUT, ping).await {
+            Ok(Ok(_)) => {
+                *self.fenced.lock().unwrap() = false;
+                true
+            }
+            Ok(Err(_)) => false,
+            Err(_) => {
+                // The connection may be stuck, so start afresh next time:
+                *self.connection.lock().await = None;
+                false
+            }
+        }
+    }
+
+    /// Run `f` with an RPC client for the remote agent on this host, reusing the open connection
+    /// to the agent if there is one, and connecting otherwise.
+    ///
+    /// If the RPC fails for any reason other than an error reported by the agent, the connection
+    /// is dropped, so that the next RPC reconnects.
+    pub async fn call_agent<T, F>(
+        &self,
+        cli: &crate::commands::Cli,
+        f: F,
+    ) -> Result<T, ResourceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(
+                crate::halo_capnp::ocf_resource_agent::Client,
+            ) -> LocalBoxFuture<'static, Result<T, ResourceError>>
+            + Send
+            + 'static,
+    {
+        let mut connection = self.connection.lock().await;
+        let open = match connection.as_ref() {
+            Some(c) if !c.is_closed() => c.clone(),
+            _ => {
+                let c = AgentConnection::open(&self.address(), self.server_name.as_deref(), cli)
+                    .await?;
+                *connection = Some(c.clone());
+                c
+            }
+        };
+        // Don't hold the lock during the RPC, so that other RPCs can use the connection too:
+        std::mem::drop(connection);
+
+        let result = open.call(f).await;
+        if let Err(ref e) = result {
+            if !matches!(e, ResourceError::AgentError(_)) {
+                *self.connection.lock().await = None;
+            }
         }
+        result
     }
 
     pub fn get_status(&self) -> HostStatus {
@@ -143,14 +412,74 @@ impl Host {
     }
 
     pub fn set_status(&self, status: HostStatus) {
-        if status == HostStatus::Down {
-            panic!("Down status for host is not possible yet. (Requires fencing.)");
-        };
         *self.status.lock().unwrap() = status;
     }
 
-    pub fn fence_agent(&self) -> &Option<FenceAgent> {
-        &self.fence_agent
+    /// Take on the known state of `old`, the same host in a cluster whose config has since been
+    /// reloaded, including its open connection to the remote agent.
+    pub fn adopt_state(&self, old: &Host) {
+        self.set_status(old.get_status());
+        self.set_maintenance(old.in_maintenance());
+        *self.fenced.lock().unwrap() = old.was_fenced();
+        if let (Ok(mut connection), Ok(old_connection)) =
+            (self.connection.try_lock(), old.connection.try_lock())
+        {
+            *connection = old_connection.clone();
+        }
+    }
+
+    pub fn in_maintenance(&self) -> bool {
+        *self.maintenance.lock().unwrap()
+    }
+
+    pub fn set_maintenance(&self, on: bool) {
+        *self.maintenance.lock().unwrap() = on;
+    }
+
+    /// Whether this host has been powered off or rebooted, and its remote agent hasn't answered a
+    /// ping since.
+    pub fn was_fenced(&self) -> bool {
+        *self.fenced.lock().unwrap()
+    }
+
+    /// Interpret the outcome of a fence action (on, off, or reboot), updating this host's status if
+    /// the action succeeded.
+    ///
+    /// A host that was powered off is known to be Down. A host that was powered on or rebooted is
+    /// not known to be Up until it has been successfully monitored, so its status becomes Unknown.
+    fn fence_result(
+        &self,
+        command: FenceCommand,
+        output: FenceOutput,
+    ) -> Result<(), Box<dyn Error>> {
+        debug!("fence agent output: {}", output.stdout);
+
+        if output.code != Some(0) {
+            return Err(Box::new(FenceError::from_output(output)));
+        }
+
+        match command {
+            FenceCommand::Off => self.set_status(HostStatus::Down),
+            FenceCommand::On | FenceCommand::Reboot => self.set_status(HostStatus::Unknown),
+            FenceCommand::Status => {}
+        };
+        if matches!(command, FenceCommand::Off | FenceCommand::Reboot) {
+            *self.fenced.lock().unwrap() = true;
+        }
+
+        Ok(())
+    }
+
+    pub fn fence_agents(&self) -> &[FenceAgent] {
+        &self.fence_agents
+    }
+
+    /// In the test environment, get the arguments of the host's test fence agent.
+    fn test_fence_args(&self) -> Option<&TestFenceArgs> {
+        self.fence_agents.iter().find_map(|agent| match agent {
+            FenceAgent::Test(test_args) => Some(test_args),
+            _ => None,
+        })
     }
 
     pub fn name(&self) -> &str {
@@ -162,14 +491,19 @@ impl Host {
     }
 
     pub fn address(&self) -> String {
-        format!("{}:{}", self.name(), self.port())
+        // IPv6 literals need to be bracketed to be distinguished from the port:
+        if self.name().contains(':') {
+            format!("[{}]:{}", self.name(), self.port())
+        } else {
+            format!("{}:{}", self.name(), self.port())
+        }
     }
 
     /// Get a unique identifier for this host. Typically, this will just be the hostname, but in
     /// the test environment, where Hosts do not have a unique hostname, the fencing target is used
     /// instead as a unique ID.
     pub fn id(&self) -> String {
-        if let Some(FenceAgent::Test(test_args)) = &self.fence_agent {
+        if let Some(test_args) = self.test_fence_args() {
             test_args.target.to_string()
         } else {
             self.name().to_string()
@@ -182,7 +516,7 @@ impl fmt::Display for Host {
         // In the test environment, a Host is more usefully identified via its "target" name which
         // is defined in its Fence Agent parameters. Otherwise, in a real environment, just use the
         // hostname.
-        if let Some(FenceAgent::Test(test_args)) = &self.fence_agent {
+        if let Some(test_args) = self.test_fence_args() {
             write!(f, "{} ({}:{})", test_args.target, self.name(), self.port())
         } else {
             write!(f, "{}", self.name())
@@ -190,22 +524,202 @@ impl fmt::Display for Host {
     }
 }
 
+/// What a fence agent reported when it was run.
+#[derive(Debug)]
+struct FenceOutput {
+    /// The exit code, or None if the fence agent was killed by a signal.
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+impl From<std::process::Output> for FenceOutput {
+    fn from(output: std::process::Output) -> Self {
+        FenceOutput {
+            code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+}
+
+/// Run the fence agent to perform `command` on the host `host_id`.
+fn run_fence_agent(
+    agent: &FenceAgent,
+    host_id: &str,
+    command: FenceCommand,
+) -> Result<FenceOutput, Box<dyn Error>> {
+    let mut child = Command::new(agent.get_executable())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let command_bytes = agent.generate_command_bytes(host_id, command);
+
+    // The fence agent reads its arguments until EOF, so stdin must be closed after writing:
+    child
+        .stdin
+        .take()
+        .expect("stdin should have been captured")
+        .write_all(&command_bytes)?;
+    let output = child.wait_with_output()?;
+
+    Ok(output.into())
+}
+
+/// Async version of run_fence_agent(), for use from within the manager's runtime.
+async fn run_fence_agent_async(
+    agent: &FenceAgent,
+    host_id: &str,
+    command: FenceCommand,
+) -> Result<FenceOutput, Box<dyn Error>> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut child = tokio::process::Command::new(agent.get_executable())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let command_bytes = agent.generate_command_bytes(host_id, command);
+
+    let mut stdin = child.stdin.take().expect("stdin should have been captured");
+    stdin.write_all(&command_bytes).await?;
+    std::mem::drop(stdin);
+    let output = child.wait_with_output().await?;
+
+    Ok(output.into())
+}
+
+/// The power state of a host, as reported by a fence agent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerStatus {
+    On,
+    Off,
+}
+
+/// Interpret the outcome of a power status check, given the fence agent's exit code and output.
+///
+/// By the fence agent conventions, the status action exits with 0 if the host is on, 2 if it is
+/// off, and anything else on an error. Some agents, though, exit with 0 either way and only say
+/// which it is in their output, so when the exit code is 0, the output is checked for a status as
+/// well--see parse_power_status_text().
+fn parse_power_status(output: FenceOutput) -> Result<PowerStatus, Box<dyn Error>> {
+    match output.code {
+        Some(0) => Ok(parse_power_status_text(&output.stdout).unwrap_or(PowerStatus::On)),
+        Some(2) => Ok(PowerStatus::Off),
+        _ => Err(Box::new(FenceError::from_output(output))),
+    }
+}
+
+/// Look for a power status in the output of a fence agent, in any of the forms that fence agents
+/// give it in, such as "Status: ON", "mds00 is OFF", or "Chassis Power is on".
+fn parse_power_status_text(out: &str) -> Option<PowerStatus> {
+    out.lines().find_map(|line| {
+        let line = line.trim().to_ascii_lowercase();
+        let status = match line.strip_prefix("status:") {
+            Some(rest) => rest.trim(),
+            None => line.rsplit_once(" is ").map_or(line.as_str(), |(_, s)| s),
+        };
+        match status.trim_end_matches('.') {
+            "on" => Some(PowerStatus::On),
+            "off" => Some(PowerStatus::Off),
+            _ => None,
+        }
+    })
+}
+
+/// An error in the address of a host given in the config.
 #[derive(Debug)]
-pub struct FenceError {}
+pub struct HostAddressError {
+    address: String,
+    reason: String,
+}
+
+impl fmt::Display for HostAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid host address \"{}\": {}",
+            self.address, self.reason
+        )
+    }
+}
+
+impl Error for HostAddressError {}
+
+/// A fence agent which ran but reported failure, with what it said about why.
+#[derive(Debug)]
+pub struct FenceError {
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+impl FenceError {
+    fn from_output(output: FenceOutput) -> Self {
+        FenceError {
+            code: output.code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }
+    }
+}
 
 impl fmt::Display for FenceError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "fencing failed")
+        match self.code {
+            Some(code) => write!(f, "fencing failed with exit code {code}")?,
+            None => write!(f, "fencing failed: the fence agent was killed by a signal")?,
+        }
+        for (name, out) in [("output", &self.stdout), ("error output", &self.stderr)] {
+            let out = out.trim();
+            if !out.is_empty() {
+                write!(f, "; {name}: \"{out}\"")?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl Error for FenceError {}
 
+/// The errors from each of a host's fence agents, when none of them succeeded.
+#[derive(Debug)]
+pub struct FenceAgentsError {
+    errors: Vec<(String, Box<dyn Error>)>,
+}
+
+impl FenceAgentsError {
+    fn new() -> Self {
+        Self { errors: Vec::new() }
+    }
+
+    fn push(&mut self, agent: &FenceAgent, error: Box<dyn Error>) {
+        self.errors.push((agent.get_executable(), error));
+    }
+}
+
+impl fmt::Display for FenceAgentsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "all fence agents failed")?;
+        for (agent, error) in self.errors.iter() {
+            write!(f, "; {agent}: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for FenceAgentsError {}
+
 /// The supported fence actions.
 #[derive(ValueEnum, Debug, Clone, Copy)]
 pub enum FenceCommand {
     On,
     Off,
+    /// Power cycle the host. All of the supported fence agents implement this natively.
+    Reboot,
     Status,
 }
 
@@ -214,6 +728,7 @@ impl fmt::Display for FenceCommand {
         match self {
             FenceCommand::On => write!(f, "on"),
             FenceCommand::Off => write!(f, "off"),
+            FenceCommand::Reboot => write!(f, "reboot"),
             FenceCommand::Status => write!(f, "status"),
         }
     }
@@ -222,8 +737,11 @@ impl fmt::Display for FenceCommand {
 /// The list of supported fence agents.
 #[derive(Debug, Clone)]
 pub enum FenceAgent {
-    Powerman,
+    Powerman(PowermanArgs),
     Redfish(RedfishArgs),
+    Ipmi(IpmiArgs),
+    Virsh(VirshArgs),
+    Apc(ApcArgs),
     Test(TestFenceArgs),
 }
 
@@ -236,10 +754,16 @@ impl FenceAgent {
     /// If the given parameters are not valid for the given agent, this panics rather than try to
     /// run with an unusable fence agent. Note that the parameters are not required for powerman,
     /// since the hostname is the only needed parameter, and that is already stored on the Host
-    /// object. However, the other fence agents need additional parameters.
+    /// object; the address of the powerman daemon may optionally be given. However, the other
+    /// fence agents need additional parameters.
     pub fn from_params(agent: &str, params: &Option<HashMap<String, String>>) -> Self {
         if agent == "powerman" {
-            return Self::Powerman;
+            let ipaddr = params.as_ref().and_then(|p| p.get("ipaddr")).cloned();
+            let port = params.as_ref().and_then(|p| p.get("port")).map(|port| {
+                port.parse::<u16>()
+                    .expect("Powerman port parameter must be a valid port number")
+            });
+            return Self::Powerman(PowermanArgs::new(ipaddr, port));
         }
 
         let params = params
@@ -256,6 +780,57 @@ impl FenceAgent {
                 };
                 Self::Redfish(RedfishArgs::new(user.to_string(), pass.to_string()))
             }
+            "fence_ipmilan" => {
+                let Some(user) = params.get("username") else {
+                    panic!("IPMI username needed but not in config parameters");
+                };
+                let Some(pass) = params.get("password") else {
+                    panic!("IPMI password needed but not in config parameters");
+                };
+                let lanplus = match params.get("lanplus").map(|s| s.as_str()) {
+                    None | Some("false") | Some("0") => false,
+                    Some("true") | Some("1") => true,
+                    Some(other) => panic!("Invalid value for IPMI lanplus parameter: \"{other}\""),
+                };
+                let port = params.get("port").map(|port| {
+                    port.parse::<u16>()
+                        .expect("IPMI port parameter must be a valid port number")
+                });
+                Self::Ipmi(IpmiArgs::new(
+                    user.to_string(),
+                    pass.to_string(),
+                    lanplus,
+                    port,
+                ))
+            }
+            "fence_virsh" => {
+                let Some(ipaddr) = params.get("ipaddr") else {
+                    panic!("Virsh hypervisor address (ipaddr) needed but not in config parameters");
+                };
+                Self::Virsh(VirshArgs::new(
+                    ipaddr.to_string(),
+                    params.get("vm").cloned(),
+                    params.get("login").cloned(),
+                    params.get("identity_file").cloned(),
+                ))
+            }
+            "fence_apc_snmp" => {
+                let Some(ipaddr) = params.get("ipaddr") else {
+                    panic!("APC PDU address (ipaddr) needed but not in config parameters");
+                };
+                let Some(port) = params.get("port") else {
+                    panic!("APC PDU outlet number (port) needed but not in config parameters");
+                };
+                let port = port
+                    .parse::<u32>()
+                    .expect("APC PDU port parameter must be a valid outlet number");
+                Self::Apc(ApcArgs::new(
+                    ipaddr.to_string(),
+                    port,
+                    params.get("community").cloned(),
+                    params.get("snmp_version").cloned(),
+                ))
+            }
             "fence_test" => {
                 let Some(args) = TestFenceArgs::new(params) else {
                     panic!("Test fence agent is missing needed parameters");
@@ -269,11 +844,14 @@ impl FenceAgent {
     }
 
     /// Gets the name of the executable file used for a given fence agent.
-    fn get_executable(&self) -> &str {
+    fn get_executable(&self) -> String {
         match self {
-            FenceAgent::Powerman => "fence_powerman",
-            FenceAgent::Redfish(_) => "fence_redfish",
-            FenceAgent::Test(_) => "tests/fence_test",
+            FenceAgent::Powerman(_) => "fence_powerman".to_string(),
+            FenceAgent::Redfish(_) => "fence_redfish".to_string(),
+            FenceAgent::Ipmi(_) => "fence_ipmilan".to_string(),
+            FenceAgent::Virsh(_) => "fence_virsh".to_string(),
+            FenceAgent::Apc(_) => "fence_apc_snmp".to_string(),
+            FenceAgent::Test(_) => crate::test_fence_agent_path(),
         }
     }
 
@@ -281,17 +859,77 @@ impl FenceAgent {
     /// send to a fence agent to do a fence action on the given host.
     fn generate_command_bytes(&self, host_id: &str, command: FenceCommand) -> Vec<u8> {
         let args = match self {
-            FenceAgent::Powerman => {
-                format!("ipaddr=localhost\naction={0}\nplug={1}\n", command, host_id)
+            FenceAgent::Powerman(powerman_args) => {
+                let mut args = format!(
+                    "ipaddr={0}\naction={1}\nplug={2}\n",
+                    powerman_args.ipaddr.as_deref().unwrap_or(host_id),
+                    command,
+                    host_id
+                );
+                if let Some(port) = powerman_args.port {
+                    args.push_str(&format!("ipport={port}\n"));
+                }
+                args
             }
             FenceAgent::Redfish(redfish_args) => format!(
                 "ipaddr={0}\naction={1}\nusername={2}\npassword={3}\nssl-insecure=true",
                 host_id, command, redfish_args.username, redfish_args.password,
             ),
-            FenceAgent::Test(args) => format!(
-                "action={}\ntest_id={}\ntarget={}",
-                command, args.test_id, args.target
-            ),
+            FenceAgent::Ipmi(ipmi_args) => {
+                let mut args = format!(
+                    "ipaddr={0}\naction={1}\nusername={2}\npassword={3}\nlanplus={4}\n",
+                    host_id,
+                    command,
+                    ipmi_args.username,
+                    ipmi_args.password,
+                    if ipmi_args.lanplus { 1 } else { 0 },
+                );
+                if let Some(port) = ipmi_args.port {
+                    args.push_str(&format!("ipport={port}\n"));
+                }
+                args
+            }
+            FenceAgent::Virsh(virsh_args) => {
+                let mut args = format!(
+                    "ipaddr={0}\naction={1}\nport={2}\n",
+                    virsh_args.ipaddr,
+                    command,
+                    virsh_args.vm.as_deref().unwrap_or(host_id),
+                );
+                if let Some(login) = &virsh_args.login {
+                    args.push_str(&format!("login={login}\n"));
+                }
+                if let Some(identity_file) = &virsh_args.identity_file {
+                    args.push_str(&format!("identity_file={identity_file}\n"));
+                }
+                args
+            }
+            FenceAgent::Apc(apc_args) => {
+                let mut args = format!(
+                    "ipaddr={0}\naction={1}\nport={2}\n",
+                    apc_args.ipaddr, command, apc_args.port,
+                );
+                if let Some(community) = &apc_args.community {
+                    args.push_str(&format!("community={community}\n"));
+                }
+                if let Some(snmp_version) = &apc_args.snmp_version {
+                    args.push_str(&format!("snmp_version={snmp_version}\n"));
+                }
+                args
+            }
+            FenceAgent::Test(args) => {
+                let mut test_args = format!(
+                    "action={}\ntest_id={}\ntarget={}",
+                    command, args.test_id, args.target
+                );
+                if args.fail {
+                    test_args.push_str("\nfail=true");
+                }
+                if let Some(fail_times) = args.fail_times {
+                    test_args.push_str(&format!("\nfail_times={fail_times}"));
+                }
+                test_args
+            }
         };
 
         args.into_bytes()
@@ -306,14 +944,44 @@ pub struct TestFenceArgs {
 
     /// The name of the specific remote agent within the test.
     target: String,
+
+    /// Whether the fence agent should fail every action, for testing fallback between agents.
+    fail: bool,
+
+    /// How many power actions the fence agent should fail before it starts succeeding, for
+    /// testing retries.
+    fail_times: Option<u32>,
 }
 
 impl TestFenceArgs {
     pub fn new(params: &HashMap<String, String>) -> Option<Self> {
         let test_id = params.get("test_id")?.to_string();
         let target = params.get("target")?.to_string();
+        let fail = params.get("fail").is_some_and(|fail| fail == "true");
+        let fail_times = params.get("fail_times").and_then(|n| n.parse().ok());
 
-        Some(Self { test_id, target })
+        Some(Self {
+            test_id,
+            target,
+            fail,
+            fail_times,
+        })
+    }
+}
+
+/// Powerman fence agent arguments.
+#[derive(Clone, Debug)]
+pub struct PowermanArgs {
+    /// The address of the powerman daemon. If not given, the host's own address is used.
+    ipaddr: Option<String>,
+
+    /// The port that the powerman daemon listens on, if not the default.
+    port: Option<u16>,
+}
+
+impl PowermanArgs {
+    pub fn new(ipaddr: Option<String>, port: Option<u16>) -> Self {
+        Self { ipaddr, port }
     }
 }
 
@@ -335,3 +1003,319 @@ impl fmt::Debug for RedfishArgs {
         write!(f, "{{username: {}, password: ***}}", self.username)
     }
 }
+
+/// IPMI (fence_ipmilan) fence agent arguments.
+#[derive(Clone)]
+pub struct IpmiArgs {
+    username: String,
+    password: String,
+
+    /// Whether to use the IPMI v2.0 "lanplus" interface.
+    lanplus: bool,
+
+    /// The port that the BMC listens on, if not the default.
+    port: Option<u16>,
+}
+
+impl IpmiArgs {
+    pub fn new(username: String, password: String, lanplus: bool, port: Option<u16>) -> Self {
+        Self {
+            username,
+            password,
+            lanplus,
+            port,
+        }
+    }
+}
+
+/// libvirt (fence_virsh) fence agent arguments, for clusters of virtual machines. fence_virsh
+/// connects to the hypervisor over SSH and runs virsh there to control the host's VM.
+#[derive(Clone, Debug)]
+pub struct VirshArgs {
+    /// The address of the hypervisor that runs the VM.
+    ipaddr: String,
+
+    /// The name of the host's VM on the hypervisor. If not given, the host's own name is used.
+    vm: Option<String>,
+
+    /// The user to log in to the hypervisor as, if not the fence agent's default.
+    login: Option<String>,
+
+    /// The SSH private key to log in to the hypervisor with.
+    identity_file: Option<String>,
+}
+
+impl VirshArgs {
+    pub fn new(
+        ipaddr: String,
+        vm: Option<String>,
+        login: Option<String>,
+        identity_file: Option<String>,
+    ) -> Self {
+        Self {
+            ipaddr,
+            vm,
+            login,
+            identity_file,
+        }
+    }
+}
+
+/// APC PDU (fence_apc_snmp) fence agent arguments. The host is fenced by switching its outlet on
+/// the PDU over SNMP.
+#[derive(Clone)]
+pub struct ApcArgs {
+    /// The address of the PDU.
+    ipaddr: String,
+
+    /// The number of the host's outlet on the PDU.
+    port: u32,
+
+    /// The SNMP community, if not the fence agent's default.
+    community: Option<String>,
+
+    /// The SNMP version to use, if not the fence agent's default.
+    snmp_version: Option<String>,
+}
+
+impl ApcArgs {
+    pub fn new(
+        ipaddr: String,
+        port: u32,
+        community: Option<String>,
+        snmp_version: Option<String>,
+    ) -> Self {
+        Self {
+            ipaddr,
+            port,
+            community,
+            snmp_version,
+        }
+    }
+}
+
+impl fmt::Debug for ApcArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{ipaddr: {}, port: {}, community: {}, snmp_version: {:?}}}",
+            self.ipaddr,
+            self.port,
+            if self.community.is_some() {
+                "***"
+            } else {
+                "None"
+            },
+            self.snmp_version
+        )
+    }
+}
+
+impl fmt::Debug for IpmiArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{username: {}, password: ***, lanplus: {}, port: {:?}}}",
+            self.username, self.lanplus, self.port
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{
+        parse_power_status, FenceAgent, FenceCommand, FenceOutput, Host, HostStatus, PowerStatus,
+    };
+
+    #[test]
+    fn set_down_status() {
+        let host = Host::new("mds00", Some(8000), Vec::new());
+        assert_eq!(host.get_status(), HostStatus::Unknown);
+
+        host.set_status(HostStatus::Down);
+        assert_eq!(host.get_status(), HostStatus::Down);
+
+        host.set_status(HostStatus::Up);
+        assert_eq!(host.get_status(), HostStatus::Up);
+    }
+
+    #[test]
+    fn host_port_ipv4() {
+        assert_eq!(Host::get_host_port("10.0.0.1").unwrap(), ("10.0.0.1", None));
+        assert_eq!(
+            Host::get_host_port("10.0.0.1:8000").unwrap(),
+            ("10.0.0.1", Some(8000))
+        );
+        assert_eq!(Host::get_host_port("mds00").unwrap(), ("mds00", None));
+    }
+
+    #[test]
+    fn host_port_ipv6() {
+        assert_eq!(Host::get_host_port("fe80::1").unwrap(), ("fe80::1", None));
+        assert_eq!(Host::get_host_port("[fe80::1]").unwrap(), ("fe80::1", None));
+        assert_eq!(
+            Host::get_host_port("[fe80::1]:8000").unwrap(),
+            ("fe80::1", Some(8000))
+        );
+    }
+
+    #[test]
+    fn host_port_invalid() {
+        assert!(Host::get_host_port("10.0.0.1:port").is_err());
+        assert!(Host::get_host_port("10.0.0.1:99999").is_err());
+        assert!(Host::get_host_port("[fe80::1:8000").is_err());
+        assert!(Host::get_host_port("[fe80::1]8000").is_err());
+        assert!(Host::get_host_port(":8000").is_err());
+    }
+
+    #[test]
+    fn powerman_address() {
+        let params = HashMap::from([
+            ("ipaddr".to_string(), "pm-server".to_string()),
+            ("port".to_string(), "10101".to_string()),
+        ]);
+        let agent = FenceAgent::from_params("powerman", &Some(params));
+        let args =
+            String::from_utf8(agent.generate_command_bytes("mds00", FenceCommand::Off)).unwrap();
+        assert!(args.contains("ipaddr=pm-server\n"));
+        assert!(args.contains("ipport=10101\n"));
+        assert!(args.contains("plug=mds00\n"));
+
+        // Without an ipaddr parameter, the host's own address is used:
+        let agent = FenceAgent::from_params("powerman", &None);
+        let args =
+            String::from_utf8(agent.generate_command_bytes("mds00", FenceCommand::Off)).unwrap();
+        assert!(args.contains("ipaddr=mds00\n"));
+        assert!(!args.contains("localhost"));
+    }
+
+    #[test]
+    fn virsh_command() {
+        let params = HashMap::from([
+            ("ipaddr".to_string(), "hypervisor".to_string()),
+            ("vm".to_string(), "halo-mds00".to_string()),
+            ("login".to_string(), "fencer".to_string()),
+            (
+                "identity_file".to_string(),
+                "/etc/halo/fence.key".to_string(),
+            ),
+        ]);
+        let agent = FenceAgent::from_params("fence_virsh", &Some(params));
+        assert_eq!(agent.get_executable(), "fence_virsh");
+        let args =
+            String::from_utf8(agent.generate_command_bytes("mds00", FenceCommand::Off)).unwrap();
+        assert_eq!(
+            args,
+            "ipaddr=hypervisor\naction=off\nport=halo-mds00\nlogin=fencer\nidentity_file=/etc/halo/fence.key\n"
+        );
+
+        // Without a vm parameter, the VM is assumed to be named after the host:
+        let params = HashMap::from([("ipaddr".to_string(), "hypervisor".to_string())]);
+        let agent = FenceAgent::from_params("fence_virsh", &Some(params));
+        let args =
+            String::from_utf8(agent.generate_command_bytes("mds00", FenceCommand::Status)).unwrap();
+        assert_eq!(args, "ipaddr=hypervisor\naction=status\nport=mds00\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "ipaddr")]
+    fn virsh_needs_hypervisor() {
+        let params = HashMap::from([("vm".to_string(), "halo-mds00".to_string())]);
+        FenceAgent::from_params("fence_virsh", &Some(params));
+    }
+
+    #[test]
+    fn apc_command() {
+        let params = HashMap::from([
+            ("ipaddr".to_string(), "pdu01".to_string()),
+            ("port".to_string(), "7".to_string()),
+            ("community".to_string(), "secret".to_string()),
+            ("snmp_version".to_string(), "2c".to_string()),
+        ]);
+        let agent = FenceAgent::from_params("fence_apc_snmp", &Some(params));
+        assert_eq!(agent.get_executable(), "fence_apc_snmp");
+        let args =
+            String::from_utf8(agent.generate_command_bytes("mds00", FenceCommand::Off)).unwrap();
+        assert_eq!(
+            args,
+            "ipaddr=pdu01\naction=off\nport=7\ncommunity=secret\nsnmp_version=2c\n"
+        );
+        assert!(!format!("{agent:?}").contains("secret"));
+    }
+
+    #[test]
+    #[should_panic(expected = "outlet number")]
+    fn apc_needs_outlet() {
+        let params = HashMap::from([("ipaddr".to_string(), "pdu01".to_string())]);
+        FenceAgent::from_params("fence_apc_snmp", &Some(params));
+    }
+
+    #[test]
+    #[should_panic(expected = "valid outlet number")]
+    fn apc_invalid_outlet() {
+        let params = HashMap::from([
+            ("ipaddr".to_string(), "pdu01".to_string()),
+            ("port".to_string(), "seven".to_string()),
+        ]);
+        FenceAgent::from_params("fence_apc_snmp", &Some(params));
+    }
+
+    #[test]
+    fn power_status_output() {
+        let status = |code, out: &str| {
+            parse_power_status(FenceOutput {
+                code,
+                stdout: out.to_string(),
+                stderr: String::new(),
+            })
+            .ok()
+        };
+
+        // fence_ipmilan, fence_redfish, and the other agents from the fence-agents package:
+        assert_eq!(status(Some(0), "Status: ON\n"), Some(PowerStatus::On));
+        assert_eq!(status(Some(2), "Status: OFF\n"), Some(PowerStatus::Off));
+
+        // The test fence agent exits with 0 either way:
+        assert_eq!(status(Some(0), "mds00 is ON\n"), Some(PowerStatus::On));
+        assert_eq!(status(Some(0), "mds00 is OFF\n"), Some(PowerStatus::Off));
+
+        // ipmitool's wording, after some noise:
+        assert_eq!(
+            status(Some(0), "Connecting to BMC...\nChassis Power is off\n"),
+            Some(PowerStatus::Off)
+        );
+
+        // With no status in the output, the exit code decides:
+        assert_eq!(status(Some(0), ""), Some(PowerStatus::On));
+        assert_eq!(status(Some(2), ""), Some(PowerStatus::Off));
+
+        // Any other exit code is an error, whatever the output says:
+        assert_eq!(
+            status(
+                Some(1),
+                "Failed: Unable to obtain correct plug status or plug is not available\n"
+            ),
+            None
+        );
+        assert_eq!(status(Some(1), "Status: ON\n"), None);
+        assert_eq!(status(None, "Status: ON\n"), None);
+    }
+
+    #[test]
+    fn fence_failure_output() {
+        let params = HashMap::from([
+            ("test_id".to_string(), "fence_failure_output".to_string()),
+            ("target".to_string(), "mds00".to_string()),
+            ("fail".to_string(), "true".to_string()),
+        ]);
+        let agent = FenceAgent::from_params("fence_test", &Some(params));
+        let host = Host::new("mds00", Some(8000), vec![agent]);
+
+        // The error says why the fence agent failed, in its own words:
+        let e = host.do_fence(FenceCommand::Off).unwrap_err().to_string();
+        assert!(e.contains("exit code 1"));
+        assert!(e.contains("failing off on mds00 as requested"));
+    }
+}
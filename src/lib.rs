@@ -7,6 +7,7 @@ pub mod config;
 pub mod halo_capnp;
 pub mod host;
 pub mod manager;
+pub mod metrics;
 pub mod remote;
 pub mod resource;
 pub mod test_env;
@@ -124,6 +125,53 @@ impl Buffer {
     }
 }
 
+/// LineHistory keeps the most recent lines written to it, up to a fixed number, so that they can
+/// be read back later, such as by an operator asking the manager for its recent activity.
+///
+/// Each line is numbered in the order it was written, starting from 1, so that a reader can ask
+/// only for the lines written since it last read.
+#[derive(Debug)]
+pub struct LineHistory {
+    capacity: usize,
+    /// The kept lines, oldest first, and how many lines have been written in all.
+    lines: Mutex<(std::collections::VecDeque<String>, u64)>,
+}
+
+impl LineHistory {
+    /// Create a history keeping up to `capacity` lines. With a capacity of 0, nothing is kept.
+    pub fn new(capacity: usize) -> Self {
+        LineHistory {
+            capacity,
+            lines: Mutex::new((std::collections::VecDeque::new(), 0)),
+        }
+    }
+
+    /// Add a line, forgetting the oldest kept line if the history is full.
+    pub fn push(&self, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.0.len() == self.capacity {
+            lines.0.pop_front();
+        }
+        lines.0.push_back(line.to_string());
+        lines.1 += 1;
+    }
+
+    /// Get up to the last `n` of the kept lines numbered after `after`, along with the number of
+    /// the last line written, to pass as `after` next time.
+    pub fn tail(&self, n: usize, after: u64) -> (Vec<String>, u64) {
+        let lines = self.lines.lock().unwrap();
+        let (kept, written) = (&lines.0, lines.1);
+        let first_kept = written - kept.len() as u64 + 1;
+        let skip = after.saturating_sub(first_kept - 1).min(kept.len() as u64) as usize;
+        let newer = kept.len() - skip;
+        let tail = kept.iter().skip(skip + newer.saturating_sub(n)).cloned();
+        (tail.collect(), written)
+    }
+}
+
 /// LogStream is an abstract object representing a writeable (and in some cases readable) stream.
 ///
 /// Each enum variant represents a concrete type that has its own way of writing (and perhaps
@@ -133,6 +181,9 @@ pub enum LogStream {
     Stdout(std::io::Stdout),
     Stderr(std::io::Stderr),
     Buffer(Buffer),
+    File(Mutex<std::fs::File>),
+    /// The local syslog daemon, reached through its unix socket. Each line is sent as one message.
+    Syslog(std::os::unix::net::UnixDatagram),
 }
 
 impl LogStream {
@@ -148,12 +199,44 @@ impl LogStream {
         LogStream::Buffer(Buffer::new())
     }
 
+    /// Open a stream which appends to the file at `path`, creating it if needed.
+    pub fn new_file(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(LogStream::File(Mutex::new(file)))
+    }
+
+    /// Open a stream to the local syslog daemon.
+    pub fn new_syslog() -> std::io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(SYSLOG_SOCKET)?;
+        Ok(LogStream::Syslog(socket))
+    }
+
+    /// Open the stream named by `dest`: "stdout", "stderr", "syslog", or else the path of a file.
+    pub fn from_destination(dest: &str) -> std::io::Result<Self> {
+        match dest {
+            "stdout" => Ok(Self::new_stdout()),
+            "stderr" => Ok(Self::new_stderr()),
+            "syslog" => Self::new_syslog(),
+            path => Self::new_file(path),
+        }
+    }
+
     pub fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
         use std::io::Write;
         match self {
             LogStream::Stdout(s) => s.lock().write(buf),
             LogStream::Stderr(s) => s.lock().write(buf),
             LogStream::Buffer(b) => b.write(buf),
+            LogStream::File(f) => f.lock().unwrap().write(buf),
+            LogStream::Syslog(s) => {
+                // Messages are sent with the "info" severity, from the "daemon" facility:
+                let message = [b"<30>halo: ", buf].concat();
+                s.send(&message).map(|_| buf.len())
+            }
         }
     }
 
@@ -162,6 +245,8 @@ impl LogStream {
             LogStream::Stdout(_) => self.write(&[buf, b"\n"].concat()),
             LogStream::Stderr(_) => self.write(&[buf, b"\n"].concat()),
             LogStream::Buffer(b) => b.writeln(buf),
+            LogStream::File(_) => self.write(&[buf, b"\n"].concat()),
+            LogStream::Syslog(_) => self.write(buf),
         }
     }
 
@@ -180,6 +265,20 @@ impl LogStream {
     }
 }
 
+/// The unix socket that the local syslog daemon listens on.
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// Set up logging to stderr for the binaries. Messages at info level and above are logged, or at
+/// debug level and above when `verbosity` is at least 1; the RUST_LOG environment variable
+/// overrides either.
+pub fn init_logging(verbosity: u8) {
+    let level = if verbosity > 0 { "debug" } else { "info" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
+        .format_target(false)
+        .format_timestamp(None)
+        .init();
+}
+
 /// Gets the port that the remote server should be listening on.
 pub fn remote_port() -> u16 {
     match std::env::var("HALO_PORT") {
@@ -197,6 +296,10 @@ pub fn default_socket() -> String {
     }
 }
 
+pub fn default_socket_group() -> Option<String> {
+    std::env::var("HALO_SOCKET_GROUP").ok()
+}
+
 pub fn default_config_path() -> String {
     match std::env::var("HALO_CONFIG") {
         Ok(conf) => conf,
@@ -218,6 +321,13 @@ pub fn default_server_key() -> String {
     }
 }
 
+pub fn default_pid_file() -> String {
+    match std::env::var("HALO_PID_FILE") {
+        Ok(path) => path,
+        Err(_) => "/var/run/halo-remote.pid".to_string(),
+    }
+}
+
 pub fn default_client_cert() -> String {
     match std::env::var("HALO_CLIENT_CERT") {
         Ok(cert) => cert,
@@ -239,9 +349,37 @@ pub fn default_ca_cert() -> String {
     }
 }
 
+/// The path of the test fence agent: HALO_TEST_FENCE_BIN if it is set, or else its full path in
+/// the crate that this was built from, so that the tests don't depend on running from the crate
+/// root.
+pub fn test_fence_agent_path() -> String {
+    match std::env::var("HALO_TEST_FENCE_BIN") {
+        Ok(path) => path,
+        Err(_) => concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fence_test").to_string(),
+    }
+}
+
 pub fn default_network() -> String {
     match std::env::var("HALO_NET") {
         Ok(net) => net,
         Err(_) => "192.168.1.0/24".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LineHistory;
+
+    #[test]
+    fn line_history_bound() {
+        let history = LineHistory::new(2);
+        for line in ["a", "b", "c"] {
+            history.push(line);
+        }
+        assert_eq!(history.tail(10, 0), (vec!["b".into(), "c".into()], 3));
+
+        let history = LineHistory::new(0);
+        history.push("a");
+        assert_eq!(history.tail(10, 0), (Vec::new(), 0));
+    }
+}
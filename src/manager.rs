@@ -1,43 +1,113 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{io, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
 
 use {
     capnp::capability::Promise,
-    capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem},
+    capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem},
     futures::AsyncReadExt,
+    log::{error, info, warn},
+    nix::{
+        sys::stat::{umask, Mode},
+        unistd::{chown, Group},
+    },
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::watch,
+    },
 };
 
-use crate::{cluster, halo_capnp::halo_mgmt, LogStream};
+use crate::{
+    cluster,
+    halo_capnp::halo_mgmt,
+    remote::ocf,
+    resource::{Location, ResourceStatus, Traversal},
+    LineHistory, LogStream,
+};
 
 /// An object that can be passed to manager functions holding some state that should be shared
 /// between these functions.
 #[derive(Debug)]
 pub struct MgrContext {
     pub out_stream: LogStream,
+
+    /// Where to write a structured event for each change in a resource's status, if anywhere.
+    pub event_stream: Option<LogStream>,
+
+    /// The manager's most recent activity, for operators to read over the management socket.
+    pub history: LineHistory,
+
+    /// How many times a resource group has been failed over since the manager started.
+    pub failovers: AtomicU64,
+
     pub args: crate::commands::Cli,
 }
 
 impl MgrContext {
     pub fn new(args: crate::commands::Cli) -> Self {
         let mut context = Self::default();
+        context.event_stream = args.event_log.as_ref().and_then(|dest| {
+            LogStream::from_destination(dest)
+                .inspect_err(|e| error!("Could not open event log \"{dest}\": {e}"))
+                .ok()
+        });
+        context.history = LineHistory::new(args.log_history);
         context.args = args;
         context
     }
+
+    /// Record a line of the manager's activity in its history, and, if `show` is set, write it
+    /// to the output stream too.
+    pub fn log_activity(&self, line: &str, show: bool) {
+        self.history.push(line);
+        if show {
+            let _ = self.out_stream.writeln(line.as_bytes());
+        }
+    }
 }
 
 impl Default for MgrContext {
     fn default() -> MgrContext {
         MgrContext {
             out_stream: crate::LogStream::new_stdout(),
+            event_stream: None,
+            history: LineHistory::new(crate::commands::DEFAULT_LOG_HISTORY),
+            failovers: AtomicU64::new(0),
             args: crate::commands::Cli::default(),
         }
     }
 }
 
 struct HaloMgmtImpl {
-    cluster: Arc<cluster::Cluster>,
+    /// The running clusters, each of which is replaced when the config is reloaded.
+    clusters: Vec<watch::Receiver<Arc<cluster::Cluster>>>,
+}
+
+impl HaloMgmtImpl {
+    /// Get the clusters as they are currently configured.
+    fn clusters(&self) -> Vec<Arc<cluster::Cluster>> {
+        self.clusters
+            .iter()
+            .map(|cluster| Arc::clone(&cluster.borrow()))
+            .collect()
+    }
+
+    /// Get the cluster holding the resource with the given ID, or else the first cluster, which
+    /// reports that there is no such resource.
+    fn cluster_of_resource(&self, id: &str) -> Arc<cluster::Cluster> {
+        let clusters = self.clusters();
+        let found = clusters
+            .iter()
+            .position(|cluster| cluster.get_resource(id).is_some());
+        Arc::clone(&clusters[found.unwrap_or(0)])
+    }
 }
 
 /// Implementation of the server side of the Management (CLI to local daemon) RPC interface.
@@ -47,19 +117,38 @@ impl halo_mgmt::Server for HaloMgmtImpl {
         _params: halo_mgmt::MonitorParams,
         mut results: halo_mgmt::MonitorResults,
     ) -> Promise<(), ::capnp::Error> {
-        let cluster = &self.cluster;
+        let clusters = self.clusters();
         let mut message = ::capnp::message::Builder::new_default();
         let mut message = message.init_root::<halo_mgmt::cluster::Builder>();
 
-        let mut resource_messages = message
-            .reborrow()
-            // TODO: store the total number of resources in Cluster so that this extra iteration
-            // isn't necessary:
-            .init_resources(cluster.resources().collect::<Vec<_>>().len() as u32);
+        let num_resources = clusters.iter().map(|c| c.num_resources()).sum();
+        let mut resource_messages = message.reborrow().init_resources(num_resources);
+
+        let mut parents: HashMap<&str, &str> = HashMap::new();
+        for res in clusters.iter().flat_map(|c| c.resources()) {
+            for dep in res.dependents.iter() {
+                parents.insert(&dep.id, &res.id);
+            }
+        }
 
-        for (i, res) in cluster.resources().enumerate() {
+        let groups = || {
+            clusters.iter().flat_map(|cluster| {
+                cluster
+                    .resource_groups()
+                    .map(move |group| (cluster.name(), group))
+            })
+        };
+        let resources = groups()
+            .flat_map(|(cluster, group)| group.resources().map(move |res| (cluster, group, res)));
+        for (i, (cluster, group, res)) in resources.enumerate() {
             let mut message = resource_messages.reborrow().get(i as u32);
+            message.set_cluster(cluster);
             message.set_status(res.get_status().into());
+            message.set_status_age(res.last_transition().elapsed().as_secs());
+            message.set_maintenance(res.home_node.in_maintenance());
+            message.set_group(&group.root.id);
+            message.set_id(&res.id);
+            message.set_parent(parents.get(res.id.as_str()).copied().unwrap_or_default());
             let mut parameters = message
                 .reborrow()
                 .init_parameters(res.parameters.len() as u32);
@@ -70,29 +159,222 @@ impl halo_mgmt::Server for HaloMgmtImpl {
             }
         }
 
+        let mut group_messages = message.reborrow().init_groups(groups().count() as u32);
+        for (i, (cluster, group)) in groups().enumerate() {
+            let mut message = group_messages.reborrow().get(i as u32);
+            message.set_cluster(cluster);
+            message.set_id(&group.root.id);
+            message.set_status(group.worst_status().into());
+        }
+
         match results.get().set_status(message.into_reader()) {
             Ok(_) => Promise::ok(()),
             Err(e) => Promise::err(e),
         }
     }
+
+    fn cleanup(
+        &mut self,
+        params: halo_mgmt::CleanupParams,
+        _results: halo_mgmt::CleanupResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let id = pry!(pry!(pry!(params.get()).get_resource()).to_str()).to_string();
+        let cluster = self.cluster_of_resource(&id);
+
+        Promise::from_future(async move {
+            let Some(res) = cluster.get_resource(&id) else {
+                return Err(::capnp::Error::failed(format!("no such resource \"{id}\"")));
+            };
+
+            res.cleanup();
+            res.update_status(res.monitor(Location::Home).await);
+
+            Ok(())
+        })
+    }
+
+    fn relocate(
+        &mut self,
+        params: halo_mgmt::RelocateParams,
+        _results: halo_mgmt::RelocateResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let id = pry!(pry!(params.get_group()).to_str()).to_string();
+        let destination = pry!(params.get_to());
+        // A group is named by the ID of its root resource:
+        let cluster = self.cluster_of_resource(&id);
+
+        Promise::from_future(async move {
+            let Some(group) = cluster.get_resource_group(&id) else {
+                return Err(::capnp::Error::failed(format!(
+                    "no such resource group \"{id}\""
+                )));
+            };
+
+            let to = match destination {
+                halo_mgmt::Destination::Home => Location::Home,
+                halo_mgmt::Destination::Away => Location::Away,
+                halo_mgmt::Destination::Other => match group.current_location() {
+                    Some(loc) => loc.other(),
+                    // Not running anywhere, so prefer the failover host, unless that would put
+                    // the group alongside an anti-affine one:
+                    None => match cluster.anti_affine_conflict(group, Location::Away) {
+                        Some(_) => Location::Home,
+                        None => Location::Away,
+                    },
+                },
+            };
+
+            cluster
+                .relocate_group(&id, to)
+                .await
+                .map_err(|e| ::capnp::Error::failed(e.to_string()))
+        })
+    }
+
+    fn maintenance(
+        &mut self,
+        params: halo_mgmt::MaintenanceParams,
+        _results: halo_mgmt::MaintenanceResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let name = pry!(pry!(params.get_host()).to_str());
+
+        let clusters = self.clusters();
+        let Some(host) = clusters.iter().find_map(|c| c.get_host(name)) else {
+            return Promise::err(::capnp::Error::failed(format!("no such host \"{name}\"")));
+        };
+        host.set_maintenance(params.get_on());
+
+        Promise::ok(())
+    }
+
+    fn start_resource(
+        &mut self,
+        params: halo_mgmt::StartResourceParams,
+        _results: halo_mgmt::StartResourceResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let id = pry!(pry!(pry!(params.get()).get_id()).to_str()).to_string();
+        let cluster = self.cluster_of_resource(&id);
+
+        Promise::from_future(
+            async move { resource_action(&cluster, &id, ResourceAction::Start).await },
+        )
+    }
+
+    fn stop_resource(
+        &mut self,
+        params: halo_mgmt::StopResourceParams,
+        _results: halo_mgmt::StopResourceResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let id = pry!(pry!(pry!(params.get()).get_id()).to_str()).to_string();
+        let cluster = self.cluster_of_resource(&id);
+
+        Promise::from_future(
+            async move { resource_action(&cluster, &id, ResourceAction::Stop).await },
+        )
+    }
+
+    fn tail_log(
+        &mut self,
+        params: halo_mgmt::TailLogParams,
+        mut results: halo_mgmt::TailLogResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        // The clusters share the manager's context, and so its history:
+        let context = Arc::clone(&self.clusters[0].borrow().context);
+        let (lines, next) = context
+            .history
+            .tail(params.get_lines() as usize, params.get_after());
+
+        let mut results = results.get();
+        results.set_next(next);
+        let mut list = results.init_lines(lines.len() as u32);
+        for (i, line) in lines.iter().enumerate() {
+            list.set(i as u32, line);
+        }
+
+        Promise::ok(())
+    }
+}
+
+/// An action that an operator can ask the manager to take on a single resource.
+#[derive(Debug, Clone, Copy)]
+enum ResourceAction {
+    Start,
+    Stop,
+}
+
+/// Start or stop the resource with the given ID, and monitor it afterward to update its status.
+///
+/// A resource is started on its home host, and stopped wherever it is running. Nothing is done on
+/// a host in maintenance.
+async fn resource_action(
+    cluster: &cluster::Cluster,
+    id: &str,
+    action: ResourceAction,
+) -> Result<(), ::capnp::Error> {
+    let Some(group) = cluster.get_group_of_resource(id) else {
+        return Err(::capnp::Error::failed(format!("no such resource \"{id}\"")));
+    };
+    let res = cluster.get_resource(id).unwrap();
+
+    let _guard = group.lock_actions().await;
+
+    let loc = match (action, res.get_status()) {
+        (ResourceAction::Stop, ResourceStatus::RunningOnAway) => Location::Away,
+        _ => Location::Home,
+    };
+    let host = res.host_at(loc);
+    if host.in_maintenance() {
+        return Err(::capnp::Error::failed(format!(
+            "{action:?} of resource \"{id}\" refused: host {host} is in maintenance"
+        )));
+    }
+
+    let result = match action {
+        ResourceAction::Start => res.start(loc).await,
+        ResourceAction::Stop => res.stop(loc).await,
+    };
+    res.update_status(res.monitor(loc).await);
+
+    match result {
+        Ok(ocf::Status::Success) => Ok(()),
+        Ok(status) => Err(::capnp::Error::failed(format!(
+            "{action:?} of resource \"{id}\" failed: {status:?}"
+        ))),
+        Err(e) => Err(::capnp::Error::failed(format!(
+            "{action:?} of resource \"{id}\" failed: {e}"
+        ))),
+    }
 }
 
+/// The permissions of the manager's socket: only its owner and group may connect to it.
+const SOCKET_MODE: u32 = 0o660;
+
 /// Get a unix socket listener from a given socket path.
 ///
 /// To avoid clobbering an already-in-use unix socket, a connection is attempted to an existing
 /// unix socket first. If this fails, a new socket listener can be returned, since an existing
 /// in-use socket was determined to be absent at the given location.
-async fn prepare_unix_socket(addr: &String) -> io::Result<tokio::net::UnixListener> {
+///
+/// The socket is only accessible to its owner and to `group`, if given. Since this changes the
+/// process's umask while it binds the socket, it must be called before any other threads that
+/// create files are started.
+async fn prepare_unix_socket(
+    addr: &String,
+    group: Option<&str>,
+) -> io::Result<tokio::net::UnixListener> {
     // Check for existing socket in use
     match tokio::net::UnixStream::connect(&addr).await {
         Ok(_) => {
-            eprintln!("Address already in use: {addr}");
+            error!("Address already in use: {addr}");
             return Err(io::Error::from(io::ErrorKind::AddrInUse));
         }
         Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
         Err(e) if e.kind() == io::ErrorKind::NotFound => {}
         Err(e) => {
-            eprintln!("Unexpected error while preparing unix socket '{addr}': {e}");
+            error!("Unexpected error while preparing unix socket '{addr}': {e}");
             return Err(e);
         }
     };
@@ -100,34 +382,73 @@ async fn prepare_unix_socket(addr: &String) -> io::Result<tokio::net::UnixListen
         Ok(_) => {}
         Err(e) if e.kind() == io::ErrorKind::NotFound => {}
         Err(e) => {
-            eprintln!("error removing old socket: {e}");
+            error!("error removing old socket: {e}");
             return Err(e);
         }
     };
-    // Create new socket
-    match tokio::net::UnixListener::bind(addr) {
-        Ok(l) => Ok(l),
+    // Create new socket. The umask ensures that it is never accessible to other users, even
+    // briefly before its permissions are set:
+    let old_umask = umask(Mode::from_bits_truncate(!SOCKET_MODE & 0o777));
+    let listener = tokio::net::UnixListener::bind(addr);
+    umask(old_umask);
+    let listener = match listener {
+        Ok(l) => l,
         Err(e) => {
-            eprintln!("error binding to socket '{addr}': {e}");
-            Err(e)
+            error!("error binding to socket '{addr}': {e}");
+            return Err(e);
         }
+    };
+
+    fs::set_permissions(addr, fs::Permissions::from_mode(SOCKET_MODE))
+        .inspect_err(|e| error!("error setting permissions on socket '{addr}': {e}"))?;
+
+    if let Some(group) = group {
+        set_socket_group(addr, group)
+            .inspect_err(|e| error!("error setting group of socket '{addr}': {e}"))?;
     }
+
+    Ok(listener)
+}
+
+/// Change the group that owns the socket at `addr` to `group`.
+fn set_socket_group(addr: &str, group: &str) -> io::Result<()> {
+    let Some(group) = Group::from_name(group)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such group \"{group}\""),
+        ));
+    };
+    chown(addr, None, Some(group.gid))?;
+    Ok(())
 }
 
+/// The longest that in-flight CLI commands are given to finish once the manager is asked to shut
+/// down.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
 /// Main entrypoint for the command server.
 ///
-/// This listens for commands on a unix socket and acts on them.
-async fn server_main(listener: tokio::net::UnixListener, cluster: Arc<cluster::Cluster>) {
+/// This listens for commands on a unix socket and acts on them, until `shutdown` is signalled.
+async fn server_main(
+    listener: tokio::net::UnixListener,
+    clusters: Vec<watch::Receiver<Arc<cluster::Cluster>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
     tokio::task::LocalSet::new()
         .run_until(async move {
-            let mgmt_client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl { cluster });
+            let mgmt_client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl { clusters });
+            let mut connections: Vec<tokio::task::JoinHandle<_>> = Vec::new();
 
             loop {
-                let (stream, _) = match listener.accept().await {
+                let accepted = tokio::select! {
+                    _ = shutdown.changed() => break,
+                    accepted = listener.accept() => accepted,
+                };
+                let (stream, _) = match accepted {
                     Ok(s) => s,
                     Err(e) => {
                         // XXX: why might accept() fail? How to properly handle error here?
-                        eprintln!("Could not accept connection: {e}");
+                        error!("Could not accept connection: {e}");
                         continue;
                     }
                 };
@@ -137,69 +458,508 @@ async fn server_main(listener: tokio::net::UnixListener, cluster: Arc<cluster::C
                     futures::io::BufReader::new(reader),
                     futures::io::BufWriter::new(writer),
                     rpc_twoparty_capnp::Side::Server,
-                    Default::default(),
+                    crate::halo_capnp::RPC_READER_OPTIONS,
                 );
 
                 let rpc_system =
                     RpcSystem::new(Box::new(network), Some(mgmt_client.clone().client));
 
-                tokio::task::spawn_local(rpc_system);
+                connections.retain(|connection| !connection.is_finished());
+                connections.push(tokio::task::spawn_local(rpc_system));
             }
+
+            // The RPC systems for connected clients run as tasks in this LocalSet, so they only
+            // make progress while it is running:
+            let finished = futures::future::join_all(connections);
+            let _ = tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, finished).await;
         })
         .await
 }
 
 /// Main entrypoint for the management service, which monitors and controls the state of
-/// the cluster.
-async fn manager_main(cluster: Arc<cluster::Cluster>) {
-    cluster.main_loop().await;
+/// the cluster, until `shutdown` is signalled.
+///
+/// When the config is reloaded, the management loops for the old cluster are dropped, and those
+/// for the new cluster are started in their place.
+async fn manager_main(
+    mut clusters: watch::Receiver<Arc<cluster::Cluster>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let cluster = Arc::clone(&clusters.borrow_and_update());
+        tokio::select! {
+            _ = cluster.main_loop() => return,
+            _ = shutdown.changed() => return,
+            Ok(()) = clusters.changed() => {}
+        }
+    }
+}
+
+/// Re-read the cluster's config file each time the manager receives SIGHUP, and publish the
+/// cluster it describes to `clusters`. When the manager manages several clusters, each has a
+/// handler of its own.
+///
+/// The hosts and resources which are unchanged keep their known state. Resources which are no
+/// longer in the config stop being managed; they are also stopped if the manager was asked to do
+/// so with --stop-removed. If the config can't be loaded, or it clashes with one of the `others`
+/// clusters that the manager manages, the running cluster is left alone.
+fn handle_reload_signals(
+    clusters: watch::Sender<Arc<cluster::Cluster>>,
+    others: Vec<watch::Receiver<Arc<cluster::Cluster>>>,
+) -> io::Result<()> {
+    let mut sighup = signal(SignalKind::hangup())?;
+
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            let old = Arc::clone(&clusters.borrow());
+            let Ok(new) = cluster::Cluster::from_path(Arc::clone(&old.context), old.config_path())
+            else {
+                warn!("Could not reload config; carrying on with the old one.");
+                continue;
+            };
+            let others: Vec<_> = others.iter().map(|c| Arc::clone(&c.borrow())).collect();
+            if check_distinct(&new, others.iter().map(Arc::as_ref)).is_err() {
+                warn!("Could not reload config; carrying on with the old one.");
+                continue;
+            }
+
+            let changes = new.adopt_state(&old);
+            for id in changes.added_resources.iter() {
+                info!("Config reloaded: now managing resource \"{id}\"");
+            }
+            for id in changes.removed_resources.iter() {
+                info!("Config reloaded: no longer managing resource \"{id}\"");
+            }
+
+            let _ = clusters.send(Arc::new(new));
+            if old.context.args.stop_removed {
+                stop_removed_resources(&old, &changes.removed_resources).await;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Stop the resources of `old` with the given IDs, stopping dependents before the resources they
+/// depend on.
+async fn stop_removed_resources(old: &cluster::Cluster, removed: &[String]) {
+    for group in old.resource_groups() {
+        let _guard = group.lock_actions().await;
+        for res in group.resources_in_order(Traversal::PostOrder) {
+            if !removed.contains(&res.id) {
+                continue;
+            }
+            let loc = match res.get_status() {
+                ResourceStatus::RunningOnAway => Location::Away,
+                _ => Location::Home,
+            };
+            if let Err(e) = res.stop(loc).await {
+                error!("Could not stop removed resource \"{}\": {e}", res.id);
+            }
+        }
+    }
+}
+
+/// Wait for the manager to be asked to stop with SIGTERM or SIGINT, then signal `shutdown`.
+async fn handle_shutdown_signals(shutdown: watch::Sender<bool>) -> io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+        let _ = shutdown.send(true);
+    });
+
+    Ok(())
+}
+
+/// Create a cluster for each of the config files given on the command line, sharing `context`.
+///
+/// The clusters' names must differ, as must the IDs of their resources, so that each resource can
+/// be found by its ID alone. No host may be in more than one cluster, since each cluster monitors
+/// and fences its hosts on its own.
+pub fn load_clusters(
+    context: Arc<MgrContext>,
+) -> Result<Vec<cluster::Cluster>, crate::commands::EmptyError> {
+    let mut clusters: Vec<cluster::Cluster> = Vec::new();
+    for path in context.args.config_paths() {
+        let cluster = cluster::Cluster::from_path(Arc::clone(&context), &path)?;
+        check_distinct(&cluster, &clusters)?;
+        clusters.push(cluster);
+    }
+
+    Ok(clusters)
+}
+
+/// Check that `cluster` can be managed alongside `others`: its name must differ from theirs, and
+/// none of its resources or hosts may be in any of them.
+fn check_distinct<'a>(
+    cluster: &cluster::Cluster,
+    others: impl IntoIterator<Item = &'a cluster::Cluster>,
+) -> Result<(), crate::commands::EmptyError> {
+    let path = cluster.config_path();
+    for other in others {
+        if other.name() == cluster.name() {
+            error!(
+                "The config files \"{}\" and \"{path}\" both describe a cluster named \"{}\"; rename one of them.",
+                other.config_path(),
+                cluster.name()
+            );
+            return Err(crate::commands::EmptyError {});
+        }
+        if let Some(res) = cluster
+            .resources()
+            .find(|res| other.get_resource(&res.id).is_some())
+        {
+            error!(
+                "Resource \"{}\" is in both \"{}\" and \"{path}\"; resource IDs must be unique across the clusters.",
+                res.id,
+                other.config_path()
+            );
+            return Err(crate::commands::EmptyError {});
+        }
+        if let Some(host) = cluster
+            .hosts()
+            .find(|host| other.hosts().any(|h| h.address() == host.address()))
+        {
+            error!(
+                "Host \"{}\" is in both \"{}\" and \"{path}\"; a host may only be in one cluster.",
+                host.address(),
+                other.config_path()
+            );
+            return Err(crate::commands::EmptyError {});
+        }
+    }
+
+    Ok(())
 }
 
 /// Rust client management daemon -
 ///
 /// This launches two "services".
 ///
-/// - A manager service which continuously monitors the state of the cluster.
+/// - A manager service which continuously monitors the state of the clusters.
 ///     The monitoring service takes actions based on cluster status, such as migrating resources,
-///     fencing nodes, etc.
+///     fencing nodes, etc. Each cluster is managed independently of the others.
 ///
 /// - A server that listens on a unix socket (/var/run/halo.socket) for
-///     commands from the command line interface.
-pub fn main(cluster: cluster::Cluster) -> crate::commands::Result {
-    let cluster = Arc::new(cluster);
+///     commands from the command line interface, about any of the clusters.
+///
+/// With --metrics-addr, the clusters' metrics are also served over HTTP alongside the socket.
+///
+/// Both services run until the process receives SIGTERM or SIGINT, at which point the socket is
+/// removed and this returns. On SIGHUP, the config files are reloaded.
+pub fn main(clusters: Vec<cluster::Cluster>) -> crate::commands::Result {
+    let Some(first) = clusters.first() else {
+        error!("There are no clusters to manage.");
+        return crate::commands::err();
+    };
+    // The clusters share the manager's context, which holds the command line arguments:
+    let context = Arc::clone(&first.context);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let (clusters_txs, clusters_rxs): (Vec<_>, Vec<_>) = clusters
+        .into_iter()
+        .map(|cluster| watch::channel(Arc::new(cluster)))
+        .unzip();
 
     let manager_rt = tokio::runtime::Runtime::new()
-        .inspect_err(|e| eprintln!("Could not launch manager runtime: {e}"))?;
+        .inspect_err(|e| error!("Could not launch manager runtime: {e}"))?;
 
     let cli_rt = tokio::runtime::Runtime::new()
-        .inspect_err(|e| eprintln!("Could not launch CLI server runtime: {e}"))?;
+        .inspect_err(|e| error!("Could not launch CLI server runtime: {e}"))?;
+
+    // The metrics listener is bound before anything else is started, so that if it can't be,
+    // there is nothing to clean up:
+    let metrics_listener = context
+        .args
+        .metrics_addr
+        .map(|metrics_addr| {
+            cli_rt
+                .block_on(tokio::net::TcpListener::bind(metrics_addr))
+                .inspect(|_| info!("serving metrics on {metrics_addr}"))
+                .inspect_err(|e| error!("Could not listen for metrics on {metrics_addr}: {e}"))
+        })
+        .transpose()?;
+
+    cli_rt
+        .block_on(handle_shutdown_signals(shutdown_tx))
+        .inspect_err(|e| error!("Could not install signal handlers: {e}"))?;
+
+    // The socket is bound before the manager thread is started, since binding it briefly changes
+    // the process's umask, which would otherwise apply to whatever that thread creates meanwhile:
+    let addr = context
+        .args
+        .socket
+        .clone()
+        .unwrap_or_else(crate::default_socket);
+    let group = context
+        .args
+        .socket_group
+        .clone()
+        .or_else(crate::default_socket_group);
+    let listener = cli_rt.block_on(prepare_unix_socket(&addr, group.as_deref()))?;
+    info!("listening on socket '{addr}'");
 
     std::thread::scope(|s| {
         // Launch the Management thread:
+        let manager_shutdown = shutdown_rx.clone();
         s.spawn(|| {
             manager_rt.block_on(async {
-                manager_main(Arc::clone(&cluster)).await;
+                for (i, clusters_tx) in clusters_txs.into_iter().enumerate() {
+                    let others = clusters_rxs
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .map(|(_, others)| others.clone())
+                        .collect();
+                    if let Err(e) = handle_reload_signals(clusters_tx, others) {
+                        error!("Could not install signal handler for reloading the config: {e}");
+                    }
+                }
+                let managers = clusters_rxs
+                    .iter()
+                    .map(|clusters| manager_main(clusters.clone(), manager_shutdown.clone()));
+                futures::future::join_all(managers).await;
             });
         });
 
         // Launch the CLI Server process to listen for CLI commands:
         cli_rt.block_on(async {
-            let addr = match &cluster.context.args.socket {
-                Some(s) => s,
-                None => &crate::default_socket(),
-            };
-            let listener = match prepare_unix_socket(addr).await {
-                Ok(l) => l,
-                Err(_) => {
-                    std::process::exit(1);
-                }
-            };
-            if cluster.context.args.verbose {
-                eprintln!("listening on socket '{addr}'");
+            futures::join!(
+                server_main(listener, clusters_rxs.clone(), shutdown_rx.clone()),
+                crate::metrics::serve(metrics_listener, clusters_rxs.clone(), shutdown_rx),
+            );
+
+            if let Err(e) = std::fs::remove_file(&addr) {
+                error!("Could not remove socket '{addr}': {e}");
             }
-            server_main(listener, Arc::clone(&cluster)).await;
         })
     });
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_log() {
+        let config = format!("{}/tests/simple.toml", env!("CARGO_MANIFEST_DIR"));
+        let cluster = cluster::Cluster::from_config(config).unwrap();
+        let res = cluster.resources().next().unwrap();
+        let transitions = [
+            (ResourceStatus::Unknown, ResourceStatus::Stopped),
+            (ResourceStatus::Stopped, ResourceStatus::RunningOnHome),
+            (ResourceStatus::RunningOnHome, ResourceStatus::Stopped),
+        ];
+        for (_, new) in transitions {
+            res.set_status_because(new, "test");
+        }
+        let expected: Vec<String> = transitions
+            .iter()
+            .map(|(old, new)| res.status_update_string(*old, *new))
+            .collect();
+
+        let (_tx, clusters) = watch::channel(Arc::new(cluster));
+        let client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl {
+            clusters: vec![clusters],
+        });
+
+        // Get up to `lines` lines after line `after`, and the number to pass as `after` next time.
+        let tail = |lines, after| {
+            let mut request = client.tail_log_request();
+            request.get().set_lines(lines);
+            request.get().set_after(after);
+            let reply = futures::executor::block_on(request.send().promise).unwrap();
+            let reply = reply.get().unwrap();
+            let lines: Vec<String> = reply
+                .get_lines()
+                .unwrap()
+                .iter()
+                .map(|line| line.unwrap().to_str().unwrap().to_string())
+                .collect();
+            (lines, reply.get_next())
+        };
+
+        let (lines, next) = tail(2, 0);
+        assert_eq!(lines, expected[1..]);
+        assert_eq!(next, 3);
+
+        // Nothing has happened since:
+        assert_eq!(tail(2, next), (Vec::new(), 3));
+        assert_eq!(tail(10, 1), (expected[1..].to_vec(), 3));
+    }
+
+    #[test]
+    fn monitor_groups() {
+        let config = format!("{}/tests/simple.toml", env!("CARGO_MANIFEST_DIR"));
+        let cluster = cluster::Cluster::from_config(config).unwrap();
+        for res in cluster.resources() {
+            let status = match res.id.as_str() {
+                "test_mdt" => ResourceStatus::Stopped,
+                _ => ResourceStatus::RunningOnHome,
+            };
+            res.set_status_because(status, "test");
+        }
+        let (_tx, clusters) = watch::channel(Arc::new(cluster));
+        let client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl {
+            clusters: vec![clusters],
+        });
+
+        let reply = futures::executor::block_on(client.monitor_request().send().promise).unwrap();
+        let status = reply.get().unwrap().get_status().unwrap();
+
+        let mut resources: Vec<(String, String)> = status
+            .get_resources()
+            .unwrap()
+            .iter()
+            .map(|res| {
+                let text = |t: capnp::Result<capnp::text::Reader>| {
+                    t.unwrap().to_str().unwrap().to_string()
+                };
+                (text(res.get_id()), text(res.get_parent()))
+            })
+            .collect();
+        resources.sort();
+        assert_eq!(
+            resources,
+            [
+                ("test_mdt", "test_zpool"),
+                ("test_mgt", "test_zpool"),
+                ("test_ost", "test_zpool"),
+                ("test_zpool", ""),
+            ]
+            .map(|(id, parent)| (id.to_string(), parent.to_string()))
+        );
+
+        let groups = status.get_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups.get(0).get_id().unwrap().to_str().unwrap(),
+            "test_zpool"
+        );
+        // The worst status of the group's resources:
+        assert_eq!(
+            groups.get(0).get_status().unwrap(),
+            halo_mgmt::Status::Stopped
+        );
+    }
+
+    #[test]
+    fn maintenance_refuses_actions() {
+        let config = format!("{}/tests/simple.toml", env!("CARGO_MANIFEST_DIR"));
+        let cluster = cluster::Cluster::from_config(config).unwrap();
+        let res = cluster.get_resource("test_ost").unwrap();
+        res.home_node.set_maintenance(true);
+        let (_tx, clusters) = watch::channel(Arc::new(cluster));
+        let client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl {
+            clusters: vec![clusters],
+        });
+
+        let mut request = client.start_resource_request();
+        request.get().set_id("test_ost");
+        let error = futures::executor::block_on(request.send().promise).unwrap_err();
+        assert!(error.to_string().contains("in maintenance"));
+
+        let mut request = client.stop_resource_request();
+        request.get().set_id("test_ost");
+        let error = futures::executor::block_on(request.send().promise).unwrap_err();
+        assert!(error.to_string().contains("in maintenance"));
+
+        let mut request = client.relocate_request();
+        request.get().set_group("test_zpool");
+        request.get().set_to(halo_mgmt::Destination::Home);
+        let error = futures::executor::block_on(request.send().promise).unwrap_err();
+        assert!(error.to_string().contains("in maintenance"));
+    }
+
+    #[test]
+    fn monitor_clusters() {
+        let config = |name| format!("{}/tests/{name}.toml", env!("CARGO_MANIFEST_DIR"));
+        let args = crate::commands::Cli {
+            config: vec![config("simple"), config("reload")],
+            ..Default::default()
+        };
+        let context = Arc::new(MgrContext::new(args));
+        let clusters = load_clusters(Arc::clone(&context)).unwrap();
+        let receivers: Vec<_> = clusters
+            .into_iter()
+            .map(|cluster| watch::channel(Arc::new(cluster)).1)
+            .collect();
+        let client: halo_mgmt::Client = capnp_rpc::new_client(HaloMgmtImpl {
+            clusters: receivers,
+        });
+
+        let reply = futures::executor::block_on(client.monitor_request().send().promise).unwrap();
+        let status = reply.get().unwrap().get_status().unwrap();
+
+        let text = |t: capnp::Result<capnp::text::Reader>| t.unwrap().to_str().unwrap().to_string();
+        let mut resources: Vec<(String, String)> = status
+            .get_resources()
+            .unwrap()
+            .iter()
+            .map(|res| (text(res.get_cluster()), text(res.get_id())))
+            .collect();
+        resources.sort();
+        assert_eq!(
+            resources,
+            [
+                ("reload", "test_zpool_a"),
+                ("simple", "test_mdt"),
+                ("simple", "test_mgt"),
+                ("simple", "test_ost"),
+                ("simple", "test_zpool"),
+            ]
+            .map(|(cluster, id)| (cluster.to_string(), id.to_string()))
+        );
+
+        let mut groups: Vec<(String, String)> = status
+            .get_groups()
+            .unwrap()
+            .iter()
+            .map(|group| (text(group.get_cluster()), text(group.get_id())))
+            .collect();
+        groups.sort();
+        assert_eq!(
+            groups,
+            [("reload", "test_zpool_a"), ("simple", "test_zpool")]
+                .map(|(cluster, id)| (cluster.to_string(), id.to_string()))
+        );
+
+        // The same cluster can't be managed twice:
+        let args = crate::commands::Cli {
+            config: vec![config("simple"), config("simple")],
+            ..Default::default()
+        };
+        assert!(load_clusters(Arc::new(MgrContext::new(args))).is_err());
+
+        // Nor can a host be in two clusters:
+        let shared = std::env::temp_dir().join(format!("halo_shared_{}.toml", std::process::id()));
+        std::fs::write(
+            &shared,
+            r#"
+[[hosts]]
+hostname = "127.0.0.1"
+
+  [hosts.resources.other_zpool]
+  kind = "heartbeat/ZFS"
+
+    [hosts.resources.other_zpool.parameters]
+    pool = "other_zpool"
+"#,
+        )
+        .unwrap();
+        let args = crate::commands::Cli {
+            config: vec![config("simple"), shared.to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let loaded = load_clusters(Arc::new(MgrContext::new(args)));
+        std::fs::remove_file(&shared).unwrap();
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+//! metrics.rs
+//!
+//! This module serves the manager's metrics about the cluster over HTTP, in the Prometheus text
+//! exposition format, for monitoring systems to scrape.
+
+use std::{fmt::Write, sync::atomic::Ordering, sync::Arc, time::Duration};
+
+use {
+    log::{debug, error},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::watch,
+    },
+};
+
+use crate::{cluster::Cluster, host::HostStatus, resource::ResourceStatus};
+
+/// How long a client is given to send its request and read the reply, so that a stalled client
+/// can't hold up the others.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most of a request that is read. Only its first line matters.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Format the metrics for `clusters`, as of now, in the Prometheus text format:
+///
+///     halo_resources_total{cluster="lustre",status="RunningOnHome"} 4
+///     halo_hosts_total{cluster="lustre",status="Up"} 2
+///     halo_failovers_total 1
+///
+/// Every status is given for each cluster, even those that no resource or host has, so that each
+/// series is always present. The failovers are counted over all of the clusters.
+pub fn render(clusters: &[Arc<Cluster>]) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP halo_resources_total The number of resources with each status.\n");
+    out.push_str("# TYPE halo_resources_total gauge\n");
+    for cluster in clusters {
+        for status in ResourceStatus::ALL {
+            let count = cluster
+                .resources()
+                .filter(|r| r.get_status() == status)
+                .count();
+            let _ = writeln!(
+                out,
+                "halo_resources_total{{cluster=\"{}\",status=\"{status:?}\"}} {count}",
+                cluster.name()
+            );
+        }
+    }
+
+    out.push_str("# HELP halo_hosts_total The number of hosts with each status.\n");
+    out.push_str("# TYPE halo_hosts_total gauge\n");
+    for cluster in clusters {
+        for status in [HostStatus::Up, HostStatus::Down, HostStatus::Unknown] {
+            let count = cluster.hosts().filter(|h| h.get_status() == status).count();
+            let _ = writeln!(
+                out,
+                "halo_hosts_total{{cluster=\"{}\",status=\"{status:?}\"}} {count}",
+                cluster.name()
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP halo_failovers_total The number of times a resource group has been failed over.\n",
+    );
+    out.push_str("# TYPE halo_failovers_total counter\n");
+    // The clusters share the manager's context, which counts the failovers in all of them:
+    let failovers = clusters
+        .first()
+        .map_or(0, |c| c.context.failovers.load(Ordering::Relaxed));
+    let _ = writeln!(out, "halo_failovers_total {failovers}");
+
+    out
+}
+
+/// Serve the metrics for the running clusters on `listener` until `shutdown` is signalled. If there
+/// is no listener, because metrics weren't asked for, this returns at once.
+pub async fn serve(
+    listener: Option<TcpListener>,
+    clusters: Vec<watch::Receiver<Arc<Cluster>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let Some(listener) = listener else {
+        return;
+    };
+
+    loop {
+        let accepted = tokio::select! {
+            _ = shutdown.changed() => break,
+            accepted = listener.accept() => accepted,
+        };
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                error!("Could not accept metrics connection: {e}");
+                continue;
+            }
+        };
+
+        // The reply is small, so each client is served in turn:
+        let current: Vec<Arc<Cluster>> = clusters.iter().map(|c| Arc::clone(&c.borrow())).collect();
+        match tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &current)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => debug!("Could not serve metrics: {e}"),
+            Err(_) => debug!("Timed out serving metrics"),
+        }
+    }
+}
+
+/// Read an HTTP request from `stream`, and reply with the metrics if it asks for them.
+async fn respond(mut stream: TcpStream, clusters: &[Arc<Cluster>]) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics" | "/")) => ("200 OK", render(clusters)),
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "only GET is allowed\n".to_string(),
+        ),
+    };
+
+    let reply = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    #[test]
+    fn scrape() {
+        let config = format!("{}/tests/simple.toml", env!("CARGO_MANIFEST_DIR"));
+        let cluster = Cluster::from_config(config).unwrap();
+        let num_resources = cluster.resources().count();
+        cluster
+            .get_resource("test_ost")
+            .unwrap()
+            .set_status_because(ResourceStatus::Stopped, "test");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let (_clusters_tx, clusters) = watch::channel(Arc::new(cluster));
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+                serve(Some(listener), vec![clusters], shutdown).await;
+            });
+        });
+
+        let get = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        };
+
+        let reply = get("/metrics");
+        let (head, body) = reply.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for line in body.lines() {
+            let Some(rest) = line.strip_prefix("halo_resources_total{cluster=\"simple\",status=\"")
+            else {
+                continue;
+            };
+            let (status, count) = rest.split_once("\"} ").unwrap();
+            counts.push((status.to_string(), count.parse().unwrap()));
+        }
+        assert_eq!(counts.len(), ResourceStatus::ALL.len());
+        assert_eq!(counts.iter().map(|(_, n)| n).sum::<usize>(), num_resources);
+        assert!(counts.contains(&("Stopped".to_string(), 1)));
+        assert!(counts.contains(&("Unknown".to_string(), num_resources - 1)));
+        assert!(body.contains("halo_hosts_total{cluster=\"simple\",status=\"Unknown\"} 1\n"));
+        assert!(body.contains("halo_failovers_total 0\n"));
+
+        assert!(get("/other").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+//! discover.rs
+//!
+//! This module finds the resources (zpools and lustre targets) running on the agent's host, so
+//! that they can be reported to `halo discover`.
+
+use std::{io, process::Command};
+
+/// Get the names of the zpools imported on this host.
+pub fn zpools(cli: &crate::remote::Cli) -> io::Result<Vec<String>> {
+    run(cli, "zpool", &["list", "-H", "-o", "name"])
+}
+
+/// Get the lines of `mount -t lustre` output, one for each lustre target mounted on this host.
+pub fn lustre_mounts(cli: &crate::remote::Cli) -> io::Result<Vec<String>> {
+    run(cli, "mount", &["-t", "lustre"])
+}
+
+/// Run a discovery command, returning the lines of its output.
+fn run(cli: &crate::remote::Cli, program: &str, args: &[&str]) -> io::Result<Vec<String>> {
+    let program = match &cli.discover_bin_dir {
+        Some(dir) => format!("{dir}/{program}"),
+        None => program.to_string(),
+    };
+
+    let output = Command::new(&program).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{program} failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.to_string())
+        .collect())
+}
@@ -1,35 +1,121 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{error::Error, net::Ipv4Addr, str::FromStr};
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    rc::Rc,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use {
     capnp::capability::Promise,
     capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem},
     clap::Parser,
-    futures::AsyncReadExt,
+    futures::{future, AsyncReadExt},
+    log::{debug, error, info, log_enabled, warn, Level},
     nix::ifaddrs,
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::{OwnedSemaphorePermit, Semaphore},
+        task::JoinHandle,
+    },
 };
 
-use crate::{halo_capnp::ocf_resource_agent, tls::get_acceptor};
+use crate::{
+    halo_capnp::ocf_resource_agent,
+    tls::{get_acceptor, get_acceptor_server_only, is_client_allowed, TlsMode, TlsPaths},
+};
 
+pub mod discover;
 pub mod ocf;
+pub mod pid_file;
 
 struct OcfResourceAgentImpl {
-    cli: Cli,
+    cli: Arc<Cli>,
+
+    /// When the agent started, for reporting its uptime.
+    start_time: Instant,
+
+    /// The activity on the connection that this serves, which each request is noted in.
+    activity: Rc<Activity>,
+}
+
+impl OcfResourceAgentImpl {
+    fn new(cli: Cli) -> Self {
+        Self {
+            cli: Arc::new(cli),
+            start_time: Instant::now(),
+            activity: Rc::new(Activity::new()),
+        }
+    }
+
+    /// A server for a single connection, sharing this one's settings, whose requests are noted in
+    /// `activity`.
+    fn for_connection(&self, activity: Rc<Activity>) -> ocf_resource_agent::Client {
+        capnp_rpc::new_client(Self {
+            cli: Arc::clone(&self.cli),
+            start_time: self.start_time,
+            activity,
+        })
+    }
+
+    /// Run `f`, which may block for as long as a resource agent takes, on a thread of its own, so
+    /// that other requests, such as pings, are still answered meanwhile. The connection isn't
+    /// taken to be idle until `f` has finished.
+    fn run_blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Cli) -> T + Send + 'static,
+    ) -> impl Future<Output = Result<T, capnp::Error>> {
+        let cli = Arc::clone(&self.cli);
+        let in_flight = InFlight::new(Rc::clone(&self.activity));
+        async move {
+            let result = tokio::task::spawn_blocking(move || f(&cli)).await;
+            drop(in_flight);
+            result.map_err(|e| capnp::Error::failed(format!("request failed: {e}")))
+        }
+    }
 }
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
-    /// If a CIDR network is specified, the agent will only listen on an IP address in that
-    /// network, or will fail to start if there is no such IP address.
+    /// If a CIDR network, IPv4 or IPv6, is specified, the agent will only listen on an IP address
+    /// in that network, or will fail to start if there is no such IP address.
     #[arg(long)]
     pub network: Option<String>,
 
     #[arg(long)]
     pub port: Option<u16>,
 
+    /// Listen on this address, given as "<ip>[:<port number>]", rather than on an address found in
+    /// the network given by --network. The address must belong to this host. If no port number is
+    /// given, the one from --port is used.
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "network")]
+    pub bind_address: Option<String>,
+
+    /// Listen on every address of this host in the network given by --network, rather than only
+    /// the first one found. This is for hosts with more than one interface in the network, which
+    /// the manager may reach the agent through.
+    #[arg(long, conflicts_with = "bind_address")]
+    pub bind_all: bool,
+
+    /// Listen on exactly this address, given as "<ip>:<port number>", in place of --network and
+    /// --port (and HALO_NET and HALO_PORT). The address must belong to this host. This is handy
+    /// for running several agents on one host.
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with_all = ["network", "port", "bind_address", "bind_all"]
+    )]
+    pub listen: Option<String>,
+
     #[arg(short, long)]
     pub verbose: bool,
 
@@ -42,106 +128,559 @@ pub struct Cli {
     #[arg(long)]
     pub ocf_root: Option<String>,
 
+    /// The resource kinds, such as "heartbeat/ZFS", that the agent may run operations on,
+    /// separated by commas. Operations on any other kind fail with OCF_ERR_PERM. If not given,
+    /// any kind under the OCF root may be run.
+    #[arg(long, value_delimiter = ',', value_name = "KIND")]
+    pub allowed_resources: Vec<String>,
+
     ///Enable mTLS, must also be enabled on client side to function
     #[arg(long)]
     pub mtls: bool,
+
+    /// Enable TLS without requiring a certificate from the client. The client must also enable
+    /// TLS, and verifies the agent's certificate against the CA.
+    #[arg(long, conflicts_with = "mtls")]
+    pub tls: bool,
+
+    /// The agent's TLS certificate. Defaults to $HALO_SERVER_CERT, or /etc/halo/server.crt.
+    #[arg(long)]
+    pub server_cert: Option<String>,
+
+    /// The agent's TLS private key. Defaults to $HALO_SERVER_KEY, or /etc/halo/server.key.
+    #[arg(long)]
+    pub server_key: Option<String>,
+
+    /// The certificate of the CA that signs the manager's certificate. Defaults to $HALO_CA_CERT,
+    /// or /etc/halo/ca.crt.
+    #[arg(long)]
+    pub ca_cert: Option<String>,
+
+    /// With mTLS, only accept connections from clients whose certificate has this common name or
+    /// DNS subject alternative name. May be given multiple times. If not given, any client with a
+    /// certificate signed by the CA is accepted.
+    #[arg(long = "allowed-client", value_name = "NAME")]
+    pub allowed_clients: Vec<String>,
+
+    /// How long, in seconds, a resource agent operation may run before it is killed. A timeout
+    /// passed by the manager for a particular operation takes precedence over this.
+    #[arg(long)]
+    pub op_timeout: Option<u64>,
+
+    /// The most connections that the agent serves at once. Further connections wait until one of
+    /// the open connections is closed. At least one connection must be allowed.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_CONNECTIONS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub max_connections: usize,
+
+    /// How long, in seconds, a connection may go without a request or a reply before the agent
+    /// closes it, so that clients which have gone away don't hold connections open. A connection
+    /// is never closed while one of its requests is still being handled. Set this to 0 to never
+    /// close idle connections.
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_IDLE_TIMEOUT)]
+    pub idle_timeout: u64,
+
+    /// The file to write the agent's PID to. The agent refuses to start if a running instance
+    /// already holds it, and removes it when it shuts down. Defaults to $HALO_PID_FILE, or
+    /// /var/run/halo-remote.pid.
+    #[arg(long, value_name = "PATH")]
+    pub pid_file: Option<String>,
+
+    /// Don't write a PID file, nor check for another running instance.
+    #[arg(long, conflicts_with = "pid_file")]
+    pub no_pid_file: bool,
+
+    /// For the test environment, the directory holding the `zpool` and `mount` commands that are
+    /// run to discover the resources on this host.
+    #[arg(long, hide = true)]
+    pub discover_bin_dir: Option<String>,
 }
 
-/// Launches the remote agent, which listens on an IP address in `network` using `port`.
+/// The default for the most connections that the agent serves at once.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// The default for how long, in seconds, a connection may be idle before the agent closes it.
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 300;
+
+/// The longest that the agent waits between checks for idle connections.
+const MAX_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Cli {
+    /// How connections to the agent are secured.
+    pub fn tls_mode(&self) -> TlsMode {
+        TlsMode::from_flags(self.tls, self.mtls)
+    }
+
+    /// Get the paths of the files used for TLS, falling back to the defaults for any that were
+    /// not given on the command line.
+    pub fn tls_paths(&self) -> TlsPaths {
+        TlsPaths {
+            cert: self
+                .server_cert
+                .clone()
+                .unwrap_or_else(crate::default_server_cert),
+            key: self
+                .server_key
+                .clone()
+                .unwrap_or_else(crate::default_server_key),
+            ca_cert: self.ca_cert.clone().unwrap_or_else(crate::default_ca_cert),
+        }
+    }
+}
+
+/// Launches the remote agent, which listens on an IP address in `network` using `port`, or on
+/// every such address with `--bind-all`, unless it is told exactly where to listen with
+/// `--listen`.
 pub fn agent_main(args: Cli) -> Result<(), Box<dyn Error>> {
-    crate::test_env::maybe_identify_agent_for_test_fence(&args);
-
-    let network = args.network.clone().unwrap_or(crate::default_network());
-    let network = cidr::Ipv4Cidr::from_str(&network).unwrap();
-    let port = args.port.unwrap_or(crate::remote_port());
-    let addr = match get_listening_address(network) {
-        Some(addr) => addr,
-        None => {
-            eprintln!("Could not find address matching {} to listen on.", network);
-            eprintln!("Try specifying management network in environment as HALO_NET=$net.");
-            return Err(From::from(std::io::Error::from(
-                std::io::ErrorKind::AddrNotAvailable,
-            )));
+    // HALO_PORT is only read when it's needed, so that it's ignored along with --port when
+    // --listen is given:
+    let port = || args.port.unwrap_or_else(crate::remote_port);
+    let addrs = match (&args.listen, &args.bind_address) {
+        (Some(listen), _) => vec![get_listen_address(listen)?],
+        (None, Some(bind_address)) => vec![get_bind_address(bind_address, port())?],
+        (None, None) => {
+            let network = args.network.clone().unwrap_or(crate::default_network());
+            let network = cidr::IpCidr::from_str(&network).inspect_err(|e| {
+                error!("Could not parse network \"{network}\" as an IPv4 or IPv6 CIDR: {e}")
+            })?;
+            let mut addrs = get_listening_addresses(network);
+            if !args.bind_all {
+                addrs.truncate(1);
+            }
+            if addrs.is_empty() {
+                error!("Could not find address matching {} to listen on.", network);
+                error!("Try specifying management network in environment as HALO_NET=$net, or an address to listen on with --bind-address.");
+                return Err(From::from(std::io::Error::from(
+                    std::io::ErrorKind::AddrNotAvailable,
+                )));
+            }
+            addrs
+                .into_iter()
+                .map(|addr| SocketAddr::new(addr, port()))
+                .collect()
         }
     };
 
-    let addr = format!("{addr}:{}", port);
+    // The PID file is held until the agent shuts down:
+    let _pid_file = match args.no_pid_file {
+        true => None,
+        false => {
+            let path = args
+                .pid_file
+                .clone()
+                .unwrap_or_else(crate::default_pid_file);
+            Some(
+                pid_file::PidFile::acquire(&path)
+                    .inspect_err(|e| error!("Could not take PID file \"{path}\": {e}"))?,
+            )
+        }
+    };
 
     let rt = tokio::runtime::Runtime::new().expect("Failed to launch runtime.");
-    rt.block_on(async { __agent_main(args, &addr).await })?;
+    rt.block_on(async { __agent_main(args, &addrs).await })?;
 
     Ok(())
 }
 
-/// Given a `network` in CIDR form, tries to find an IP address on the system in that network.
-fn get_listening_address(network: cidr::Ipv4Cidr) -> Option<Ipv4Addr> {
+/// Parse an address given with --bind-address, of the form "<ip>[:<port number>]", using `port`
+/// if it has no port number of its own, and check that it belongs to this host.
+///
+/// The unspecified address ("0.0.0.0" or "::") is accepted too, to listen on every interface.
+fn get_bind_address(bind_address: &str, port: u16) -> Result<SocketAddr, Box<dyn Error>> {
+    let addr = match SocketAddr::from_str(bind_address) {
+        Ok(addr) => addr,
+        Err(_) => match IpAddr::from_str(bind_address) {
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => {
+                error!(
+                    "Invalid bind address \"{bind_address}\": expected \"<ip>[:<port number>]\"."
+                );
+                return Err(From::from(std::io::Error::from(
+                    std::io::ErrorKind::InvalidInput,
+                )));
+            }
+        },
+    };
+
+    check_local_address(addr, "--bind-address")
+}
+
+/// Parse an address given with --listen, of the form "<ip>:<port number>", and check that it
+/// belongs to this host.
+fn get_listen_address(listen: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    let Ok(addr) = SocketAddr::from_str(listen) else {
+        error!("Invalid listen address \"{listen}\": expected \"<ip>:<port number>\".");
+        return Err(From::from(std::io::Error::from(
+            std::io::ErrorKind::InvalidInput,
+        )));
+    };
+
+    check_local_address(addr, "--listen")
+}
+
+/// Check that the address given with `flag` is one of this host's, or the unspecified address.
+fn check_local_address(addr: SocketAddr, flag: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    let ip = addr.ip();
+    if !ip.is_unspecified() && !local_addresses().contains(&ip) {
+        error!("Could not listen on {ip}: it is not an address of this host.");
+        error!("Try specifying one of the addresses of this host's interfaces with {flag}, or leave it out to listen on an address in the management network.");
+        return Err(From::from(std::io::Error::from(
+            std::io::ErrorKind::AddrNotAvailable,
+        )));
+    }
+
+    Ok(addr)
+}
+
+/// Given a `network` in CIDR form, finds the IP addresses on the system in that network, in the
+/// order that the system lists its interfaces.
+///
+/// Both IPv4 and IPv6 interface addresses are considered.
+fn get_listening_addresses(network: cidr::IpCidr) -> Vec<IpAddr> {
+    let mut addrs: Vec<IpAddr> = Vec::new();
+    for addr in local_addresses() {
+        if network.contains(&addr) && !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+
+    addrs
+}
+
+/// List the IP addresses, both IPv4 and IPv6, of the system's interfaces.
+fn local_addresses() -> Vec<IpAddr> {
     let ifaddrs = ifaddrs::getifaddrs().unwrap();
+    let mut addrs = Vec::new();
     for ifa in ifaddrs {
         if let Some(addr) = ifa.address {
             if let Some(addr) = addr.as_sockaddr_in() {
-                let addr = addr.ip();
-                if network.contains(&addr) {
-                    return Some(addr);
-                }
+                addrs.push(IpAddr::V4(addr.ip()));
+            } else if let Some(addr) = addr.as_sockaddr_in6() {
+                addrs.push(IpAddr::V6(addr.ip()));
             }
         }
     }
 
-    None
+    addrs
 }
 
-async fn __agent_main(args: Cli, addr: &str) -> Result<(), Box<dyn Error>> {
-    let mtls = args.mtls;
+async fn __agent_main(args: Cli, addrs: &[SocketAddr]) -> Result<(), Box<dyn Error>> {
+    if args.tls_mode() != TlsMode::None {
+        crate::tls::check_server_cert(&args.tls_paths())
+            .inspect_err(|e| error!("Could not start with TLS: {e}"))?;
+    }
     tokio::task::LocalSet::new()
         .run_until(async move {
-            let listener = tokio::net::TcpListener::bind(addr)
-                .await
-                .inspect_err(|e| eprintln!("Could not listen on address \"{addr}\": {e}"))?;
-            if args.verbose {
-                eprintln!("Listening on {addr}");
+            let mut listeners = Vec::new();
+            for addr in addrs {
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .inspect_err(|e| error!("Could not listen on address \"{addr}\": {e}"))?;
+                info!("Listening on {addr}");
+                listeners.push(listener);
             }
 
-            let agent_client: ocf_resource_agent::Client =
-                capnp_rpc::new_client(OcfResourceAgentImpl { cli: args });
-
-            loop {
-                let (stream, _) = listener.accept().await?;
-                stream.set_nodelay(true)?;
-                if mtls {
-                    //Create mtls acceptor
-                    let mtls_acceptor = get_acceptor();
-
-                    //mTLS handshake
-                    let mtls_stream = match mtls_acceptor.accept(stream).await {
-                        Ok(s) => s,
-                        Err(e) => {
-                            return Err(format!("mTLS accept error: {}", e).into());
-                        }
-                    };
-                    __agent_rpc_main(mtls_stream, agent_client.clone());
-                } else {
-                    __agent_rpc_main(stream, agent_client.clone());
+            let mut sigterm = signal(SignalKind::terminate())?;
+            let mut sigint = signal(SignalKind::interrupt())?;
+            tokio::select! {
+                result = __agent_serve(listeners, args) => result,
+                _ = sigterm.recv() => {
+                    info!("Got SIGTERM, shutting down");
+                    Ok(())
+                }
+                _ = sigint.recv() => {
+                    info!("Got SIGINT, shutting down");
+                    Ok(())
                 }
             }
         })
         .await
 }
 
+/// Accept connections on each of `listeners` and serve the agent's RPCs on them, for as long as
+/// the agent runs. The connections on every listener are served by the same agent.
+///
+/// At most `--max-connections` connections are served at once, over all of the listeners: a
+/// permit is taken before each connection is accepted, and given back once the connection is
+/// closed, so that further clients wait in the listen backlog in the meantime. Connections which
+/// have been idle for longer than `--idle-timeout` are closed.
+///
+/// This must be called from within a LocalSet, since each connection is served by a local task.
+async fn __agent_serve(
+    listeners: Vec<tokio::net::TcpListener>,
+    args: Cli,
+) -> Result<(), Box<dyn Error>> {
+    let tls_mode = args.tls_mode();
+    let tls_paths = args.tls_paths();
+    let allowed_clients = args.allowed_clients.clone();
+    let permits = Arc::new(Semaphore::new(args.max_connections));
+    let idle_timeout = Duration::from_secs(args.idle_timeout);
+    let connections = RefCell::new(Vec::new());
+
+    let agent = OcfResourceAgentImpl::new(args);
+
+    let accept_loops = listeners.into_iter().map(|listener| {
+        __agent_accept_loop(
+            listener,
+            &agent,
+            Arc::clone(&permits),
+            tls_mode,
+            &tls_paths,
+            &allowed_clients,
+            &connections,
+        )
+    });
+    tokio::select! {
+        result = future::try_join_all(accept_loops) => {
+            result?;
+        }
+        _ = __reap_idle_connections(&connections, idle_timeout) => {}
+    }
+
+    Ok(())
+}
+
+/// Close any of `connections` that have been idle for longer than `idle_timeout`, and forget
+/// those that have been closed by the client. A connection with a request still being handled is
+/// not idle. This runs for as long as the agent does; if `idle_timeout` is zero, idle connections
+/// are never closed.
+async fn __reap_idle_connections(connections: &RefCell<Vec<Connection>>, idle_timeout: Duration) {
+    if idle_timeout.is_zero() {
+        return future::pending().await;
+    }
+
+    let check_interval = idle_timeout.min(MAX_IDLE_CHECK_INTERVAL);
+    loop {
+        tokio::time::sleep(check_interval).await;
+        connections.borrow_mut().retain(|connection| {
+            if connection.task.is_finished() {
+                return false;
+            }
+            let Some(idle) = connection.activity.idle_for() else {
+                return true;
+            };
+            if idle < idle_timeout {
+                return true;
+            }
+            info!(
+                "Closing connection that has been idle for {}s",
+                idle.as_secs()
+            );
+            // Aborting the task drops its RPC system, which closes the connection and gives its
+            // permit back:
+            connection.task.abort();
+            false
+        });
+    }
+}
+
+/// Accept connections on a single listener, taking a permit from `permits` for each one.
+async fn __agent_accept_loop(
+    listener: tokio::net::TcpListener,
+    agent: &OcfResourceAgentImpl,
+    permits: Arc<Semaphore>,
+    tls_mode: TlsMode,
+    tls_paths: &TlsPaths,
+    allowed_clients: &[String],
+    connections: &RefCell<Vec<Connection>>,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let permit = Arc::clone(&permits).acquire_owned().await?;
+        let (stream, _) = listener.accept().await?;
+        stream.set_nodelay(true)?;
+
+        //Create TLS acceptor. If that fails, refuse the connection, but keep
+        //listening--the certificates may be fixed without restarting the agent.
+        let acceptor = match tls_mode {
+            TlsMode::None => {
+                let connection = __agent_rpc_main(stream, agent, permit);
+                connections.borrow_mut().push(connection);
+                continue;
+            }
+            TlsMode::Tls => get_acceptor_server_only(tls_paths),
+            TlsMode::Mtls => get_acceptor(tls_paths),
+        };
+        let acceptor = match acceptor {
+            Ok(a) => a,
+            Err(e) => {
+                error!("Could not set up TLS, refusing connection: {e}");
+                continue;
+            }
+        };
+
+        //TLS handshake
+        let tls_stream = match acceptor.accept(stream).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("TLS accept error: {e}");
+                continue;
+            }
+        };
+
+        if tls_mode == TlsMode::Mtls {
+            let client_cert = tls_stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first());
+            let allowed = match client_cert {
+                Some(cert) => is_client_allowed(cert, allowed_clients),
+                None => false,
+            };
+            if !allowed {
+                warn!("Refusing connection from client that is not allowed");
+                continue;
+            }
+        }
+
+        let connection = __agent_rpc_main(tls_stream, agent, permit);
+        connections.borrow_mut().push(connection);
+    }
+}
+
+/// A connection that the agent is serving.
+struct Connection {
+    /// The task serving the connection's RPCs. Aborting it closes the connection.
+    task: JoinHandle<()>,
+
+    /// What has happened on the connection lately.
+    activity: Rc<Activity>,
+}
+
+/// What has happened on a connection lately, for deciding whether it is idle.
+struct Activity {
+    /// When anything was last read from or written to the connection, or a request on it was
+    /// started or finished.
+    last: Cell<Instant>,
+
+    /// How many requests on the connection are still being handled; see InFlight.
+    in_flight: Cell<usize>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            last: Cell::new(Instant::now()),
+            in_flight: Cell::new(0),
+        }
+    }
+
+    /// Note that something happened on the connection.
+    fn touch(&self) {
+        self.last.set(Instant::now());
+    }
+
+    /// How long the connection has been idle, or None if a request on it is still being handled.
+    fn idle_for(&self) -> Option<Duration> {
+        match self.in_flight.get() {
+            0 => Some(self.last.get().elapsed()),
+            _ => None,
+        }
+    }
+}
+
+/// A request which is still being handled, off the RPC thread, on a connection whose Activity
+/// this holds. The connection isn't idle until it is dropped.
+struct InFlight(Rc<Activity>);
+
+impl InFlight {
+    fn new(activity: Rc<Activity>) -> Self {
+        activity.touch();
+        activity.in_flight.set(activity.in_flight.get() + 1);
+        Self(activity)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.in_flight.set(self.0.in_flight.get() - 1);
+    }
+}
+
+/// One half of a connection's stream, which notes in its Activity whenever anything is read from
+/// or written to it.
+struct ActivityStream<S> {
+    inner: S,
+    activity: Rc<Activity>,
+}
+
+impl<R: futures::AsyncRead + Unpin> futures::AsyncRead for ActivityStream<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        result
+    }
+}
+
+impl<W: futures::AsyncWrite + Unpin> futures::AsyncWrite for ActivityStream<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Serve the agent's RPCs on `stream` in a local task, holding `permit` until the connection is
+/// closed.
 fn __agent_rpc_main<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + 'static>(
     stream: S,
-    agent_client: ocf_resource_agent::Client,
-) {
+    agent: &OcfResourceAgentImpl,
+    permit: OwnedSemaphorePermit,
+) -> Connection {
     let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let activity = Rc::new(Activity::new());
+    let reader = ActivityStream {
+        inner: reader,
+        activity: Rc::clone(&activity),
+    };
+    let writer = ActivityStream {
+        inner: writer,
+        activity: Rc::clone(&activity),
+    };
     let network = twoparty::VatNetwork::new(
         futures::io::BufReader::new(reader),
         futures::io::BufWriter::new(writer),
         rpc_twoparty_capnp::Side::Server,
-        Default::default(),
+        crate::halo_capnp::RPC_READER_OPTIONS,
     );
 
+    let agent_client = agent.for_connection(Rc::clone(&activity));
     let rpc_system = RpcSystem::new(Box::new(network), Some(agent_client.client));
 
-    tokio::task::spawn_local(rpc_system);
+    let task = tokio::task::spawn_local(async move {
+        let _ = rpc_system.await;
+        drop(permit);
+    });
+
+    Connection { task, activity }
 }
 
 impl ocf_resource_agent::Server for OcfResourceAgentImpl {
@@ -159,40 +698,744 @@ impl ocf_resource_agent::Server for OcfResourceAgentImpl {
             ocf_resource_agent::Operation::Monitor => ocf::Operation::Monitor,
             ocf_resource_agent::Operation::Start => ocf::Operation::Start,
             ocf_resource_agent::Operation::Stop => ocf::Operation::Stop,
+            ocf_resource_agent::Operation::ValidateAll => ocf::Operation::ValidateAll,
+            ocf_resource_agent::Operation::MetaData => ocf::Operation::MetaData,
         };
 
-        let args = pry!(params.get_args());
-        let mut ocf_args: Vec<(&str, &str)> = Vec::new();
-        for i in 0..args.len() {
-            let arg = args.get(i);
-            let key = pry!(pry!(arg.get_key()).to_str());
-            let value = pry!(pry!(arg.get_value()).to_str());
-            ocf_args.push((key, value));
-        }
+        let ocf_args = pry!(read_arguments(pry!(params.get_args())));
         let ocf_args = ocf::Arguments::from(&ocf_args);
 
-        if self.cli.verbose {
+        if log_enabled!(Level::Debug) {
             log_operation(&op, &ocf_args);
         }
 
-        match ocf::do_operation(resource, op, &ocf_args, &self.cli) {
-            Ok(s) => {
-                pry!(results.get().get_result()).set_ok(s);
+        let is_meta_data = matches!(op, ocf::Operation::MetaData);
+        let resource = resource.to_string();
+        let output = self.run_blocking(move |cli| ocf::do_operation(&resource, op, &ocf_args, cli));
+
+        Promise::from_future(async move {
+            match output.await? {
+                Ok(output) => {
+                    let mut result = results.get().get_result()?;
+                    result.set_ok(output.code);
+                    result.set_stderr(output.stderr);
+                    if is_meta_data {
+                        result.set_stdout(output.stdout);
+                    }
+                }
+                Err(e) => {
+                    results.get().get_result()?.set_err(format!("{e}"));
+                }
+            };
+            Ok(())
+        })
+    }
+
+    fn discover(
+        &mut self,
+        _params: ocf_resource_agent::DiscoverParams,
+        mut results: ocf_resource_agent::DiscoverResults,
+    ) -> Promise<(), ::capnp::Error> {
+        debug!("Got discover request");
+
+        let zpools = match discover::zpools(&self.cli) {
+            Ok(zpools) => zpools,
+            Err(e) => {
+                return Promise::err(capnp::Error::failed(format!("could not list zpools: {e}")))
             }
+        };
+        let lustre_mounts = match discover::lustre_mounts(&self.cli) {
+            Ok(mounts) => mounts,
             Err(e) => {
-                pry!(results.get().get_result()).set_err(format!("{e}"));
+                return Promise::err(capnp::Error::failed(format!(
+                    "could not list lustre mounts: {e}"
+                )))
             }
         };
 
+        let mut results = results.get();
+        let mut list = results.reborrow().init_zpools(zpools.len() as u32);
+        for (i, zpool) in zpools.iter().enumerate() {
+            list.set(i as u32, zpool.as_str());
+        }
+        let mut list = results.init_lustre_mounts(lustre_mounts.len() as u32);
+        for (i, mount) in lustre_mounts.iter().enumerate() {
+            list.set(i as u32, mount.as_str());
+        }
+
         Promise::ok(())
     }
+
+    fn ping(
+        &mut self,
+        _params: ocf_resource_agent::PingParams,
+        mut results: ocf_resource_agent::PingResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let mut results = results.get();
+        results.set_uptime(self.start_time.elapsed().as_secs());
+        results.set_version(env!("CARGO_PKG_VERSION"));
+
+        Promise::ok(())
+    }
+
+    fn info(
+        &mut self,
+        _params: ocf_resource_agent::InfoParams,
+        mut results: ocf_resource_agent::InfoResults,
+    ) -> Promise<(), ::capnp::Error> {
+        debug!("Got info request");
+
+        let mut results = results.get();
+        results.set_version(env!("CARGO_PKG_VERSION"));
+        results.set_uptime_secs(self.start_time.elapsed().as_secs());
+        results.set_ocf_root(ocf::ocf_root(&self.cli));
+
+        Promise::ok(())
+    }
+
+    fn list_running(
+        &mut self,
+        params: ocf_resource_agent::ListRunningParams,
+        mut results: ocf_resource_agent::ListRunningResults,
+    ) -> Promise<(), ::capnp::Error> {
+        debug!("Got list running request");
+
+        let probes = pry!(pry!(params.get()).get_resources());
+        let mut monitors: Vec<(String, ocf::Arguments)> = Vec::new();
+        for probe in probes.iter() {
+            let resource = pry!(pry!(probe.get_resource()).to_str());
+            let ocf_args = pry!(read_arguments(pry!(probe.get_args())));
+            monitors.push((resource.to_string(), ocf::Arguments::from(&ocf_args)));
+        }
+
+        let running = self.run_blocking(move |cli| {
+            let mut running: Vec<(u32, i32)> = Vec::new();
+            for (i, (resource, ocf_args)) in monitors.iter().enumerate() {
+                match ocf::do_operation(resource, ocf::Operation::Monitor, ocf_args, cli) {
+                    Ok(output) if ocf::Status::from(output.code).is_running() => {
+                        running.push((i as u32, output.code))
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("Could not monitor resource {resource}: {e}"),
+                }
+            }
+            running
+        });
+
+        Promise::from_future(async move {
+            let running = running.await?;
+            let mut list = results.get().init_running(running.len() as u32);
+            for (i, (index, status)) in running.into_iter().enumerate() {
+                let mut entry = list.reborrow().get(i as u32);
+                entry.set_index(index);
+                entry.set_status(status);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Read the arguments to pass to a resource agent from a request.
+fn read_arguments(
+    args: ::capnp::struct_list::Reader<'_, ocf_resource_agent::argument::Owned>,
+) -> ::capnp::Result<Vec<(&str, &str)>> {
+    let mut ocf_args = Vec::new();
+    for arg in args.iter() {
+        ocf_args.push((arg.get_key()?.to_str()?, arg.get_value()?.to_str()?));
+    }
+    Ok(ocf_args)
 }
 
-/// Print a message to stderr with the operation and arguments, for debugging.
+/// Log the operation and its arguments, for debugging.
 fn log_operation(op: &ocf::Operation, ocf_args: &ocf::Arguments) {
     let mut msg = format!("Got operation request: {op}\n");
     for (k, v) in ocf_args.args.iter() {
         msg.push_str(&format!("    {}: {}\n", k, v));
     }
-    eprintln!("{msg}");
+    debug!("{msg}");
+}
+
+/// For unit tests, run an agent on a loopback port in a thread of its own, using the test resource
+/// agents, and counting the connections made to it in `connections`. Returns the port.
+#[cfg(test)]
+pub(crate) fn spawn_test_agent(connections: std::sync::Arc<std::sync::atomic::AtomicUsize>) -> u16 {
+    spawn_test_agent_with_id(connections, None)
+}
+
+/// Like spawn_test_agent(), but giving the agent the test ID `test_id`, so that the test resource
+/// agents can tell it apart from other agents.
+#[cfg(test)]
+pub(crate) fn spawn_test_agent_with_id(
+    connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    test_id: Option<&str>,
+) -> u16 {
+    let test_id = test_id.map(|id| ["--test-id", id].map(String::from));
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    listener.set_nonblocking(true).unwrap();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+            let cli = Cli::parse_from(
+                ["halo_remote", "--ocf-root", &ocf_root]
+                    .map(String::from)
+                    .into_iter()
+                    .chain(test_id.into_iter().flatten()),
+            );
+            let agent = OcfResourceAgentImpl::new(cli);
+            let permits = Arc::new(Semaphore::new(Semaphore::MAX_PERMITS));
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let permit = Arc::clone(&permits).acquire_owned().await.unwrap();
+                let _ = __agent_rpc_main(stream, &agent, permit);
+            }
+        });
+    });
+
+    port
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discover_canned_resources() {
+        let bin_dir = format!("{}/tests/discover_bin", env!("CARGO_MANIFEST_DIR"));
+        let cli = Cli::parse_from(["halo_remote", "--discover-bin-dir", &bin_dir]);
+        let client: ocf_resource_agent::Client =
+            capnp_rpc::new_client(OcfResourceAgentImpl::new(cli));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (zpools, lustre_mounts) = rt.block_on(async {
+            let reply = client.discover_request().send().promise.await.unwrap();
+            let reply = reply.get().unwrap();
+            let zpools: Vec<String> = reply
+                .get_zpools()
+                .unwrap()
+                .iter()
+                .map(|z| z.unwrap().to_str().unwrap().to_string())
+                .collect();
+            let lustre_mounts: Vec<String> = reply
+                .get_lustre_mounts()
+                .unwrap()
+                .iter()
+                .map(|m| m.unwrap().to_str().unwrap().to_string())
+                .collect();
+            (zpools, lustre_mounts)
+        });
+
+        assert_eq!(zpools, vec!["mds00e0", "mds00e1"]);
+        assert_eq!(lustre_mounts.len(), 1);
+        assert!(lustre_mounts[0].starts_with("mds00e0/mdt0 on /mnt/mdt0 type lustre"));
+    }
+
+    #[test]
+    fn explicit_tls_paths() {
+        // The defaults come from the environment, which isn't changed here, since other tests
+        // running at the same time may read it:
+        let cli = Cli::parse_from(["halo_remote"]);
+        assert_eq!(cli.tls_paths().cert, crate::default_server_cert());
+        assert_eq!(cli.tls_paths().ca_cert, crate::default_ca_cert());
+
+        let cli = Cli::parse_from([
+            "halo_remote",
+            "--server-cert",
+            "/tmp/agent.crt",
+            "--ca-cert",
+            "/tmp/ca.crt",
+        ]);
+        assert_eq!(cli.tls_paths().cert, "/tmp/agent.crt");
+        assert_eq!(cli.tls_paths().key, crate::default_server_key());
+        assert_eq!(cli.tls_paths().ca_cert, "/tmp/ca.crt");
+    }
+
+    #[test]
+    fn tls_flags() {
+        assert_eq!(Cli::parse_from(["halo_remote"]).tls_mode(), TlsMode::None);
+        assert_eq!(
+            Cli::parse_from(["halo_remote", "--tls"]).tls_mode(),
+            TlsMode::Tls
+        );
+        assert_eq!(
+            Cli::parse_from(["halo_remote", "--mtls"]).tls_mode(),
+            TlsMode::Mtls
+        );
+        assert!(Cli::try_parse_from(["halo_remote", "--tls", "--mtls"]).is_err());
+    }
+
+    #[test]
+    fn max_connections_flag() {
+        let cli = Cli::parse_from(["halo_remote", "--max-connections", "1"]);
+        assert_eq!(cli.max_connections, 1);
+        assert!(Cli::try_parse_from(["halo_remote", "--max-connections", "0"]).is_err());
+    }
+
+    #[test]
+    fn monitors_share_connection() {
+        use std::sync::{atomic::AtomicUsize, atomic::Ordering, Arc};
+
+        use crate::{host::Host, manager::MgrContext, resource::Location, resource::Resource};
+
+        let connections = Arc::new(AtomicUsize::new(0));
+        let port = spawn_test_agent(Arc::clone(&connections));
+
+        let resource = Resource::from_config(
+            crate::config::Resource::new_zpool("pool".to_string()),
+            Vec::new(),
+            Arc::new(Host::new("127.0.0.1", Some(port), Vec::new())),
+            None,
+            Arc::new(MgrContext::default()),
+            "pool".to_string(),
+            Default::default(),
+        );
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            for _ in 0..5 {
+                let status = resource.monitor(Location::Home).await;
+                assert!(!status.is_err_and(|e| e.is_connection_error()));
+            }
+        });
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn meta_data_request() {
+        use std::sync::{atomic::AtomicUsize, Arc};
+
+        let port = spawn_test_agent(Arc::new(AtomicUsize::new(0)));
+        let address = format!("127.0.0.1:{port}");
+        let cli = crate::commands::Cli::default();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let meta_data = tokio::task::LocalSet::new().block_on(
+            &rt,
+            crate::halo_capnp::do_meta_data_request(&address, "test/Pool", &cli),
+        );
+
+        let meta_data = meta_data.unwrap();
+        assert!(meta_data.starts_with("<?xml"));
+        assert!(meta_data.contains(r#"<parameter name="state_dir" required="1">"#));
+    }
+
+    /// Connect to the agent at `addr` and ping it, returning once it replies.
+    async fn ping_agent(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+        let client = connect_to_agent(addr).await?;
+        client.ping_request().send().promise.await?;
+        Ok(())
+    }
+
+    /// Connect to the agent at `addr`, which must be done within a LocalSet.
+    async fn connect_to_agent(
+        addr: SocketAddr,
+    ) -> Result<ocf_resource_agent::Client, Box<dyn Error>> {
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+        let network = twoparty::VatNetwork::new(
+            futures::io::BufReader::new(reader),
+            futures::io::BufWriter::new(writer),
+            rpc_twoparty_capnp::Side::Client,
+            crate::halo_capnp::RPC_READER_OPTIONS,
+        );
+        let mut rpc_system = RpcSystem::new(Box::new(network), None);
+        let client: ocf_resource_agent::Client =
+            rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+        tokio::task::spawn_local(rpc_system);
+        Ok(client)
+    }
+
+    #[test]
+    fn excess_connections_wait() {
+        use std::time::Duration;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            tokio::task::LocalSet::new().block_on(&rt, async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                let cli = Cli::parse_from(["halo_remote", "--max-connections", "2"]);
+                __agent_serve(vec![listener], cli).await.unwrap();
+            });
+        });
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            // Take up both of the agent's connections:
+            let first = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let _second = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+
+            // A third connection is not served while the others are open...
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            let third = tokio::task::spawn_local(async move { ping_agent(addr).await.is_ok() });
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(!third.is_finished());
+
+            // ...but is once one of them is closed:
+            drop(first);
+            let served = tokio::time::timeout(Duration::from_secs(5), third).await;
+            assert!(served.unwrap().unwrap());
+        });
+    }
+
+    #[test]
+    fn idle_connection_closed() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            tokio::task::LocalSet::new().block_on(&rt, async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                let cli = Cli::parse_from(["halo_remote", "--idle-timeout", "1"]);
+                __agent_serve(vec![listener], cli).await.unwrap();
+            });
+        });
+
+        // Connect, but never make a request:
+        let start = Instant::now();
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // The agent closes the connection once it has been idle for longer than the timeout:
+        let mut buf = [0u8; 64];
+        match stream.read(&mut buf) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+        }
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn connection_kept_during_slow_operation() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            tokio::task::LocalSet::new().block_on(&rt, async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+                let cli = Cli::parse_from([
+                    "halo_remote",
+                    "--idle-timeout",
+                    "1",
+                    "--ocf-root",
+                    &ocf_root,
+                ]);
+                __agent_serve(vec![listener], cli).await.unwrap();
+            });
+        });
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            let stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .unwrap();
+            let (reader, writer) =
+                tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+            let network = twoparty::VatNetwork::new(
+                futures::io::BufReader::new(reader),
+                futures::io::BufWriter::new(writer),
+                rpc_twoparty_capnp::Side::Client,
+                crate::halo_capnp::RPC_READER_OPTIONS,
+            );
+            let mut rpc_system = RpcSystem::new(Box::new(network), None);
+            let client: ocf_resource_agent::Client =
+                rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+            tokio::task::spawn_local(rpc_system);
+
+            // A start which takes longer than the idle timeout still gets its reply:
+            let mut request = client.operation_request();
+            let mut params = request.get();
+            params.set_op(ocf_resource_agent::Operation::Start);
+            params.set_resource("test/SlowMonitor");
+            params.init_args(0);
+            let reply = tokio::time::timeout(Duration::from_secs(10), request.send().promise)
+                .await
+                .unwrap()
+                .unwrap();
+            let result = reply.get().unwrap().get_result().unwrap();
+            assert!(matches!(
+                result.which(),
+                Ok(ocf_resource_agent::result::Ok(0))
+            ));
+        });
+    }
+
+    #[test]
+    fn ping_answered_during_operation() {
+        let port = spawn_test_agent(Default::default());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            let client = connect_to_agent(addr).await.unwrap();
+
+            // Start a resource whose start takes a couple of seconds:
+            let mut request = client.operation_request();
+            let mut params = request.get();
+            params.set_op(ocf_resource_agent::Operation::Start);
+            params.set_resource("test/SlowMonitor");
+            params.init_args(0);
+            let start = request.send().promise;
+
+            // While it is starting, the agent still answers pings, on the same connection:
+            let begun = Instant::now();
+            let ping = client.ping_request().send().promise;
+            let ping = tokio::time::timeout(Duration::from_secs(1), ping).await;
+            assert!(ping.unwrap().is_ok());
+            assert!(begun.elapsed() < Duration::from_secs(1));
+
+            let reply = tokio::time::timeout(Duration::from_secs(10), start)
+                .await
+                .unwrap()
+                .unwrap();
+            let result = reply.get().unwrap().get_result().unwrap();
+            assert!(matches!(
+                result.which(),
+                Ok(ocf_resource_agent::result::Ok(0))
+            ));
+        });
+    }
+
+    #[test]
+    fn oversized_message_refused() {
+        use std::{
+            io::{Read, Write},
+            time::Duration,
+        };
+
+        let port = spawn_test_agent(Default::default());
+
+        // A message is framed by its segment table: the number of segments, less one, followed by
+        // the size of each segment in words. Claim a single segment of 32 MiB, which is within
+        // capnp's default limit, but not the agent's:
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(&0u32.to_le_bytes()).unwrap();
+        stream.write_all(&(1u32 << 22).to_le_bytes()).unwrap();
+
+        // The agent closes the connection rather than wait for the rest of the message:
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buf = [0u8; 64];
+        match stream.read(&mut buf) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+        }
+
+        // It goes on serving other connections:
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            let ping = tokio::time::timeout(Duration::from_secs(5), ping_agent(addr)).await;
+            assert!(ping.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn serve_on_two_addresses() {
+        use std::time::Duration;
+
+        let listeners: Vec<std::net::TcpListener> = ["127.0.0.1:0", "127.0.0.2:0"]
+            .iter()
+            .map(|addr| std::net::TcpListener::bind(addr).unwrap())
+            .collect();
+        let addrs: Vec<SocketAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            tokio::task::LocalSet::new().block_on(&rt, async move {
+                let listeners = listeners
+                    .into_iter()
+                    .map(|listener| {
+                        listener.set_nonblocking(true).unwrap();
+                        tokio::net::TcpListener::from_std(listener).unwrap()
+                    })
+                    .collect();
+                __agent_serve(listeners, Cli::parse_from(["halo_remote"]))
+                    .await
+                    .unwrap();
+            });
+        });
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&rt, async move {
+            for addr in addrs {
+                let ping = tokio::time::timeout(Duration::from_secs(5), ping_agent(addr)).await;
+                assert!(ping.unwrap().is_ok(), "{addr}");
+            }
+        });
+    }
+
+    #[test]
+    fn listening_addresses_in_network() {
+        let loopback = cidr::IpCidr::from_str("127.0.0.0/8").unwrap();
+        let addrs = get_listening_addresses(loopback);
+        assert!(addrs.contains(&IpAddr::from([127, 0, 0, 1])));
+        assert!(addrs.iter().all(|addr| loopback.contains(addr)));
+    }
+
+    #[test]
+    fn listening_addresses_in_ipv6_network() {
+        let loopback = cidr::IpCidr::from_str("::1/128").unwrap();
+        let addrs = get_listening_addresses(loopback);
+        assert!(addrs.iter().all(|addr| loopback.contains(addr)));
+        // The host may have IPv6 disabled, in which case it has no IPv6 loopback address:
+        let ipv6_loopback = IpAddr::from(std::net::Ipv6Addr::LOCALHOST);
+        assert_eq!(
+            addrs.contains(&ipv6_loopback),
+            local_addresses().contains(&ipv6_loopback)
+        );
+    }
+
+    #[test]
+    fn ping() {
+        let cli = Cli::parse_from(["halo_remote"]);
+        let client: ocf_resource_agent::Client =
+            capnp_rpc::new_client(OcfResourceAgentImpl::new(cli));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (uptime, version) = rt.block_on(async {
+            let reply = client.ping_request().send().promise.await.unwrap();
+            let reply = reply.get().unwrap();
+            (
+                reply.get_uptime(),
+                reply.get_version().unwrap().to_str().unwrap().to_string(),
+            )
+        });
+
+        assert!(uptime < 60);
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn info() {
+        let cli = Cli::parse_from(["halo_remote", "--ocf-root", "/opt/ocf"]);
+        let client: ocf_resource_agent::Client =
+            capnp_rpc::new_client(OcfResourceAgentImpl::new(cli));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (version, uptime, ocf_root) = rt.block_on(async {
+            let reply = client.info_request().send().promise.await.unwrap();
+            let reply = reply.get().unwrap();
+            (
+                reply.get_version().unwrap().to_str().unwrap().to_string(),
+                reply.get_uptime_secs(),
+                reply.get_ocf_root().unwrap().to_str().unwrap().to_string(),
+            )
+        });
+
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        assert!(uptime < 60);
+        assert_eq!(ocf_root, "/opt/ocf");
+    }
+
+    #[test]
+    fn list_running() {
+        let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+        let cli = Cli::parse_from(["halo_remote", "--ocf-root", &ocf_root]);
+        let client: ocf_resource_agent::Client =
+            capnp_rpc::new_client(OcfResourceAgentImpl::new(cli));
+
+        // Three pools, of which the first and last are running:
+        let state_dirs: Vec<_> = (0..3)
+            .map(|i| {
+                let dir = std::env::temp_dir()
+                    .join(format!("halo_list_running_{}_{i}", std::process::id()));
+                std::fs::create_dir_all(&dir).unwrap();
+                let _ = std::fs::remove_file(dir.join("pool"));
+                dir
+            })
+            .collect();
+        std::fs::write(state_dirs[0].join("pool"), "").unwrap();
+        std::fs::write(state_dirs[2].join("pool"), "").unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let running = rt.block_on(async {
+            let mut request = client.list_running_request();
+            let mut probes = request.get().init_resources(state_dirs.len() as u32);
+            for (i, dir) in state_dirs.iter().enumerate() {
+                let mut probe = probes.reborrow().get(i as u32);
+                probe.set_resource("test/Pool");
+                let mut arg = probe.init_args(1).get(0);
+                arg.set_key("state_dir");
+                arg.set_value(dir.to_str().unwrap());
+            }
+            let reply = request.send().promise.await.unwrap();
+            let running: Vec<(u32, i32)> = reply
+                .get()
+                .unwrap()
+                .get_running()
+                .unwrap()
+                .iter()
+                .map(|r| (r.get_index(), r.get_status()))
+                .collect();
+            running
+        });
+
+        for dir in state_dirs {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+        assert_eq!(running, vec![(0, 0), (2, 0)]);
+    }
 }
@@ -6,7 +6,18 @@
 //! This module implements OCF resource agent operations on nodes which
 //! runs a resource.
 
-use std::{io, process::Command};
+use std::{
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use {
+    log::{debug, error, warn},
+    nix::{sys::signal, unistd::Pid},
+};
 
 /// OCF Resource Agent operations that can be performed on a resource.
 #[derive(Debug)]
@@ -14,6 +25,8 @@ pub enum Operation {
     Start,
     Stop,
     Monitor,
+    ValidateAll,
+    MetaData,
 }
 
 impl std::fmt::Display for Operation {
@@ -25,6 +38,8 @@ impl std::fmt::Display for Operation {
                 Operation::Start => "start",
                 Operation::Stop => "stop",
                 Operation::Monitor => "monitor",
+                Operation::ValidateAll => "validate-all",
+                Operation::MetaData => "meta-data",
             }
         )
     }
@@ -61,6 +76,28 @@ pub enum Status {
     ErrInstalled,
     ErrConfigured,
     ErrNotRunning,
+    RunningMaster,
+    FailedMaster,
+    Degraded,
+    DegradedMaster,
+    ErrTimeout,
+}
+
+impl Status {
+    /// Whether this status may be caused by a passing condition, such as a device being busy for
+    /// a moment, so that the operation is worth retrying.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Status::ErrGeneric | Status::ErrTimeout)
+    }
+
+    /// Whether this status, as the result of a monitor operation, shows the resource running,
+    /// healthy or not.
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self,
+            Status::Success | Status::RunningMaster | Status::Degraded | Status::DegradedMaster
+        )
+    }
 }
 
 impl std::fmt::Display for Status {
@@ -77,6 +114,11 @@ impl std::fmt::Display for Status {
                 Status::ErrInstalled => "OCF_ERR_INSTALLED",
                 Status::ErrConfigured => "OCF_ERR_CONFIGURED",
                 Status::ErrNotRunning => "OCF_NOT_RUNNING",
+                Status::RunningMaster => "OCF_RUNNING_MASTER",
+                Status::FailedMaster => "OCF_FAILED_MASTER",
+                Status::Degraded => "OCF_DEGRADED",
+                Status::DegradedMaster => "OCF_DEGRADED_MASTER",
+                Status::ErrTimeout => "OCF_TIMEOUT",
             }
         )
     }
@@ -93,17 +135,57 @@ impl std::convert::From<i32> for Status {
             5 => Status::ErrInstalled,
             6 => Status::ErrConfigured,
             7 => Status::ErrNotRunning,
+            8 => Status::RunningMaster,
+            9 => Status::FailedMaster,
+            190 => Status::Degraded,
+            191 => Status::DegradedMaster,
+            OCF_TIMEOUT => Status::ErrTimeout,
             _ => {
-                eprintln!("Warning: unexpected return status for Resource Agent: {st}");
+                warn!("unexpected return status for Resource Agent: {st}");
                 Status::ErrUnimplemented
             }
         }
     }
 }
 
+/// The outcome of running an OCF Resource Agent script: its exit code, together with whatever it
+/// wrote to stdout and stderr.
+#[derive(Debug)]
+pub struct OperationOutput {
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
 /// Typical installation path for directory containing OCF Resource Agent scripts.
 const OCF_ROOT: &str = "/usr/lib/ocf";
 
+/// The exit code reported for an operation that was killed for exceeding its timeout. This is not
+/// a standard OCF return code; it is the value that Pacemaker uses internally for the same purpose.
+pub const OCF_TIMEOUT: i32 = 198;
+
+/// How long an operation may run when no timeout is given by the operation arguments or the CLI.
+pub const DEFAULT_OP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The operation argument through which a caller can request a timeout, in milliseconds. This
+/// follows the Pacemaker convention for passing operation timeouts to resource agents. Like any
+/// other argument, it reaches the resource agent prefixed with "OCF_RESKEY_".
+pub const META_TIMEOUT_KEY: &str = "CRM_meta_timeout";
+
+/// The exit code reported for an operation on a resource kind that the agent may not run. This is
+/// the standard OCF_ERR_PERM code.
+const OCF_ERR_PERM: i32 = 4;
+
+/// The exit code reported for an operation whose arguments are malformed. This is the standard
+/// OCF_ERR_ARGS code.
+const OCF_ERR_ARGS: i32 = 2;
+
+/// The most arguments that may be passed to a single operation.
+pub const MAX_ARGUMENTS: usize = 64;
+
+/// The longest that an argument's key and value, together, may be, in bytes.
+pub const MAX_ARGUMENT_SIZE: usize = 4096;
+
 /// Perform an on operation on an OCF resource.
 ///
 /// - resource: the name of the resource, which corresponds to its location under
@@ -112,41 +194,326 @@ const OCF_ROOT: &str = "/usr/lib/ocf";
 /// - args: List of arguments to the operation.
 /// - test_id: set the HALO_TEST_ID environment variable. Used in the testing environment to
 ///   distinguish multiple agents running on the same system.
+///
+/// Returns the exit code of the resource agent script along with its stdout and stderr. If the
+/// resource kind is not one that the agent may run, the script is not run and OCF_ERR_PERM is
+/// returned instead; likewise OCF_ERR_ARGS if the arguments are malformed.
 pub fn do_operation(
     resource: &str,
     op: Operation,
     ocf_operation_args: &Arguments,
     cli_args: &crate::remote::Cli,
-) -> io::Result<i32> {
+) -> io::Result<OperationOutput> {
+    if let Err(reason) = check_permitted(resource, cli_args) {
+        debug!("Refusing to run resource agent '{resource}': {reason}");
+        return Ok(OperationOutput {
+            code: OCF_ERR_PERM,
+            stdout: String::new(),
+            stderr: reason,
+        });
+    }
+
+    if let Err(reason) = check_arguments(ocf_operation_args) {
+        debug!("Refusing to run resource agent '{resource}': {reason}");
+        return Ok(OperationOutput {
+            code: OCF_ERR_ARGS,
+            stdout: String::new(),
+            stderr: reason,
+        });
+    }
+
     let test_id = match &cli_args.test_id {
         Some(id) => id.clone(),
         None => std::process::id().to_string(),
     };
 
-    let ocf_root = cli_args
-        .ocf_root
-        .clone()
-        .unwrap_or(std::env::var("OCF_ROOT").unwrap_or(OCF_ROOT.to_string()));
+    let ocf_root = ocf_root(cli_args);
     let script = format!("{ocf_root}/resource.d/{resource}");
 
-    let output = Command::new(script)
+    let timeout = operation_timeout(ocf_operation_args, cli_args);
+
+    // The resource agent is run in its own process group so that, if it times out, any processes
+    // that it has spawned can be killed along with it.
+    let mut child = Command::new(script)
         .args([op.to_string()])
         .env("OCF_ROOT", ocf_root)
         .env("HALO_TEST_ID", test_id)
         .envs(ocf_operation_args.args.clone())
-        .output()?;
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    // Drain stdout and stderr while waiting, so that a chatty agent can't block on a full pipe:
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
 
-    let exit_code = match output.status.code() {
+    let Some(status) = wait_with_deadline(&mut child, Instant::now() + timeout)? else {
+        kill_process_group(&child);
+        let _ = child.wait();
+        debug!(
+            "Resource agent timed out after {} seconds; killed it.",
+            timeout.as_secs()
+        );
+        return Ok(OperationOutput {
+            code: OCF_TIMEOUT,
+            stdout: String::new(),
+            stderr: format!("operation timed out after {} seconds", timeout.as_secs()),
+        });
+    };
+
+    let exit_code = match status.code() {
         Some(code) => code,
         None => {
-            eprintln!("Could not get exit status from Resource Agent");
+            error!("Could not get exit status from Resource Agent");
             return Err(io::Error::from(io::ErrorKind::Interrupted));
         }
     };
 
-    if exit_code != 0 && cli_args.verbose {
-        println!("Output: {:?}", output);
+    let output = OperationOutput {
+        code: exit_code,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+
+    if exit_code != 0 {
+        debug!("Resource agent exited with status {exit_code}");
+        debug!("stdout: {}", output.stdout);
+        debug!("stderr: {}", output.stderr);
+    }
+
+    Ok(output)
+}
+
+/// The directory that holds the resource agent scripts: the one given on the command line, or else
+/// $OCF_ROOT, or else the typical installation path.
+pub fn ocf_root(cli_args: &crate::remote::Cli) -> String {
+    cli_args
+        .ocf_root
+        .clone()
+        .unwrap_or(std::env::var("OCF_ROOT").unwrap_or(OCF_ROOT.to_string()))
+}
+
+/// Check that the agent may run operations on the given resource kind: the kind must name a
+/// script under the OCF root, and must be on the allowlist, if one was given.
+fn check_permitted(resource: &str, cli_args: &crate::remote::Cli) -> Result<(), String> {
+    if resource.starts_with('/') || resource.split('/').any(|part| part == "..") {
+        return Err(format!(
+            "resource kind '{resource}' is not a path under the OCF root"
+        ));
+    }
+
+    if !cli_args.allowed_resources.is_empty()
+        && !cli_args
+            .allowed_resources
+            .iter()
+            .any(|kind| kind == resource)
+    {
+        return Err(format!(
+            "resource kind '{resource}' is not allowed on this agent"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that the arguments are safe to pass to a resource agent as environment variables: there
+/// must not be too many of them, none may be too long, and each key may contain only ASCII letters,
+/// digits and underscores.
+fn check_arguments(ocf_operation_args: &Arguments) -> Result<(), String> {
+    if ocf_operation_args.args.len() > MAX_ARGUMENTS {
+        return Err(format!(
+            "{} arguments given; at most {MAX_ARGUMENTS} are allowed",
+            ocf_operation_args.args.len()
+        ));
+    }
+
+    for (key, value) in &ocf_operation_args.args {
+        let name = key.strip_prefix("OCF_RESKEY_").unwrap_or(key);
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid argument key '{name}'"));
+        }
+        if name.len() + value.len() > MAX_ARGUMENT_SIZE {
+            return Err(format!(
+                "argument '{name}' is longer than {MAX_ARGUMENT_SIZE} bytes"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Determine how long an operation may run before it is killed.
+///
+/// A timeout passed in the operation arguments takes precedence over the one given on the agent's
+/// command line.
+fn operation_timeout(ocf_operation_args: &Arguments, cli_args: &crate::remote::Cli) -> Duration {
+    let from_args = ocf_operation_args
+        .args
+        .iter()
+        .find(|(k, _)| k.strip_prefix("OCF_RESKEY_") == Some(META_TIMEOUT_KEY))
+        .and_then(|(_, v)| v.parse::<u64>().ok())
+        .map(Duration::from_millis);
+
+    from_args
+        .or(cli_args.op_timeout.map(Duration::from_secs))
+        .unwrap_or(DEFAULT_OP_TIMEOUT)
+}
+
+/// Wait for `child` to exit, giving up at `deadline`. Returns `None` if the deadline passed first.
+fn wait_with_deadline(child: &mut Child, deadline: Instant) -> io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Kill every process in the process group led by `child`.
+fn kill_process_group(child: &Child) {
+    let pgid = Pid::from_raw(child.id() as i32);
+    if let Err(e) = signal::killpg(pgid, signal::Signal::SIGKILL) {
+        error!("Could not kill resource agent process group {pgid}: {e}");
+    }
+}
+
+/// Read a child's output stream to the end in a separate thread.
+fn read_in_background<R: Read + Send + 'static>(stream: Option<R>) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut out);
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn operation_times_out() {
+        let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+        let cli = crate::remote::Cli::parse_from([
+            "halo_remote",
+            "--ocf-root",
+            &ocf_root,
+            "--op-timeout",
+            "1",
+        ]);
+        let args = Arguments::from(&vec![]);
+
+        let start = Instant::now();
+        let output = do_operation("test/Sleep", Operation::Start, &args, &cli).unwrap();
+
+        assert_eq!(Status::from(output.code), Status::ErrTimeout);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    fn allowlist_cli() -> crate::remote::Cli {
+        let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+        crate::remote::Cli::parse_from([
+            "halo_remote",
+            "--ocf-root",
+            &ocf_root,
+            "--allowed-resources",
+            "test/Pool,heartbeat/ZFS",
+        ])
     }
 
-    Ok(exit_code)
+    #[test]
+    fn allowed_kind_runs() {
+        let cli = allowlist_cli();
+        let args = Arguments::from(&vec![("state_dir", "/nonexistent")]);
+
+        let output = do_operation("test/Pool", Operation::Monitor, &args, &cli).unwrap();
+
+        assert_eq!(Status::from(output.code), Status::ErrNotRunning);
+    }
+
+    #[test]
+    fn validate_all_checks_parameters() {
+        let cli = allowlist_cli();
+
+        let good = Arguments::from(&vec![("state_dir", "/tmp")]);
+        let output = do_operation("test/Pool", Operation::ValidateAll, &good, &cli).unwrap();
+        assert_eq!(Status::from(output.code), Status::Success);
+
+        let bad = Arguments::from(&vec![("state_dir", "/nonexistent")]);
+        let output = do_operation("test/Pool", Operation::ValidateAll, &bad, &cli).unwrap();
+        assert_eq!(Status::from(output.code), Status::ErrConfigured);
+    }
+
+    #[test]
+    fn meta_data_is_returned() {
+        let cli = allowlist_cli();
+        let args = Arguments::from(&vec![]);
+
+        let output = do_operation("test/Pool", Operation::MetaData, &args, &cli).unwrap();
+
+        assert_eq!(Status::from(output.code), Status::Success);
+        assert!(output.stdout.contains(r#"<resource-agent name="Pool">"#));
+        assert!(output
+            .stdout
+            .contains(r#"<parameter name="state_dir" required="1">"#));
+    }
+
+    #[test]
+    fn disallowed_kind_is_refused() {
+        let cli = allowlist_cli();
+        let args = Arguments::from(&vec![]);
+
+        let output = do_operation("test/Sleep", Operation::Start, &args, &cli).unwrap();
+
+        assert_eq!(Status::from(output.code), Status::ErrPerm);
+    }
+
+    #[test]
+    fn valid_arguments_are_accepted() {
+        let args = Arguments::from(&vec![("pool", "ost0"), ("state_dir", "/tmp/halo_test")]);
+        assert!(check_arguments(&args).is_ok());
+    }
+
+    #[test]
+    fn invalid_argument_key_is_refused() {
+        let cli = allowlist_cli();
+        let args = Arguments::from(&vec![("state_dir=/tmp LD_PRELOAD", "/tmp/evil.so")]);
+
+        let output = do_operation("test/Pool", Operation::Monitor, &args, &cli).unwrap();
+
+        assert_eq!(Status::from(output.code), Status::ErrArgs);
+    }
+
+    #[test]
+    fn oversized_arguments_are_refused() {
+        let keys: Vec<String> = (0..=MAX_ARGUMENTS).map(|i| format!("key{i}")).collect();
+        let too_many: Vec<(&str, &str)> = keys.iter().map(|k| (k.as_str(), "value")).collect();
+        assert!(check_arguments(&Arguments::from(&too_many)).is_err());
+
+        let long_value = "x".repeat(MAX_ARGUMENT_SIZE);
+        assert!(check_arguments(&Arguments::from(&vec![("pool", long_value.as_str())])).is_err());
+    }
+
+    #[test]
+    fn path_traversal_is_refused() {
+        let ocf_root = format!("{}/tests/ocf_resources", env!("CARGO_MANIFEST_DIR"));
+        let cli = crate::remote::Cli::parse_from(["halo_remote", "--ocf-root", &ocf_root]);
+        let args = Arguments::from(&vec![]);
+
+        for resource in [
+            "../resource.d/test/Sleep",
+            "test/../test/Sleep",
+            "/bin/true",
+        ] {
+            let output = do_operation(resource, Operation::Start, &args, &cli).unwrap();
+            assert_eq!(Status::from(output.code), Status::ErrPerm, "{resource}");
+        }
+    }
 }
@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: MIT
+// Copyright 2025. Triad National Security, LLC.
+
+//! pid_file.rs
+//!
+//! This module implements the remote agent's PID file, which keeps more than one instance of the
+//! agent from running on a host at once.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use nix::{errno::Errno, sys::signal, unistd::Pid};
+
+/// A PID file held by this process. The file is removed when this is dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write this process's PID to the file at `path`, unless another live process already holds
+    /// it, in which case an error of kind AlreadyExists naming that process is returned. A file
+    /// left behind by a process that is no longer running is replaced.
+    pub fn acquire(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        // The file is created exclusively, so that two instances starting at once can't both
+        // believe that they hold it. If it has to be removed as stale, creating it is retried once.
+        for _ in 0..2 {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(mut file) => {
+                    file.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+
+            // The file may be removed by its holder between failing to create it and reading it:
+            let holder = match fs::read_to_string(path) {
+                Ok(contents) => contents.trim().parse::<i32>().ok(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(pid) = holder.filter(|pid| is_alive(*pid)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "another instance is already running with PID {pid}, according to \"{}\"",
+                        path.display()
+                    ),
+                ));
+            }
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("could not take over \"{}\"", path.display()),
+        ))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether a process with the given PID is running. A zombie process, which has exited but not
+/// been reaped by its parent, is not.
+fn is_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    match signal::kill(Pid::from_raw(pid), None) {
+        Ok(()) | Err(Errno::EPERM) => {}
+        Err(_) => return false,
+    }
+
+    // The state is the field after the command name, which is in parentheses and may itself
+    // contain spaces:
+    let Ok(stat) = fs::read_to_string(format!("/proc/{pid}/stat")) else {
+        return true;
+    };
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next());
+    state != Some("Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire() {
+        let path = std::env::temp_dir().join(format!("halo_pid_file_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let pid_file = PidFile::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+
+        // While this process is running, the file can't be taken:
+        let e = PidFile::acquire(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // A file left behind by a process that has exited is taken over:
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        fs::write(&path, format!("{dead}\n")).unwrap();
+        let pid_file = PidFile::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+        drop(pid_file);
+    }
+}
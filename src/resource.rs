@@ -4,12 +4,19 @@
 use std::{
     collections::{HashMap, VecDeque},
     error::Error,
-    sync::{Arc, Mutex},
+    fmt,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use futures::future;
+use {
+    futures::future,
+    log::{debug, error, warn},
+};
 
 use crate::{
+    cluster::Cluster,
+    config::{OpTimeouts, StartRetry, DEFAULT_OP_TIMEOUTS},
     halo_capnp::{do_ocf_request, ocf_resource_agent},
     host::*,
     manager::MgrContext,
@@ -22,21 +29,49 @@ use crate::{
 pub struct ResourceGroup {
     pub root: Resource,
     overall_status: Mutex<ResourceStatus>,
+
+    /// How many resources are in this group, counting the root.
+    num_resources: u32,
+
+    /// Held while the manager acts on the resources in this group, so that actions asked for over
+    /// the management socket don't race with those taken by the management loop.
+    action_lock: tokio::sync::Mutex<()>,
+
+    /// When one of this group's hosts was first found unreachable, while the group wasn't running
+    /// anywhere, if it hasn't been reachable since; see unreachable_for().
+    unreachable_since: Mutex<Option<Instant>>,
 }
 
 impl ResourceGroup {
     pub fn new(root: Resource) -> Self {
         assert!(root.kind == "heartbeat/ZFS");
+        let num_resources = ResourceIterator::new(&root, Traversal::BreadthFirst).count() as u32;
         Self {
             root,
             overall_status: Mutex::new(ResourceStatus::Unknown),
+            num_resources,
+            action_lock: tokio::sync::Mutex::new(()),
+            unreachable_since: Mutex::new(None),
         }
     }
-    pub async fn main_loop(&self, args: &crate::commands::Cli) {
-        if args.manage_resources {
-            self.manage_loop(args).await
+
+    /// How many resources are in this group, counting the root.
+    pub fn num_resources(&self) -> u32 {
+        self.num_resources
+    }
+
+    /// Wait until no other action is being taken on this group's resources, and prevent any from
+    /// being taken until the returned guard is dropped.
+    pub async fn lock_actions(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.action_lock.lock().await
+    }
+
+    /// Manage or observe this resource group, as `cluster`, which holds it, is set to.
+    pub async fn main_loop(&self, cluster: &Cluster) {
+        if cluster.manage_resources() {
+            self.manage_loop(cluster).await
         } else {
-            self.observe_loop(args).await
+            self.observe_loop(&cluster.context.args).await
         }
     }
 
@@ -46,71 +81,109 @@ impl ResourceGroup {
     /// A resource starts out in ResourceState::Unknown. As monitor, start, and stop operations are
     /// performed on that resource, across both its home and away hosts, this function tracks that
     /// state.
-    async fn manage_loop(&self, args: &crate::commands::Cli) {
+    async fn manage_loop(&self, cluster: &Cluster) {
         let high_availability = self.root.failover_node.is_some();
 
         match high_availability {
-            true => self.manage_ha(args).await,
-            false => self.manage_non_ha(args).await,
+            true => self.manage_ha(cluster).await,
+            false => self.manage_non_ha(&cluster.context.args).await,
         };
     }
 
-    async fn manage_non_ha(&self, _args: &crate::commands::Cli) -> ! {
+    async fn manage_non_ha(&self, args: &crate::commands::Cli) -> ! {
+        let mut jitter = Jitter::new(&self.root.id, args.poll_jitter);
         self.update_resources(Location::Home).await;
+        let interval = self.root.monitor_interval.unwrap_or(Duration::from_secs(3));
         loop {
-            match self.get_overall_status() {
-                ResourceStatus::Unknown => self.update_resources(Location::Home).await,
-                ResourceStatus::Stopped => self.try_start_resources(Location::Home).await,
-                ResourceStatus::RunningOnHome => self.update_resources(Location::Home).await,
-                ResourceStatus::RunningOnAway => {
-                    panic!("RunningOnAway shouldn't be reachable in a non-HA cluster.")
-                }
-                ResourceStatus::Unrunnable => {}
-                ResourceStatus::CheckingHome => panic!("CheckingHome shouldn't be reachable here."),
-                ResourceStatus::CheckingAway => {
-                    panic!("CheckingAway shouldn't be reachable in a non-HA cluster.")
-                }
-            };
+            self.manage_non_ha_once().await;
+            tokio::time::sleep(jitter.apply(interval)).await;
+        }
+    }
+
+    /// Take whatever action the overall status of this resource group calls for, once, in a
+    /// cluster without failover hosts.
+    ///
+    /// While the home host is in maintenance, the resources are only monitored, so that their
+    /// status is still reported, but nothing is started.
+    async fn manage_non_ha_once(&self) {
+        let _guard = self.lock_actions().await;
+
+        if self.root.home_node.in_maintenance() {
+            self.update_resources(Location::Home).await;
             self.update_overall_status();
-            tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+            return;
         }
+
+        match self.get_overall_status() {
+            ResourceStatus::Unknown => self.update_resources(Location::Home).await,
+            ResourceStatus::Stopped => self.try_start_resources(Location::Home).await,
+            ResourceStatus::RunningOnHome | ResourceStatus::Degraded => {
+                self.update_resources(Location::Home).await
+            }
+            ResourceStatus::RunningOnAway => {
+                panic!("RunningOnAway shouldn't be reachable in a non-HA cluster.")
+            }
+            ResourceStatus::Unrunnable | ResourceStatus::Disabled => {}
+            ResourceStatus::SplitBrain => {
+                panic!("SplitBrain shouldn't be reachable in a non-HA cluster.")
+            }
+            ResourceStatus::CheckingHome => panic!("CheckingHome shouldn't be reachable here."),
+            ResourceStatus::CheckingAway => {
+                panic!("CheckingAway shouldn't be reachable in a non-HA cluster.")
+            }
+        };
+        self.update_overall_status();
     }
 
-    /// Check the statuses of each of the resources in this resource group.
+    /// Check the statuses of each of the resources in this resource group at `loc`.
     ///
     /// This function updates the status of each resource (zpool and target) in the resource
-    /// group, and the host.
+    /// group, and the host at `loc`.
     async fn update_resources(&self, loc: Location) {
+        // Whether the host is up is decided by pinging its agent, independently of the state of
+        // the resources on it.
+        //
+        // If the host has been fenced, then failing to reach it is expected, and the resources
+        // are known not to be running there. Marking them as Stopped rather than Unknown is what
+        // allows them to be relocated.
+        let host = self.root.host_at(loc);
+        let host_down = host.get_status() == HostStatus::Down;
+        if !host.is_agent_reachable(&self.root.context.args).await {
+            if !host_down {
+                host.set_status(HostStatus::Unknown);
+            }
+            for resource in self.resources() {
+                if host_down {
+                    resource.set_status_because(ResourceStatus::Stopped, "host fenced");
+                } else {
+                    resource.set_status_because(ResourceStatus::Unknown, "agent unreachable");
+                }
+            }
+            return;
+        }
+        host.set_status(HostStatus::Up);
+
         let futures = self
             .resources()
+            .filter(|r| r.enabled)
             .map(|r| async move { (r, r.monitor(loc).await) });
 
         let statuses = future::join_all(futures).await;
 
-        let mut error_seen = false;
         for (resource, status) in statuses.iter() {
             match status {
                 Ok(monitor_res) => {
-                    match monitor_res {
-                        ocf::Status::Success => resource.set_status(ResourceStatus::RunningOnHome),
-                        ocf::Status::ErrNotRunning => resource.set_status(ResourceStatus::Stopped),
-                        // XXX: connection timed out is probably caught in this branch?
-                        // needs to set error_seen to true?
-                        // need better error model...
-                        _ => resource.set_status(ResourceStatus::Unknown),
+                    let status = match (ResourceStatus::from_monitor(monitor_res), loc) {
+                        (ResourceStatus::RunningOnHome, Location::Away) => {
+                            ResourceStatus::RunningOnAway
+                        }
+                        (status, _) => status,
                     };
+                    resource.set_status_because(status, "monitor")
                 }
-                Err(_) => {
-                    resource.set_status(ResourceStatus::Unknown);
-                    error_seen = true;
-                }
+                Err(_) => resource.set_status_because(ResourceStatus::Unknown, "monitor failed"),
             }
         }
-        if error_seen {
-            self.root.home_node.set_status(HostStatus::Unknown);
-        } else {
-            self.root.home_node.set_status(HostStatus::Up);
-        }
     }
 
     /// Attempt to start the resources in this resource group on the given location.
@@ -131,11 +204,12 @@ impl ResourceGroup {
     /// The overall status becomes the "worst" status of any member. For example, if most members
     /// are started but one member is stopped, the overall status is stopped.
     fn update_overall_status(&self) {
-        let statuses = self.resources().map(|r| r.get_status());
-
-        let overall_status = ResourceStatus::get_worst(statuses.into_iter());
+        self.set_overall_status(self.worst_status());
+    }
 
-        self.set_overall_status(overall_status);
+    /// The "worst" status of any member of this group, as it is now.
+    pub fn worst_status(&self) -> ResourceStatus {
+        ResourceStatus::get_worst(self.resources().map(|r| r.get_status()))
     }
 
     async fn observe_loop(&self, args: &crate::commands::Cli) {
@@ -143,46 +217,380 @@ impl ResourceGroup {
         let _ = future::join_all(futures).await;
     }
 
-    pub fn resources(&self) -> ResourceIterator<'_> {
-        ResourceIterator {
-            queue: VecDeque::from([&self.root]),
+    /// Move this resource group to the location `to`.
+    ///
+    /// The group is first stopped on the other location, with dependents stopped before the
+    /// resources they depend on. It is then started on `to`, with resources started before their
+    /// dependents. If the group can't be stopped, it is not started on `to`.
+    ///
+    /// A disabled group is not moved.
+    pub async fn relocate(&self, to: Location) -> Result<(), Box<dyn Error>> {
+        if !self.root.enabled {
+            return Ok(());
+        }
+        if self.root.failover_node.is_none() {
+            return Err(format!("resource group \"{}\" has no failover host", self.root.id).into());
+        }
+        if self.root.get_status() == ResourceStatus::SplitBrain {
+            return Err(format!(
+                "resource group \"{}\" is running on both of its hosts; clean it up first",
+                self.root.id
+            )
+            .into());
+        }
+
+        self.root.stop_if_needed_recursive(to.other()).await?;
+        self.root.start_if_needed_recursive(to).await;
+        self.update_overall_status();
+
+        match self.get_overall_status() {
+            ResourceStatus::RunningOnHome | ResourceStatus::RunningOnAway => Ok(()),
+            other => Err(format!(
+                "resource group \"{}\" did not start on {to:?}; status is {other:?}",
+                self.root.id
+            )
+            .into()),
+        }
+    }
+
+    /// The first of this group's hosts that is in maintenance, if any is.
+    pub fn host_in_maintenance(&self) -> Option<&Host> {
+        std::iter::once(self.root.home_node.as_ref())
+            .chain(self.root.failover_node.as_deref())
+            .find(|host| host.in_maintenance())
+    }
+
+    /// Guess where this resource group is currently running, based on its root resource.
+    pub fn current_location(&self) -> Option<Location> {
+        match self.root.get_status() {
+            ResourceStatus::RunningOnHome => Some(Location::Home),
+            ResourceStatus::RunningOnAway => Some(Location::Away),
+            _ => None,
         }
     }
+
+    /// Iterate over the resources in this group in breadth-first order.
+    pub fn resources(&self) -> ResourceIterator<'_> {
+        self.resources_in_order(Traversal::BreadthFirst)
+    }
+
+    /// Iterate over the resources in this group in the given order.
+    pub fn resources_in_order(&self, order: Traversal) -> ResourceIterator<'_> {
+        ResourceIterator::new(&self.root, order)
+    }
 }
 
 /// Implementations for a ResourceGroup with a failover host
 impl ResourceGroup {
-    /// Main loop for managing a ResourceGroup with a failover host
-    async fn manage_ha(&self, _args: &crate::commands::Cli) -> ! {
-        let _loc = self.check_location().await;
+    /// Main loop for managing a ResourceGroup with a failover host, which is one of those in
+    /// `cluster`.
+    async fn manage_ha(&self, cluster: &Cluster) -> ! {
+        let mut jitter = Jitter::new(&self.root.id, cluster.context.args.poll_jitter);
+        let interval = self.root.monitor_interval.unwrap_or(Duration::from_secs(3));
         loop {
-            todo!()
+            self.manage_ha_once(cluster).await;
+            tokio::time::sleep(jitter.apply(interval)).await;
+        }
+    }
+
+    /// Take whatever action the state of this resource group calls for, once, in a cluster with
+    /// failover hosts.
+    ///
+    /// A group in split-brain is left alone, other than to complain about it, until its root
+    /// resource is cleaned up. A group is failed over through `cluster`, which holds it, so that
+    /// the constraints on it are kept. A group which failed at the away location is stopped there
+    /// before it is started at home.
+    async fn manage_ha_once(&self, cluster: &Cluster) {
+        let guard = self.lock_actions().await;
+
+        // A disabled group is left alone entirely: it is not started, nor failed over, so its
+        // hosts are never fenced on its account:
+        if !self.root.enabled {
+            return;
+        }
+
+        if self.root.get_status() == ResourceStatus::SplitBrain {
+            self.report_split_brain();
+            return;
         }
+
+        let (loc, away_failed) = self.check_location().await;
+        if self.root.get_status() == ResourceStatus::SplitBrain {
+            return;
+        }
+
+        // While either host is in maintenance, the resources are only monitored:
+        let home = &self.root.home_node;
+        let away = self.root.host_at(Location::Away);
+        if self.host_in_maintenance().is_some() {
+            if let Some(loc) = loc {
+                self.update_resources(loc).await;
+            }
+            self.update_overall_status();
+            return;
+        }
+
+        match loc {
+            Some(loc) => {
+                self.update_resources(loc).await;
+                self.update_overall_status();
+                if self.get_overall_status() == ResourceStatus::Stopped {
+                    self.try_start_resources(loc).await;
+                }
+            }
+            None => {
+                // The group isn't running anywhere. It is started at home if both hosts can be
+                // reached; if one of them can't, that host is fenced, in case the group is in fact
+                // running there, and the group is started on the other one.
+                let args = &self.root.context.args;
+                let (home_up, away_up) =
+                    future::join(home.is_agent_reachable(args), away.is_agent_reachable(args))
+                        .await;
+                let unreachable_for = self.unreachable_for(home_up && away_up);
+                let result = match (home_up, away_up) {
+                    (true, true) => {
+                        // If the group failed at the away location, it may still be partly
+                        // running there, so it is stopped there before it is started at home:
+                        let stopped = match away_failed {
+                            true => {
+                                for resource in self.resources() {
+                                    resource.set_status_because(
+                                        ResourceStatus::Unknown,
+                                        "monitor failed",
+                                    );
+                                }
+                                self.root.stop_if_needed_recursive(Location::Away).await
+                            }
+                            false => Ok(()),
+                        };
+                        if stopped.is_ok() {
+                            self.try_start_resources(Location::Home).await;
+                        }
+                        stopped
+                    }
+                    (false, true) | (true, false) if unreachable_for < args.failover_debounce() => {
+                        Ok(())
+                    }
+                    (false, true) | (true, false) => {
+                        let to = match home_up {
+                            true => Location::Home,
+                            false => Location::Away,
+                        };
+                        // The cluster locks this group again, along with any colocated with it:
+                        std::mem::drop(guard);
+                        cluster.fail_over_group(&self.root.id, to).await
+                    }
+                    (false, false) => Err("neither host can be reached".into()),
+                };
+                if let Err(e) = result {
+                    error!("Could not start resource group \"{}\": {e}", self.root.id);
+                }
+            }
+        }
+        self.update_overall_status();
+    }
+
+    /// Note whether both of this group's hosts could be reached, and get how long at least one of
+    /// them has been unreachable, or zero if both could be. A host that has only just dropped out
+    /// may come back by itself, so it isn't fenced until this reaches the manager's
+    /// `--failover-debounce`.
+    fn unreachable_for(&self, both_up: bool) -> Duration {
+        let mut since = self.unreachable_since.lock().unwrap();
+        if both_up {
+            *since = None;
+            return Duration::ZERO;
+        }
+        since.get_or_insert_with(Instant::now).elapsed()
+    }
+
+    /// Fence the host at the other location from `to`, so that this resource group can be
+    /// started at `to`. This is how the group is recovered when the host it was on can't be
+    /// reached; see Cluster::fail_over_group().
+    ///
+    /// The host is powered off, or, if the manager was run with `--reboot-on-failover`, rebooted,
+    /// so that it can rejoin the cluster. Either way, the group stays at `to` afterwards, until it
+    /// is moved back.
+    pub async fn fence_for_failover(&self, to: Location) -> Result<(), Box<dyn Error>> {
+        let failed = self.root.host_at(to.other());
+        if failed.in_maintenance() {
+            return Err(format!("host {failed} is in maintenance, so it won't be fenced").into());
+        }
+        if failed.fence_agents().is_empty() {
+            return Err(format!("host {failed} has no fence agent, so it can't be fenced").into());
+        }
+
+        // A host that is already known to be powered off doesn't need fencing again, and nor does
+        // one that has been rebooted, for another group, and hasn't answered since:
+        if failed.get_status() != HostStatus::Down && !failed.was_fenced() {
+            let command = if self.root.context.args.reboot_on_failover {
+                FenceCommand::Reboot
+            } else {
+                FenceCommand::Off
+            };
+            failed.do_fence_async(command).await?;
+        }
+        Ok(())
+    }
+
+    /// Start this resource group at `to`, once the host at the other location has been fenced by
+    /// fence_for_failover(), counting this as a failover.
+    ///
+    /// The caller must hold the lock on this group's actions.
+    pub async fn take_over(&self, to: Location) -> Result<(), Box<dyn Error>> {
+        for resource in self.resources() {
+            resource.set_status_because(ResourceStatus::Stopped, "host fenced");
+        }
+        self.relocate(to).await?;
+
+        self.root.context.failovers.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 
     /// Check if the ResourceGroup's root resource is running on either of its hosts.
-    async fn check_location(&self) -> Option<Location> {
-        match self.root.monitor(Location::Home) {
-            _ => todo!(),
+    ///
+    /// If it is running on both, the root is marked SplitBrain, and None is returned. Also returns
+    /// whether monitoring it at the away location failed, rather than finding it running or not,
+    /// in which case it may be left in a failed state there.
+    async fn check_location(&self) -> (Option<Location>, bool) {
+        let (home, away) = self.root.monitor_all_locations().await;
+        if is_split_brain(&home, &away) {
+            self.root
+                .set_status_because(ResourceStatus::SplitBrain, "running on both hosts");
+            self.update_overall_status();
+            self.report_split_brain();
+            return (None, false);
+        }
+
+        let away_failed =
+            !matches!(away, Ok(ocf::Status::ErrNotRunning)) && !monitored_running(&away);
+        let loc = if monitored_running(&home) {
+            Some(Location::Home)
+        } else if monitored_running(&away) {
+            Some(Location::Away)
+        } else {
+            None
+        };
+        (loc, away_failed)
+    }
+
+    /// Log, loudly, that this resource group is running on both of its hosts.
+    fn report_split_brain(&self) {
+        error!(
+            "SPLIT BRAIN: resource group \"{}\" is running on both {} and {}! Not starting or stopping it until it is cleaned up.",
+            self.root.id,
+            self.root.home_node,
+            self.root.host_at(Location::Away),
+        );
+    }
+}
+
+/// Randomly varies the manager's polling intervals, so that the monitors for different resources
+/// don't all happen at the same moment.
+///
+/// This is a small xorshift generator seeded from a resource's ID: it doesn't need to be
+/// unpredictable, only to differ between resources.
+struct Jitter {
+    factor: f64,
+    state: u64,
+}
+
+impl Jitter {
+    /// Create a Jitter that varies intervals by up to `factor` of their length either way.
+    fn new(seed: &str, factor: f64) -> Self {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        seed.hash(&mut hasher);
+        Jitter {
+            factor: factor.clamp(0.0, 1.0),
+            // The state of a xorshift generator must never be zero:
+            state: hasher.finish() | 1,
         }
     }
+
+    /// Get a random number in [0, 1).
+    fn next_fraction(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Randomly lengthen or shorten `interval` by up to the jitter factor.
+    fn apply(&mut self, interval: Duration) -> Duration {
+        if self.factor == 0.0 {
+            return interval;
+        }
+        let offset = self.factor * (self.next_fraction() * 2.0 - 1.0);
+        interval.mul_f64(1.0 + offset)
+    }
 }
 
-/// This iterator visits all of the Resources in a dependency tree in breadth-first order.
+/// The orders in which a ResourceIterator can visit the resources in a dependency tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Traversal {
+    /// Each level of the tree is visited before the next one down.
+    BreadthFirst,
+
+    /// Depth-first, with each resource visited before its dependents. This is the order in which
+    /// resources can be started.
+    PreOrder,
+
+    /// Depth-first, with each resource visited after its dependents. This is the order in which
+    /// resources can be stopped.
+    PostOrder,
+}
+
+/// This iterator visits all of the Resources in a dependency tree, in the order given by its
+/// Traversal.
 pub struct ResourceIterator<'a> {
-    queue: VecDeque<&'a Resource>,
+    order: Traversal,
+
+    /// The resources still to be visited. Each is paired with whether its dependents have already
+    /// been queued, which only matters in a post-order traversal.
+    queue: VecDeque<(&'a Resource, bool)>,
+}
+
+impl<'a> ResourceIterator<'a> {
+    pub fn new(root: &'a Resource, order: Traversal) -> Self {
+        ResourceIterator {
+            order,
+            queue: VecDeque::from([(root, false)]),
+        }
+    }
+
+    /// Queue the dependents of `res` so that the first of them is popped off the back first.
+    fn push_dependents_back(&mut self, res: &'a Resource) {
+        self.queue
+            .extend(res.dependents.iter().rev().map(|dep| (dep, false)));
+    }
 }
 
 impl<'a> Iterator for ResourceIterator<'a> {
     type Item = &'a Resource;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res = self.queue.pop_front()?;
-
-        self.queue
-            .append(&mut VecDeque::from_iter(res.dependents.iter()));
-
-        Some(res)
+        match self.order {
+            Traversal::BreadthFirst => {
+                let (res, _) = self.queue.pop_front()?;
+                self.queue
+                    .extend(res.dependents.iter().map(|dep| (dep, false)));
+                Some(res)
+            }
+            Traversal::PreOrder => {
+                let (res, _) = self.queue.pop_back()?;
+                self.push_dependents_back(res);
+                Some(res)
+            }
+            Traversal::PostOrder => loop {
+                let (res, queued) = self.queue.pop_back()?;
+                if queued || res.dependents.is_empty() {
+                    return Some(res);
+                }
+                self.queue.push_back((res, true));
+                self.push_dependents_back(res);
+            },
+        }
     }
 }
 
@@ -207,12 +615,79 @@ pub struct Resource {
     /// Unique identifier for the resource.
     pub id: String,
 
-    // TODO: better privacy here
-    pub status: Mutex<ResourceStatus>,
+    /// The status of this resource, and when it last changed, or when the resource was created if
+    /// its status has never changed. The two are kept under one lock so that they always agree.
+    status: Mutex<(ResourceStatus, Instant)>,
     pub home_node: Arc<Host>,
     pub failover_node: Option<Arc<Host>>,
 
     pub context: Arc<MgrContext>,
+
+    /// How to retry starting this resource when a start fails.
+    pub start_retry: StartRetry,
+
+    /// How often to monitor this resource, if not at the manager's usual intervals.
+    pub monitor_interval: Option<Duration>,
+
+    /// How long the remote agent should let an operation on this resource run before killing it,
+    /// if not for the agent's own timeout.
+    pub op_timeout: Option<Duration>,
+
+    /// How long the remote agent should let each kind of operation on this resource run, where it
+    /// differs from `op_timeout`.
+    pub timeouts: OpTimeouts,
+
+    /// How many starts of this resource have failed in a row.
+    failed_starts: Mutex<u32>,
+
+    /// Among the resources started at the same stage, those with a higher priority are started
+    /// first.
+    pub priority: i32,
+
+    /// Whether the manager looks after this resource, rather than leaving it alone, as given in
+    /// the config.
+    pub enabled: bool,
+}
+
+/// Whether the result of monitoring a resource shows it running, healthy or not.
+fn monitored_running(result: &Result<ocf::Status, ResourceError>) -> bool {
+    matches!(result, Ok(status) if status.is_running())
+}
+
+/// Whether the results of monitoring a resource at home and away, as from
+/// `Resource::monitor_all_locations()`, show it running on both hosts at once.
+pub fn is_split_brain(
+    home: &Result<ocf::Status, ResourceError>,
+    away: &Result<ocf::Status, ResourceError>,
+) -> bool {
+    monitored_running(home) && monitored_running(away)
+}
+
+/// Group `resources` into batches of the same priority, ordered from the highest priority to the
+/// lowest. Within a batch, the resources keep their original order.
+pub fn by_priority<'a>(
+    resources: impl IntoIterator<Item = &'a Resource>,
+) -> Vec<Vec<&'a Resource>> {
+    let mut resources: Vec<&Resource> = resources.into_iter().collect();
+    resources.sort_by_key(|r| std::cmp::Reverse(r.priority));
+
+    let mut batches: Vec<Vec<&Resource>> = Vec::new();
+    for resource in resources {
+        match batches.last_mut() {
+            Some(batch) if batch[0].priority == resource.priority => batch.push(resource),
+            _ => batches.push(vec![resource]),
+        }
+    }
+    batches
+}
+
+/// The settings from the config file which apply to a resource, besides the resource's own
+/// definition.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceSettings {
+    pub start_retry: StartRetry,
+    pub monitor_interval: Option<Duration>,
+    pub op_timeout: Option<Duration>,
 }
 
 impl Resource {
@@ -223,186 +698,415 @@ impl Resource {
         failover_node: Option<Arc<Host>>,
         context: Arc<MgrContext>,
         id: String,
+        settings: ResourceSettings,
     ) -> Self {
+        let enabled = res.enabled.unwrap_or(true);
         Resource {
             kind: res.kind,
             parameters: res.parameters,
             dependents,
-            status: Mutex::new(ResourceStatus::Unknown),
+            status: Mutex::new((
+                if enabled {
+                    ResourceStatus::Unknown
+                } else {
+                    ResourceStatus::Disabled
+                },
+                Instant::now(),
+            )),
             home_node,
             failover_node,
             context,
             id,
+            start_retry: settings.start_retry,
+            monitor_interval: settings.monitor_interval,
+            op_timeout: settings.op_timeout,
+            timeouts: res.timeouts.unwrap_or_default(),
+            failed_starts: Mutex::new(0),
+            priority: res.priority.unwrap_or(0),
+            enabled,
         }
     }
 
     /// This is the loop for tracking a resource's life cycle in Observe mode, where the manager
     /// only checks on resource state and does not actively start / stop a resource.
     async fn observe_loop(&self, args: &crate::commands::Cli) -> ! {
+        if !self.enabled {
+            return future::pending().await;
+        }
+        let mut jitter = Jitter::new(&self.id, args.poll_jitter);
         loop {
             let new_status = self.monitor(Location::Home).await;
-            let mut old_status = self.status.lock().unwrap();
-            *old_status = match &new_status {
-                Ok(s) => match *s {
-                    ocf::Status::Success => ResourceStatus::RunningOnHome,
-                    ocf::Status::ErrNotRunning => ResourceStatus::Stopped,
-                    _ => ResourceStatus::Unknown,
-                },
+            match &new_status {
+                Ok(s) => self.set_status_because(ResourceStatus::from_monitor(s), "monitor"),
                 Err(e) => {
-                    if args.verbose {
-                        eprintln!("Could not monitor {:?}: {}\n", self, e);
-                    }
-                    ResourceStatus::Unknown
+                    debug!("Could not monitor {:?}: {}", self, e);
+                    self.set_status_because(ResourceStatus::Unknown, "monitor failed");
                 }
             };
-            std::mem::drop(old_status);
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            let interval = self.monitor_interval.unwrap_or(Duration::from_secs(5));
+            tokio::time::sleep(jitter.apply(interval)).await;
         }
     }
 
     /// Recursively start a resource as well as all of its dependents.
     /// Updates the status of each resource based on the outcome of the start attempt.
     async fn start_if_needed_recursive(&self, loc: Location) {
+        // If this resource has been given up on, leave it alone until it is cleaned up. A disabled
+        // resource is left alone too, and so are its dependents, since they can't run without it:
+        if !self.enabled || self.get_status() == ResourceStatus::Unrunnable {
+            return;
+        }
+
         // If this resource is already running, don't bother doing anything:
         if !self.is_running() {
-            match self.start(loc).await {
+            let attempts = self.start_retry.attempts;
+            let base_delay = Duration::from_millis(self.start_retry.base_delay_ms);
+            match self.start_with_retry(loc, attempts, base_delay).await {
                 Ok(status) => match status {
                     ocf::Status::Success => {
+                        *self.failed_starts.lock().unwrap() = 0;
                         self.set_running_on_loc(loc);
                     }
-                    _ => self.set_status(ResourceStatus::Stopped),
+                    _ => self.start_failed(),
                 },
-                Err(_) => self.set_status(ResourceStatus::Unknown),
+                Err(_) => self.set_status_because(ResourceStatus::Unknown, "start failed"),
             };
         }
 
-        // Only start the dependents of this resource if it actually started succesfully:
+        // Only start the dependents of this resource if it actually started succesfully. The
+        // dependents of the same priority are started together, higher priorities first:
         if self.is_running() {
-            let futures = self
-                .dependents
-                .iter()
-                .map(|r| r.start_if_needed_recursive(loc));
-            future::join_all(futures).await;
+            for batch in by_priority(&self.dependents) {
+                let futures = batch.iter().map(|r| r.start_if_needed_recursive(loc));
+                future::join_all(futures).await;
+            }
+        }
+    }
+
+    /// Recursively stop a resource as well as all of its dependents, on the given location.
+    ///
+    /// The dependency tree is traversed in post-order: dependents are stopped before the resources
+    /// they depend on, e.g. Lustre targets before the zpool beneath them. A resource that is
+    /// already known to be stopped is not stopped again. If any resource fails to stop, the
+    /// resources it depends on are left alone, and an error is returned.
+    pub async fn stop_if_needed_recursive(&self, loc: Location) -> Result<(), Box<dyn Error>> {
+        let futures = self
+            .dependents
+            .iter()
+            .map(|r| r.stop_if_needed_recursive(loc));
+        for result in future::join_all(futures).await {
+            result?;
+        }
+
+        if !self.enabled || self.get_status() == ResourceStatus::Stopped {
+            return Ok(());
+        }
+
+        match self.stop(loc).await {
+            Ok(ocf::Status::Success) => {
+                self.set_status_because(ResourceStatus::Stopped, "stopped");
+                Ok(())
+            }
+            Ok(status) => Err(format!("could not stop resource \"{}\": {status}", self.id).into()),
+            Err(e) => {
+                self.set_status_because(ResourceStatus::Unknown, "stop failed");
+                Err(Box::new(e))
+            }
         }
     }
 
+    /// How long the remote agent should let the operation `op` on this resource run before
+    /// killing it: the resource's own timeout for `op`, or else its host's, or else the default for
+    /// `op`. None, for an operation with no default, leaves it to the agent.
+    pub fn op_timeout_for(&self, op: ocf_resource_agent::Operation) -> Option<Duration> {
+        let secs = |timeouts: &OpTimeouts| match op {
+            ocf_resource_agent::Operation::Start => timeouts.start,
+            ocf_resource_agent::Operation::Stop => timeouts.stop,
+            ocf_resource_agent::Operation::Monitor => timeouts.monitor,
+            _ => None,
+        };
+        secs(&self.timeouts)
+            .map(Duration::from_secs)
+            .or(self.op_timeout)
+            .or(secs(&DEFAULT_OP_TIMEOUTS).map(Duration::from_secs))
+    }
+
     /// Perform a monitor RPC for this resource.
-    pub async fn monitor(&self, loc: Location) -> Result<ocf::Status, Box<dyn Error>> {
-        tokio::task::LocalSet::new()
-            .run_until(async {
-                let reply =
-                    do_ocf_request(self, loc, ocf_resource_agent::Operation::Monitor).await?;
-                let status = reply.get()?.get_result()?;
-                match status.which() {
-                    Ok(ocf_resource_agent::result::Ok(st)) => {
-                        let st: ocf::Status = st.into();
-                        Ok(st)
-                    }
-                    Ok(ocf_resource_agent::result::Err(e)) => {
-                        let err_str = e?.to_str()?;
-                        println!("Remote agent returned error: {err_str}");
-                        // XXX: return an actual Err(_) here?
-                        Ok(ocf::Status::ErrGeneric)
-                    }
-                    Err(::capnp::NotInSchema(_)) => {
-                        eprintln!("unknown result");
-                        Ok(ocf::Status::ErrUnimplemented)
-                    }
-                }
-            })
+    pub async fn monitor(&self, loc: Location) -> Result<ocf::Status, ResourceError> {
+        self.ocf_operation(loc, ocf_resource_agent::Operation::Monitor)
             .await
     }
 
+    /// Perform a monitor RPC for this resource on its home host and its failover host at the same
+    /// time, returning the results as (home, away). This shows where the resource is actually
+    /// running, including whether it is running on both hosts at once--see `is_split_brain()`.
+    ///
+    /// A resource without a failover host can't be running away from home, so for one of those,
+    /// only the home host is monitored and the away result is ErrNotRunning.
+    pub async fn monitor_all_locations(
+        &self,
+    ) -> (
+        Result<ocf::Status, ResourceError>,
+        Result<ocf::Status, ResourceError>,
+    ) {
+        if !self.enabled {
+            return (
+                Ok(ocf::Status::ErrNotRunning),
+                Ok(ocf::Status::ErrNotRunning),
+            );
+        }
+        if self.failover_node.is_none() {
+            return (
+                self.monitor(Location::Home).await,
+                Ok(ocf::Status::ErrNotRunning),
+            );
+        }
+        future::join(self.monitor(Location::Home), self.monitor(Location::Away)).await
+    }
+
     /// Perform a start RPC for this resource.
-    pub async fn start(&self, loc: Location) -> Result<ocf::Status, Box<dyn Error>> {
-        tokio::task::LocalSet::new()
-            .run_until(async {
-                let reply = do_ocf_request(self, loc, ocf_resource_agent::Operation::Start).await?;
-                let status = reply.get()?.get_result()?;
-                match status.which() {
-                    Ok(ocf_resource_agent::result::Ok(st)) => {
-                        let st: ocf::Status = st.into();
-                        Ok(st)
-                    }
-                    Ok(ocf_resource_agent::result::Err(e)) => {
-                        let e = e?.to_str()?;
-                        println!("Remote agent returned error: {e}");
-                        Ok(ocf::Status::ErrGeneric)
-                    }
-                    Err(::capnp::NotInSchema(_)) => {
-                        eprintln!("unknown result");
-                        Ok(ocf::Status::ErrUnimplemented)
-                    }
-                }
-            })
+    ///
+    /// In a dry run, the start is only printed, and is reported as having succeeded.
+    pub async fn start(&self, loc: Location) -> Result<ocf::Status, ResourceError> {
+        if self.context.args.dry_run {
+            println!(
+                "Would start resource \"{}\" on {}",
+                self.id,
+                self.host_at(loc)
+            );
+            return Ok(ocf::Status::Success);
+        }
+        self.ocf_operation(loc, ocf_resource_agent::Operation::Start)
             .await
     }
 
+    /// Record that a start of this resource failed. Once too many starts have failed in a row, the
+    /// resource is marked Unrunnable so that the manager stops trying to start it.
+    fn start_failed(&self) {
+        let mut failed_starts = self.failed_starts.lock().unwrap();
+        *failed_starts += 1;
+        if *failed_starts >= self.start_retry.max_failed_starts {
+            error!(
+                "Resource \"{}\" failed to start {} times in a row; giving up on it until it is cleaned up.",
+                self.id, *failed_starts
+            );
+            self.set_status_because(ResourceStatus::Unrunnable, "too many failed starts");
+        } else {
+            self.set_status_because(ResourceStatus::Stopped, "start failed");
+        }
+    }
+
+    /// Try to start this resource up to `attempts` times, waiting `base_delay` before the first
+    /// retry and twice as long before each further one.
+    ///
+    /// Only failures that may be transient are retried: a generic error or a timeout. Other
+    /// failures, such as errors in the resource's configuration or arguments, won't go away by
+    /// themselves, so they are returned straight away.
+    pub async fn start_with_retry(
+        &self,
+        loc: Location,
+        attempts: u32,
+        base_delay: Duration,
+    ) -> Result<ocf::Status, ResourceError> {
+        let mut delay = base_delay;
+        let mut attempt = 1;
+        loop {
+            let result = self.start(loc).await;
+            let transient = match &result {
+                Ok(status) => status.is_transient(),
+                Err(e) => matches!(e, ResourceError::Timeout),
+            };
+            if !transient || attempt >= attempts {
+                return result;
+            }
+
+            warn!(
+                "Start of resource \"{}\" failed (attempt {attempt} of {attempts}), retrying in {} ms",
+                self.id,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
     /// Perform a stop RPC for this resource.
-    pub async fn stop(&self) -> Result<ocf::Status, Box<dyn Error>> {
-        tokio::task::LocalSet::new()
-            .run_until(async {
-                let reply =
-                    do_ocf_request(self, Location::Home, ocf_resource_agent::Operation::Stop)
-                        .await?;
-                let status = reply.get()?.get_result()?;
-                match status.which() {
-                    Ok(ocf_resource_agent::result::Ok(st)) => {
-                        let st: ocf::Status = st.into();
-                        Ok(st)
-                    }
-                    Ok(ocf_resource_agent::result::Err(e)) => {
-                        let e = e?.to_str()?;
-                        println!("Remote agent returned error: {e}");
-                        Ok(ocf::Status::ErrGeneric)
-                    }
-                    Err(::capnp::NotInSchema(_)) => {
-                        eprintln!("unknown result");
-                        Ok(ocf::Status::ErrUnimplemented)
-                    }
-                }
-            })
+    ///
+    /// In a dry run, the stop is only printed, and is reported as having succeeded.
+    pub async fn stop(&self, loc: Location) -> Result<ocf::Status, ResourceError> {
+        if self.context.args.dry_run {
+            println!(
+                "Would stop resource \"{}\" on {}",
+                self.id,
+                self.host_at(loc)
+            );
+            return Ok(ocf::Status::Success);
+        }
+        self.ocf_operation(loc, ocf_resource_agent::Operation::Stop)
             .await
     }
 
+    /// The host that this resource runs on at the given location.
+    pub fn host_at(&self, loc: Location) -> &Host {
+        match loc {
+            Location::Home => &self.home_node,
+            Location::Away => self
+                .failover_node
+                .as_ref()
+                .expect("Called operation on failover node for resource without failover node"),
+        }
+    }
+
+    /// Perform a validate-all RPC for this resource, which asks the resource agent to check the
+    /// resource's parameters without starting or stopping it.
+    pub async fn validate(&self, loc: Location) -> Result<ocf::Status, ResourceError> {
+        self.ocf_operation(loc, ocf_resource_agent::Operation::ValidateAll)
+            .await
+    }
+
+    /// Perform an OCF operation RPC for this resource on the given location, and interpret the
+    /// reply.
+    ///
+    /// An error reported by the remote agent itself is returned as ResourceError::AgentError, so
+    /// that callers can tell it apart from a failure to reach the agent at all.
+    async fn ocf_operation(
+        &self,
+        loc: Location,
+        op: ocf_resource_agent::Operation,
+    ) -> Result<ocf::Status, ResourceError> {
+        do_ocf_request(self, loc, op).await
+    }
+
     /// Given the result of a monitor operation--which could have either succesfully returned an
     /// OCF status (like running, not running, etc.) or failed due to a network error, etc.--
     /// update the status of this resource based on that result.
-    pub fn update_status(&self, status: Result<ocf::Status, Box<dyn Error>>) {
+    ///
+    /// The home node's status is updated too: a failure to reach the agent means that the state of
+    /// the host is not known, while any reply from the agent (even an error) means the host is up.
+    /// A host that has been fenced stays Down until it replies again.
+    pub fn update_status(&self, status: Result<ocf::Status, ResourceError>) {
         match status {
             Ok(monitor_res) => {
-                match monitor_res {
-                    ocf::Status::Success => self.set_status(ResourceStatus::RunningOnHome),
-                    ocf::Status::ErrNotRunning => self.set_status(ResourceStatus::Stopped),
-                    // XXX: connection timed out is probably caught in this branch?
-                    // needs to set error_seen to true?
-                    // need better error model...
-                    _ => self.set_status(ResourceStatus::Unknown),
-                };
+                self.set_status_because(ResourceStatus::from_monitor(&monitor_res), "monitor");
+                self.home_node.set_status(HostStatus::Up);
+            }
+            Err(e) if e.is_connection_error() => {
+                if self.home_node.get_status() == HostStatus::Down {
+                    self.set_status_because(ResourceStatus::Stopped, "host fenced");
+                } else {
+                    self.set_status_because(ResourceStatus::Unknown, "agent unreachable");
+                    self.home_node.set_status(HostStatus::Unknown);
+                }
             }
             Err(_) => {
-                self.set_status(ResourceStatus::Unknown);
+                self.set_status_because(ResourceStatus::Unknown, "monitor failed");
+                self.home_node.set_status(HostStatus::Up);
             }
         };
     }
 
     pub fn get_status(&self) -> ResourceStatus {
-        *self.status.lock().unwrap()
+        self.status.lock().unwrap().0
+    }
+
+    /// Forget what is known about the state of this resource, so that it is re-evaluated from
+    /// scratch the next time it is monitored.
+    pub fn cleanup(&self) {
+        *self.failed_starts.lock().unwrap() = 0;
+        self.change_status(ResourceStatus::Unknown, "cleanup", true);
+    }
+
+    /// Whether this resource is defined in the same way as `other`: the same ID, kind and
+    /// parameters, on the same hosts, and enabled or disabled alike.
+    pub fn same_definition(&self, other: &Resource) -> bool {
+        self.id == other.id
+            && self.kind == other.kind
+            && self.parameters == other.parameters
+            && self.enabled == other.enabled
+            && self.home_node.address() == other.home_node.address()
+            && self.failover_node.as_ref().map(|h| h.address())
+                == other.failover_node.as_ref().map(|h| h.address())
+    }
+
+    /// Take on the known state of `old`, the same resource in a cluster whose config has since
+    /// been reloaded. This is not a change in status, so it is not logged.
+    pub fn adopt_state(&self, old: &Resource) {
+        *self.status.lock().unwrap() = *old.status.lock().unwrap();
+        *self.failed_starts.lock().unwrap() = *old.failed_starts.lock().unwrap();
+    }
+
+    /// When the status of this resource last changed. Setting the status to the value it already
+    /// has does not count as a change.
+    pub fn last_transition(&self) -> Instant {
+        self.status.lock().unwrap().1
     }
 
     pub fn set_status(&self, status: ResourceStatus) {
+        self.set_status_because(status, "status set");
+    }
+
+    /// Set the status of this resource, giving the reason for the change for the event log.
+    ///
+    /// The status of a disabled resource is always Disabled, so it is never changed. An Unrunnable
+    /// resource stays Unrunnable when it is found stopped, or can't be checked, until it is
+    /// cleaned up.
+    pub fn set_status_because(&self, status: ResourceStatus, reason: &str) {
+        self.change_status(status, reason, false);
+    }
+
+    /// Set the status of this resource, as for `set_status_because()`, but with `cleanup` set to
+    /// also take an Unrunnable resource out of that status.
+    fn change_status(&self, status: ResourceStatus, reason: &str, cleanup: bool) {
+        if !self.enabled {
+            return;
+        }
         let mut old_status = self.status.lock().unwrap();
-        let old_status_copy = *old_status;
-        *old_status = status;
+        let old_status_copy = old_status.0;
+        let given_up = old_status_copy == ResourceStatus::Unrunnable
+            && matches!(status, ResourceStatus::Stopped | ResourceStatus::Unknown)
+            && !cleanup;
+        if old_status_copy == status || given_up {
+            return;
+        }
+        *old_status = (status, Instant::now());
         std::mem::drop(old_status);
-        if self.context.args.verbose && old_status_copy != status {
-            let _ = self.context.out_stream.writeln(
-                self.status_update_string(old_status_copy, status)
-                    .as_bytes(),
-            );
+        self.context.log_activity(
+            &self.status_update_string(old_status_copy, status),
+            self.context.args.log_transitions(),
+        );
+        if let Some(events) = &self.context.event_stream {
+            let event = self.status_event(old_status_copy, status, reason);
+            let _ = events.writeln(event.to_string().as_bytes());
         }
     }
 
+    /// Build the structured event recording a change in this resource's status, of the form:
+    ///
+    ///     { "timestamp": 1718200000.25, "resource": "ost1", "parameters": { ... },
+    ///       "old_status": "Stopped", "new_status": "RunningOnHome", "reason": "started" }
+    ///
+    /// where the timestamp is in seconds since the Unix epoch.
+    fn status_event(
+        &self,
+        old: ResourceStatus,
+        new: ResourceStatus,
+        reason: &str,
+    ) -> serde_json::Value {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|t| t.as_secs_f64())
+            .unwrap_or_default();
+        serde_json::json!({
+            "timestamp": timestamp,
+            "resource": self.id,
+            "parameters": self.parameters,
+            "old_status": format!("{old:?}"),
+            "new_status": format!("{new:?}"),
+            "reason": reason,
+        })
+    }
+
     pub fn status_update_string(&self, old: ResourceStatus, new: ResourceStatus) -> String {
         format!(
             "Updating status of resource {} from {:?} to {:?}",
@@ -414,15 +1118,17 @@ impl Resource {
 
     fn is_running(&self) -> bool {
         match self.get_status() {
-            ResourceStatus::RunningOnHome | ResourceStatus::RunningOnAway => true,
+            ResourceStatus::RunningOnHome
+            | ResourceStatus::RunningOnAway
+            | ResourceStatus::Degraded => true,
             _ => false,
         }
     }
 
     pub fn set_running_on_loc(&self, loc: Location) {
         match loc {
-            Location::Home => self.set_status(ResourceStatus::RunningOnHome),
-            Location::Away => self.set_status(ResourceStatus::RunningOnAway),
+            Location::Home => self.set_status_because(ResourceStatus::RunningOnHome, "started"),
+            Location::Away => self.set_status_because(ResourceStatus::RunningOnAway, "started"),
         };
     }
 
@@ -450,16 +1156,41 @@ impl Resource {
 /// are RunningOnHome, but one member is Stopped, the group should be considered stopped.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ResourceStatus {
-    Unknown,
+    /// The resource is running on its home host and its failover host at once. Nothing is done
+    /// with it until it is cleaned up, since starting or stopping it could corrupt its data.
+    SplitBrain,
+    /// Starting the resource has failed too many times in a row, so the manager has given up on
+    /// it until it is cleaned up. This is worse than Unknown, since it needs an operator's
+    /// attention, however the rest of the group is doing.
     Unrunnable,
+    Unknown,
     Stopped,
     CheckingAway,
     CheckingHome,
+    /// The resource is running, but its agent reports that it is not healthy.
+    Degraded,
     RunningOnAway,
     RunningOnHome,
+    /// The resource is disabled in the config, so the manager leaves it alone. This comes last so
+    /// that a disabled resource doesn't make the overall status of its group any worse.
+    Disabled,
 }
 
 impl ResourceStatus {
+    /// Every status, from worst to best.
+    pub const ALL: [ResourceStatus; 10] = [
+        Self::SplitBrain,
+        Self::Unrunnable,
+        Self::Unknown,
+        Self::Stopped,
+        Self::CheckingAway,
+        Self::CheckingHome,
+        Self::Degraded,
+        Self::RunningOnAway,
+        Self::RunningOnHome,
+        Self::Disabled,
+    ];
+
     /// Given an iterator over ResourceStatuses, determine the "worst" one. This is used to assign
     /// an overall status to a group of resources based on the worst member status.
     ///
@@ -470,6 +1201,96 @@ impl ResourceStatus {
     {
         list.min().unwrap_or(Self::Unknown)
     }
+
+    /// The status of a resource on its home node, given the status reported by monitoring it
+    /// there.
+    pub fn from_monitor(status: &ocf::Status) -> Self {
+        match status {
+            ocf::Status::Success | ocf::Status::RunningMaster => ResourceStatus::RunningOnHome,
+            ocf::Status::Degraded | ocf::Status::DegradedMaster => ResourceStatus::Degraded,
+            ocf::Status::ErrNotRunning => ResourceStatus::Stopped,
+            _ => ResourceStatus::Unknown,
+        }
+    }
+}
+
+/// The ways in which an operation on a resource can fail.
+///
+/// These are distinguished so that a failure to reach a host (which may mean the host is down) can
+/// be told apart from the remote agent reporting that the operation itself failed.
+#[derive(Debug)]
+pub enum ResourceError {
+    /// Could not establish a connection to the remote agent.
+    Connect(std::io::Error),
+
+    /// The remote agent did not respond in time.
+    Timeout,
+
+    /// The RPC to the remote agent failed after a connection was established.
+    Rpc(capnp::Error),
+
+    /// The remote agent reported an error while performing the operation.
+    AgentError(String),
+
+    /// Could not set up TLS for the connection to the remote agent, e.g. because a certificate
+    /// could not be loaded.
+    Tls(String),
+}
+
+impl ResourceError {
+    /// Whether this error means the remote agent could not be reached at all, including when the
+    /// connection to it was lost partway through an RPC, e.g. because the agent died.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            ResourceError::Connect(_) | ResourceError::Timeout => true,
+            ResourceError::Rpc(e) => e.kind == capnp::ErrorKind::Disconnected,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::Connect(e) => write!(f, "could not connect to remote agent: {e}"),
+            ResourceError::Timeout => write!(f, "timed out waiting for remote agent"),
+            ResourceError::Rpc(e) => write!(f, "RPC to remote agent failed: {e}"),
+            ResourceError::AgentError(e) => write!(f, "remote agent returned error: {e}"),
+            ResourceError::Tls(e) => write!(f, "could not set up TLS: {e}"),
+        }
+    }
+}
+
+impl Error for ResourceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ResourceError::Connect(e) => Some(e),
+            ResourceError::Rpc(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ResourceError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::TimedOut {
+            ResourceError::Timeout
+        } else {
+            ResourceError::Connect(e)
+        }
+    }
+}
+
+impl From<capnp::Error> for ResourceError {
+    fn from(e: capnp::Error) -> Self {
+        ResourceError::Rpc(e)
+    }
+}
+
+impl From<std::str::Utf8Error> for ResourceError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        ResourceError::Rpc(capnp::Error::failed(format!("invalid UTF-8 in reply: {e}")))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -478,14 +1299,85 @@ pub enum Location {
     Away,
 }
 
+impl Location {
+    /// The location that is not this one.
+    pub fn other(&self) -> Self {
+        match self {
+            Location::Home => Location::Away,
+            Location::Away => Location::Home,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ResourceStatus;
+    use super::*;
+
+    #[test]
+    fn last_transition() {
+        let resource = Resource::from_config(
+            crate::config::Resource::new_zpool("pool".to_string()),
+            Vec::new(),
+            Arc::new(Host::new("mds00", None, Vec::new())),
+            None,
+            Arc::new(MgrContext::default()),
+            "pool".to_string(),
+            Default::default(),
+        );
+        let created = resource.last_transition();
+
+        std::thread::sleep(Duration::from_millis(10));
+        resource.set_status(ResourceStatus::Unknown);
+        assert_eq!(resource.last_transition(), created);
+
+        resource.set_status(ResourceStatus::Stopped);
+        let stopped = resource.last_transition();
+        assert!(stopped > created);
+
+        std::thread::sleep(Duration::from_millis(10));
+        resource.set_status(ResourceStatus::Stopped);
+        assert_eq!(resource.last_transition(), stopped);
+
+        resource.set_status(ResourceStatus::RunningOnHome);
+        assert!(resource.last_transition() > stopped);
+    }
+
+    #[test]
+    fn status_events() {
+        let context = MgrContext {
+            event_stream: Some(crate::LogStream::new_buffer()),
+            ..Default::default()
+        };
+        let resource = Resource::from_config(
+            crate::config::Resource::new_zpool("pool".to_string()),
+            Vec::new(),
+            Arc::new(Host::new("mds00", None, Vec::new())),
+            None,
+            Arc::new(context),
+            "pool".to_string(),
+            Default::default(),
+        );
+
+        // Setting the status it already has is not a transition, so only one event is written:
+        resource.set_status_because(ResourceStatus::Unknown, "monitor");
+        resource.set_status_because(ResourceStatus::Stopped, "monitor");
+
+        let events = resource.context.event_stream.as_ref().unwrap();
+        let mut buffer = vec![0u8; 4096];
+        let n = events.readln(&mut buffer).unwrap();
+        let event: serde_json::Value = serde_json::from_slice(&buffer[..n]).unwrap();
+        assert_eq!(event["resource"], "pool");
+        assert_eq!(event["parameters"]["pool"], "pool");
+        assert_eq!(event["old_status"], "Unknown");
+        assert_eq!(event["new_status"], "Stopped");
+        assert_eq!(event["reason"], "monitor");
+        assert!(event["timestamp"].as_f64().unwrap() > 0.0);
+    }
 
     #[test]
     fn test_get_worst() {
         assert_eq!(
-            ResourceStatus::Unknown,
+            ResourceStatus::Unrunnable,
             ResourceStatus::get_worst(
                 vec![ResourceStatus::Unknown, ResourceStatus::Unrunnable].into_iter()
             )
@@ -503,4 +1395,637 @@ mod tests {
             ResourceStatus::RunningOnAway,
         );
     }
+
+    /// A test agent to run resources on, with a scratch directory for the test resource agents to
+    /// keep their state in. The directory is removed when the fixture is dropped.
+    struct AgentFixture {
+        host: Arc<Host>,
+        context: Arc<MgrContext>,
+        state_dir: std::path::PathBuf,
+    }
+
+    impl AgentFixture {
+        /// Start a test agent, with a fresh scratch directory whose name includes `name`.
+        fn new(name: &str) -> Self {
+            Self::with_context(name, MgrContext::default())
+        }
+
+        /// Like `new()`, but giving the resources the context `context`.
+        fn with_context(name: &str, context: MgrContext) -> Self {
+            let port = crate::remote::spawn_test_agent(Default::default());
+            let state_dir =
+                std::env::temp_dir().join(format!("halo_{name}_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&state_dir);
+            std::fs::create_dir_all(&state_dir).unwrap();
+            Self {
+                host: Arc::new(Host::new("127.0.0.1", Some(port), Vec::new())),
+                context: Arc::new(context),
+                state_dir,
+            }
+        }
+
+        /// The path of `file` in the scratch directory.
+        fn path(&self, file: &str) -> String {
+            self.state_dir.join(file).to_str().unwrap().to_string()
+        }
+
+        /// The contents of `file` in the scratch directory.
+        fn read(&self, file: &str) -> String {
+            std::fs::read_to_string(self.path(file)).unwrap()
+        }
+
+        /// The config of a resource of the given kind, whose "state_dir" parameter is the scratch
+        /// directory.
+        fn config(&self, kind: &str) -> crate::config::Resource {
+            crate::config::Resource {
+                kind: kind.to_string(),
+                parameters: HashMap::from([("state_dir".to_string(), self.path(""))]),
+                requires: None,
+                priority: None,
+                enabled: None,
+                timeouts: None,
+            }
+        }
+
+        /// A resource on the test agent with the given config, ID, dependents and settings.
+        fn resource(
+            &self,
+            config: crate::config::Resource,
+            id: &str,
+            dependents: Vec<Resource>,
+            settings: ResourceSettings,
+        ) -> Resource {
+            Resource::from_config(
+                config,
+                dependents,
+                Arc::clone(&self.host),
+                None,
+                Arc::clone(&self.context),
+                id.to_string(),
+                settings,
+            )
+        }
+    }
+
+    impl Drop for AgentFixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.state_dir);
+        }
+    }
+
+    #[test]
+    fn start_retries_flaky_agent() {
+        let agent = AgentFixture::new("flaky");
+        let mut config = agent.config("test/Flaky");
+        config
+            .parameters
+            .insert("counter".to_string(), agent.path("counter"));
+        let resource = agent.resource(config, "flaky", Vec::new(), Default::default());
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // Two attempts aren't enough:
+        let status =
+            rt.block_on(resource.start_with_retry(Location::Home, 2, Duration::from_millis(10)));
+        assert_eq!(status.unwrap(), ocf::Status::ErrGeneric);
+
+        std::fs::remove_file(agent.path("counter")).unwrap();
+        let status =
+            rt.block_on(resource.start_with_retry(Location::Home, 3, Duration::from_millis(10)));
+        assert_eq!(status.unwrap(), ocf::Status::Success);
+        assert_eq!(agent.read("counter").trim(), "3");
+    }
+
+    #[test]
+    fn unrunnable_after_failed_starts() {
+        let agent = AgentFixture::new("fail_start");
+        let starts = || agent.read("counter").trim().parse::<u32>().unwrap();
+
+        let mut config = agent.config("test/FailStart");
+        config
+            .parameters
+            .insert("counter".to_string(), agent.path("counter"));
+        let resource = agent.resource(
+            config,
+            "broken",
+            Vec::new(),
+            ResourceSettings {
+                start_retry: StartRetry {
+                    attempts: 1,
+                    base_delay_ms: 0,
+                    max_failed_starts: 3,
+                },
+                ..Default::default()
+            },
+        );
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for _ in 0..2 {
+            rt.block_on(resource.start_if_needed_recursive(Location::Home));
+            assert_eq!(resource.get_status(), ResourceStatus::Stopped);
+        }
+        rt.block_on(resource.start_if_needed_recursive(Location::Home));
+        assert_eq!(resource.get_status(), ResourceStatus::Unrunnable);
+        assert_eq!(starts(), 3);
+
+        // Once the resource is Unrunnable, no more starts are tried:
+        for _ in 0..3 {
+            rt.block_on(resource.start_if_needed_recursive(Location::Home));
+        }
+        assert_eq!(resource.get_status(), ResourceStatus::Unrunnable);
+        assert_eq!(starts(), 3);
+
+        // Nor is it forgotten when the resource is next monitored:
+        resource.update_status(rt.block_on(resource.monitor(Location::Home)));
+        assert_eq!(resource.get_status(), ResourceStatus::Unrunnable);
+
+        // Cleaning up the resource gives it a fresh set of attempts:
+        resource.cleanup();
+        rt.block_on(resource.start_if_needed_recursive(Location::Home));
+        assert_eq!(resource.get_status(), ResourceStatus::Stopped);
+        assert_eq!(starts(), 4);
+    }
+
+    #[test]
+    fn stop_dependents_first() {
+        let agent = AgentFixture::new("stop_order");
+        std::fs::write(agent.path("pool"), "").unwrap();
+        std::fs::write(agent.path("target"), "").unwrap();
+
+        let target = agent.resource(
+            agent.config("test/Target"),
+            "target",
+            Vec::new(),
+            Default::default(),
+        );
+        let pool = agent.resource(
+            agent.config("test/Pool"),
+            "pool",
+            vec![target],
+            Default::default(),
+        );
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        // The pool can't be stopped while the target is running:
+        assert_eq!(
+            rt.block_on(pool.stop(Location::Home)).unwrap(),
+            ocf::Status::ErrGeneric
+        );
+
+        rt.block_on(pool.stop_if_needed_recursive(Location::Home))
+            .unwrap();
+        assert_eq!(agent.read("stops"), "target\npool\n");
+        assert_eq!(pool.get_status(), ResourceStatus::Stopped);
+        assert_eq!(pool.dependents[0].get_status(), ResourceStatus::Stopped);
+    }
+
+    #[test]
+    fn op_timeouts() {
+        let agent = AgentFixture::new("op_timeouts");
+        let mut config = agent.config("test/SlowMonitor");
+        config.timeouts = Some(crate::config::OpTimeouts {
+            start: Some(30),
+            stop: None,
+            monitor: Some(1),
+        });
+        // The host's timeout alone would be too short for the start:
+        let resource = agent.resource(
+            config,
+            "slow",
+            Vec::new(),
+            ResourceSettings {
+                op_timeout: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            resource.op_timeout_for(ocf_resource_agent::Operation::Stop),
+            Some(Duration::from_secs(1))
+        );
+
+        // Without the host's timeout, each operation has a default of its own:
+        let defaults = agent.resource(
+            agent.config("test/SlowMonitor"),
+            "slow",
+            Vec::new(),
+            Default::default(),
+        );
+        assert_eq!(
+            defaults.op_timeout_for(ocf_resource_agent::Operation::Start),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            defaults.op_timeout_for(ocf_resource_agent::Operation::Monitor),
+            Some(Duration::from_secs(60))
+        );
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(
+            rt.block_on(resource.start(Location::Home)).unwrap(),
+            ocf::Status::Success
+        );
+
+        let begun = Instant::now();
+        assert_eq!(
+            rt.block_on(resource.monitor(Location::Home)).unwrap(),
+            ocf::Status::ErrTimeout
+        );
+        assert!(begun.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn start_dependents_by_priority() {
+        let agent = AgentFixture::new("start_priority");
+        let target = |name: &str, priority| {
+            let mut config = agent.config("test/Target");
+            config.priority = priority;
+            config
+                .parameters
+                .insert("name".to_string(), name.to_string());
+            agent.resource(config, name, Vec::new(), Default::default())
+        };
+        let pool = agent.resource(
+            agent.config("test/Pool"),
+            "pool",
+            vec![
+                target("low", None),
+                target("high", Some(10)),
+                target("negative", Some(-1)),
+                target("middle", Some(5)),
+            ],
+            Default::default(),
+        );
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(pool.start_if_needed_recursive(Location::Home));
+
+        assert_eq!(agent.read("starts"), "high\nmiddle\nlow\nnegative\n");
+    }
+
+    /// A port on localhost that nothing listens at, for a host that can't be reached.
+    fn unused_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn update_resources_away() {
+        let agent = AgentFixture::new("update_away");
+
+        let home = Arc::new(Host::new("127.0.0.1", Some(unused_port()), Vec::new()));
+        let home_status = home.get_status();
+
+        let group = ResourceGroup::new(Resource::from_config(
+            crate::config::Resource::new_zpool(format!("halo_update_away_{}", std::process::id())),
+            Vec::new(),
+            home,
+            Some(Arc::clone(&agent.host)),
+            Arc::clone(&agent.context),
+            "pool".to_string(),
+            Default::default(),
+        ));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(group.update_resources(Location::Away));
+
+        // Only the away host was asked about, and it could be reached:
+        assert_eq!(agent.host.get_status(), HostStatus::Up);
+        assert_eq!(group.root.home_node.get_status(), home_status);
+        assert_eq!(group.root.get_status(), ResourceStatus::Stopped);
+    }
+
+    /// A context for a manager which only prints the fence actions and resource starts and stops
+    /// that it would take, waiting `failover_debounce` seconds before failing over.
+    fn dry_run_context(failover_debounce: f64) -> MgrContext {
+        MgrContext {
+            args: crate::commands::Cli {
+                dry_run: true,
+                failover_debounce,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// A cluster of two hosts in a failover pair: a home host that can't be reached, fenced with
+    /// powerman, and the test agent of `agent`. Each of them has a zpool of each of the given IDs,
+    /// and `constraints` is added to the config, in TOML.
+    fn failover_cluster(
+        agent: &AgentFixture,
+        constraints: &str,
+        home_pools: &[&str],
+        away_pools: &[&str],
+    ) -> Cluster {
+        let config = failover_config(agent, constraints, home_pools, away_pools);
+        Cluster::with_config(Arc::clone(&agent.context), config).unwrap()
+    }
+
+    /// The config of failover_cluster(), for a test to change before building the cluster.
+    fn failover_config(
+        agent: &AgentFixture,
+        constraints: &str,
+        home_pools: &[&str],
+        away_pools: &[&str],
+    ) -> crate::config::Config {
+        let pools = |host: &str, ids: &[&str]| -> String {
+            if ids.is_empty() {
+                return "resources = {}\n".to_string();
+            }
+            ids.iter()
+                .map(|id| {
+                    format!(
+                        "[hosts.resources.{id}]\nkind = \"heartbeat/ZFS\"\nparameters = {{ pool = \"halo_{host}_{id}_{}\" }}\n",
+                        std::process::id()
+                    )
+                })
+                .collect()
+        };
+        let home = format!("127.0.0.1:{}", unused_port());
+        let away = agent.host.address();
+        let config = format!(
+            "failover_pairs = [[\"{home}\", \"{away}\"]]\n{constraints}\n\
+             [[hosts]]\nhostname = \"{home}\"\nfence_agent = \"powerman\"\n{}\n\
+             [[hosts]]\nhostname = \"{away}\"\n{}",
+            pools("home", home_pools),
+            pools("away", away_pools),
+        );
+        crate::config::Config::parse(&config, "failover_cluster").unwrap()
+    }
+
+    #[test]
+    fn failover_debounce() {
+        let agent = AgentFixture::with_context("debounce", dry_run_context(0.5));
+        let cluster = failover_cluster(&agent, "", &["pool"], &[]);
+        let group = cluster.get_resource_group("pool").unwrap();
+        let failovers = || agent.context.failovers.load(Ordering::Relaxed);
+
+        // The group has been Unknown for longer than the debounce, but the home host has only
+        // just been found unreachable, so it isn't fenced yet:
+        std::thread::sleep(Duration::from_millis(500));
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_eq!(group.root.get_status(), ResourceStatus::Unknown);
+        assert_eq!(failovers(), 0);
+
+        std::thread::sleep(Duration::from_millis(500));
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_eq!(group.root.get_status(), ResourceStatus::RunningOnAway);
+        assert_eq!(failovers(), 1);
+    }
+
+    #[test]
+    fn disabled_group_not_failed_over() {
+        let agent = AgentFixture::with_context("disabled_failover", dry_run_context(0.0));
+        let mut config = failover_config(&agent, "", &["pool"], &[]);
+        config.hosts[0].resources.get_mut("pool").unwrap().enabled = Some(false);
+        let cluster = Cluster::with_config(Arc::clone(&agent.context), config).unwrap();
+        let group = cluster.get_resource_group("pool").unwrap();
+
+        // The home host can't be reached, but the group is disabled, so it isn't fenced:
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_eq!(group.root.get_status(), ResourceStatus::Disabled);
+        rt.block_on(cluster.fail_over_group("pool", Location::Away))
+            .unwrap();
+        assert_eq!(group.root.get_status(), ResourceStatus::Disabled);
+        assert_eq!(agent.context.failovers.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn failed_away_stopped_before_home_start() {
+        let agent = AgentFixture::new("failed_away");
+        let [home, away] = ["home", "away"].map(|id| {
+            let port = crate::remote::spawn_test_agent_with_id(Default::default(), Some(id));
+            format!("127.0.0.1:{port}")
+        });
+        let config = format!(
+            "failover_pairs = [[\"{home}\", \"{away}\"]]\n\
+             [[hosts]]\nhostname = \"{home}\"\n\
+             [hosts.resources.res]\nkind = \"test/HostState\"\nparameters = {{ state_dir = \"{}\" }}\n\
+             [[hosts]]\nhostname = \"{away}\"\nresources = {{}}\n",
+            agent.path(""),
+        );
+        let config = crate::config::Config::parse(&config, "failed_away").unwrap();
+        let cluster = Cluster::with_config(Arc::clone(&agent.context), config).unwrap();
+        let group = cluster.get_resource_group("res").unwrap();
+
+        // The group isn't running anywhere, but its monitor failed at the away location:
+        std::fs::write(agent.path("away.failed"), "").unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_eq!(agent.read("actions"), "stop away\nstart home\n");
+        assert_eq!(group.root.get_status(), ResourceStatus::RunningOnHome);
+    }
+
+    #[test]
+    fn colocated_failover() {
+        let agent = AgentFixture::with_context("colocated_failover", dry_run_context(0.0));
+        let cluster = failover_cluster(
+            &agent,
+            "[[colocation]]\nresources = [\"pool_a\", \"pool_b\"]",
+            &["pool_a", "pool_b"],
+            &[],
+        );
+
+        // Failing one group over takes the group colocated with it along:
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let group = cluster.get_resource_group("pool_a").unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        for id in ["pool_a", "pool_b"] {
+            let root = &cluster.get_resource_group(id).unwrap().root;
+            assert_eq!(root.get_status(), ResourceStatus::RunningOnAway);
+        }
+        assert_eq!(agent.context.failovers.load(Ordering::Relaxed), 2);
+
+        // The other group's own pass finds it already moved:
+        let group = cluster.get_resource_group("pool_b").unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_eq!(agent.context.failovers.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn anti_affine_failover() {
+        let agent = AgentFixture::with_context("anti_affine_failover", dry_run_context(0.0));
+        let cluster = failover_cluster(
+            &agent,
+            "[[anti_affinity]]\nresources = [\"pool_a\", \"pool_b\"]",
+            &["pool_a"],
+            &["pool_b"],
+        );
+        cluster
+            .get_resource_group("pool_b")
+            .unwrap()
+            .root
+            .set_status(ResourceStatus::RunningOnHome);
+
+        // The only host that the group could fail over to is running a group anti-affine to it:
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let group = cluster.get_resource_group("pool_a").unwrap();
+        rt.block_on(group.manage_ha_once(&cluster));
+        assert_ne!(group.root.get_status(), ResourceStatus::RunningOnAway);
+        assert_eq!(agent.context.failovers.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        // Start a resource with the given verbosity, returning the lines logged while doing so.
+        let logged_lines = |verbose| {
+            let agent = AgentFixture::with_context(
+                &format!("verbosity_{verbose}"),
+                MgrContext {
+                    out_stream: crate::LogStream::new_buffer(),
+                    args: crate::commands::Cli {
+                        verbose,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            );
+            let pool = agent.resource(
+                agent.config("test/Pool"),
+                "pool",
+                Vec::new(),
+                Default::default(),
+            );
+
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(pool.start_if_needed_recursive(Location::Home));
+
+            // Mark the end of the output, since reading past it would block:
+            let out = &agent.context.out_stream;
+            out.writeln(b"END").unwrap();
+            let mut lines = Vec::new();
+            loop {
+                let mut buf = vec![0u8; 1024];
+                let n = out.readln(&mut buf).unwrap();
+                let line = String::from_utf8_lossy(&buf[..n]).trim_end().to_string();
+                if line == "END" {
+                    return lines;
+                }
+                lines.push(line);
+            }
+        };
+        let transitions = |lines: &[String]| lines.iter().any(|l| l.starts_with("Updating status"));
+        let rpcs = |lines: &[String]| lines.iter().any(|l| l.starts_with("RPC "));
+
+        let lines = logged_lines(0);
+        assert!(!transitions(&lines) && !rpcs(&lines));
+
+        let lines = logged_lines(1);
+        assert!(transitions(&lines) && !rpcs(&lines));
+
+        let lines = logged_lines(2);
+        assert!(transitions(&lines) && rpcs(&lines));
+    }
+
+    /// Build a resource with the given ID and dependents, for testing traversals.
+    fn tree_node(id: &str, dependents: Vec<Resource>) -> Resource {
+        Resource::from_config(
+            crate::config::Resource::new_zpool(id.to_string()),
+            dependents,
+            Arc::new(Host::new("mds00", None, Vec::new())),
+            None,
+            Arc::new(MgrContext::default()),
+            id.to_string(),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn traversal_orders() {
+        //        a
+        //      /   \
+        //     b     c
+        //    / \    |
+        //   d   e   f
+        let root = tree_node(
+            "a",
+            vec![
+                tree_node("b", vec![tree_node("d", vec![]), tree_node("e", vec![])]),
+                tree_node("c", vec![tree_node("f", vec![])]),
+            ],
+        );
+        let group = ResourceGroup::new(root);
+        let ids = |order| {
+            group
+                .resources_in_order(order)
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(Traversal::BreadthFirst), ["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(ids(Traversal::PreOrder), ["a", "b", "d", "e", "c", "f"]);
+        assert_eq!(ids(Traversal::PostOrder), ["d", "e", "b", "f", "c", "a"]);
+
+        // The default order is unchanged:
+        assert_eq!(
+            group.resources().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            ids(Traversal::BreadthFirst)
+        );
+    }
+
+    #[test]
+    fn jitter_range() {
+        let interval = Duration::from_secs(5);
+        let (min, max) = (interval.mul_f64(0.8), interval.mul_f64(1.2));
+
+        let mut jitter = Jitter::new("mds00e0", 0.2);
+        let sleeps: Vec<Duration> = (0..1000).map(|_| jitter.apply(interval)).collect();
+        assert!(sleeps.iter().all(|d| *d >= min && *d <= max));
+        // The sleeps actually vary:
+        assert!(sleeps.iter().any(|d| *d != sleeps[0]));
+
+        // Different resources get different sleeps:
+        let mut other = Jitter::new("mds00e1", 0.2);
+        assert_ne!(
+            other.apply(interval),
+            Jitter::new("mds00e0", 0.2).apply(interval)
+        );
+
+        let mut none = Jitter::new("mds00e0", 0.0);
+        assert!((0..100).all(|_| none.apply(interval) == interval));
+    }
+
+    #[test]
+    fn degraded_ordering() {
+        assert!(ResourceStatus::Degraded < ResourceStatus::RunningOnAway);
+        assert!(ResourceStatus::Degraded < ResourceStatus::RunningOnHome);
+        assert!(ResourceStatus::Degraded > ResourceStatus::Stopped);
+
+        assert_eq!(
+            ResourceStatus::get_worst(
+                vec![ResourceStatus::RunningOnHome, ResourceStatus::Degraded].into_iter()
+            ),
+            ResourceStatus::Degraded,
+        );
+        assert_eq!(
+            ResourceStatus::get_worst(
+                vec![ResourceStatus::Degraded, ResourceStatus::Stopped].into_iter()
+            ),
+            ResourceStatus::Stopped,
+        );
+    }
+
+    #[test]
+    fn disconnected_is_connection_error() {
+        let lost = ResourceError::Rpc(capnp::Error::disconnected("agent died".to_string()));
+        assert!(lost.is_connection_error());
+
+        let failed = ResourceError::Rpc(capnp::Error::failed("bad reply".to_string()));
+        assert!(!failed.is_connection_error());
+        assert!(!ResourceError::AgentError("error".to_string()).is_connection_error());
+    }
+
+    #[test]
+    fn monitor_status() {
+        let from_code = |code: i32| ResourceStatus::from_monitor(&ocf::Status::from(code));
+
+        assert_eq!(from_code(0), ResourceStatus::RunningOnHome);
+        assert_eq!(from_code(7), ResourceStatus::Stopped);
+        assert_eq!(from_code(8), ResourceStatus::RunningOnHome);
+        assert_eq!(from_code(190), ResourceStatus::Degraded);
+        assert_eq!(from_code(191), ResourceStatus::Degraded);
+        assert_eq!(from_code(1), ResourceStatus::Unknown);
+    }
 }
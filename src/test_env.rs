@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{fs, io, io::Write, net, sync::Arc};
+use std::{collections::HashMap, fs, io, io::Write, net, sync::Arc, time::Duration};
 
-use crate::{cluster::Cluster, manager::MgrContext, resource::Resource};
+use crate::{
+    cluster::Cluster,
+    manager::MgrContext,
+    resource::{Resource, ResourceStatus},
+};
 
 /// Given a relative `path` in the test directory, prepend the
 /// full path to the test directory.
@@ -25,6 +29,12 @@ impl IgnoreEexist for io::Result<()> {
     }
 }
 
+/// How long to wait for an agent to finish logging a line before giving up on it.
+const AGENT_LINE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait by default for the remote agents started by a test to accept connections.
+pub const DEFAULT_AGENT_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// This struct is used to hold handles to the remote agent processes so that they can be shut
 /// down when the test ends.
 pub struct ChildHandle {
@@ -37,6 +47,21 @@ impl Drop for ChildHandle {
     }
 }
 
+/// A handle to a remote agent that was started by something other than the test, such as an agent
+/// that the test fence agent restarted when rebooting it, so that it is killed when the test ends.
+pub struct RestartedAgent {
+    pub pid: i32,
+}
+
+impl Drop for RestartedAgent {
+    fn drop(&mut self) {
+        let _ = nix::sys::signal::kill(
+            nix::unistd::Pid::from_raw(self.pid),
+            nix::sys::signal::Signal::SIGKILL,
+        );
+    }
+}
+
 /// A TestEnvironment holds all the information needed to access a test's runtime state. This
 /// includes a "private" working directory in which log files, resource status files, and other
 /// state for the running test will be stored.
@@ -54,9 +79,9 @@ pub struct TestEnvironment {
     /// `tests/ocf_resources/`) to log the actions they take.
     log_file_path: String,
 
-    /// A handle to the OCF resource log file, used to determine what actions they take during the
-    /// test run.
-    log_file: fs::File,
+    /// A reader of the OCF resource log file, used to determine what actions they take during the
+    /// test run. It keeps its position in the file between reads, so that each line is read once.
+    log_file: io::BufReader<fs::File>,
 
     /// The agent binary path has to be passed in as an argument from the tests because the
     /// CARGO_BIN_EXE_* environment variables aren't defined during non-test compilation.
@@ -66,6 +91,14 @@ pub struct TestEnvironment {
     /// manager. The output in this file is for reference only; it is not used by the test
     /// itself.
     manager_log_file: Option<fs::File>,
+
+    /// How long start_remote_agents() waits for the agents to accept connections.
+    agent_ready_timeout: Duration,
+
+    /// The latest status of each resource seen in the manager's status transition lines, keyed by
+    /// the resource's parameters string, so that assert_resource_reaches() knows about statuses
+    /// that were reached while it was waiting on another resource.
+    seen_statuses: HashMap<String, String>,
 }
 
 impl TestEnvironment {
@@ -95,7 +128,7 @@ impl TestEnvironment {
         let _ = std::fs::File::create(&log_file_path).unwrap();
         // Since create() opens the file in write-only mode, ignore that handle and re-open a
         // read-only handle for the test's use:
-        let log_file = std::fs::File::open(&log_file_path).unwrap();
+        let log_file = io::BufReader::new(std::fs::File::open(&log_file_path).unwrap());
 
         Self {
             test_id,
@@ -104,20 +137,43 @@ impl TestEnvironment {
             log_file,
             agent_binary_path: agent_binary_path.to_string(),
             manager_log_file: None,
+            agent_ready_timeout: DEFAULT_AGENT_READY_TIMEOUT,
+            seen_statuses: HashMap::new(),
         }
     }
 
+    /// Set how long start_remote_agents() waits for the agents to accept connections.
+    pub fn set_agent_ready_timeout(&mut self, timeout: Duration) {
+        self.agent_ready_timeout = timeout;
+    }
+
     /// Build a MgrContext for the given test environment. This assumes that the config file for
     /// the test is in a toml file named {test_id}.toml.
     pub fn manager_context(&self) -> MgrContext {
         let config_path = test_path(&format!("{}.toml", self.test_id));
         let socket_path = format!("{}/{}", self.private_dir_path, "test.socket");
         MgrContext::new(crate::commands::Cli {
-            config: Some(config_path),
+            config: vec![config_path],
             socket: Some(socket_path),
-            verbose: true,
+            socket_group: None,
+            verbose: 1,
+            dry_run: false,
             mtls: false,
+            tls: false,
+            client_cert: None,
+            client_key: None,
+            ca_cert: None,
             manage_resources: true,
+            observe_resources: false,
+            stop_removed: false,
+            reboot_on_failover: false,
+            failover_debounce: 0.0,
+            // The tests rely on the manager polling at predictable times:
+            poll_jitter: 0.0,
+            connect_timeout: crate::commands::DEFAULT_CONNECT_TIMEOUT,
+            log_history: crate::commands::DEFAULT_LOG_HISTORY,
+            event_log: None,
+            metrics_addr: None,
             command: None,
         })
     }
@@ -145,7 +201,7 @@ impl TestEnvironment {
             .expect("Could not create cluster from config file");
 
         std::thread::spawn(move || {
-            if let Err(_) = crate::manager::main(cluster) {
+            if let Err(_) = crate::manager::main(vec![cluster]) {
                 std::process::exit(1);
             }
         });
@@ -176,12 +232,76 @@ impl TestEnvironment {
         );
     }
 
+    /// Reads the manager's status transition lines until `res` reaches `status`, or has already
+    /// reached it in a line read earlier, and panics with the transitions seen for `res` if it
+    /// doesn't within `timeout`. Unlike assert_manager_next_line(), this doesn't depend on the
+    /// exact sequence of intermediate statuses that the manager goes through.
+    ///
+    /// Panics if self.start_manager() has not previously been called.
+    pub fn assert_resource_reaches(
+        &mut self,
+        context: &Arc<MgrContext>,
+        res: &Resource,
+        status: ResourceStatus,
+        timeout: Duration,
+    ) {
+        let params = res.params_string();
+        let target = format!("{status:?}");
+        let deadline = std::time::Instant::now() + timeout;
+        let mut history = Vec::new();
+        while self.seen_statuses.get(&params) != Some(&target) {
+            if !context.out_stream.has_unread() {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "resource {params} did not reach {target} within {timeout:?}; saw: {history:?}"
+                );
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            let mut buffer: Vec<u8> = vec![0u8; 4096];
+            let n = context
+                .out_stream
+                .readln(&mut buffer)
+                .expect("failed to read from reader");
+            let _ = self
+                .manager_log_file
+                .as_ref()
+                .unwrap()
+                .write(&buffer[0..n])
+                .expect("failed to write to logfile");
+
+            let line = std::str::from_utf8(&buffer[0..n]).unwrap().trim_end();
+            let Some((line_params, old, new)) = parse_status_update(line) else {
+                continue;
+            };
+            if line_params == params {
+                history.push(format!("{old} -> {new}"));
+            }
+            self.seen_statuses
+                .insert(line_params.to_string(), new.to_string());
+        }
+    }
+
     /// Starts a remote agent in a new process for each port in the given list of `ports`.
     ///
     /// Waits until the remotes are listening and ready to accept connections before returning, so
-    /// that any subsequent code knows the remotes are up and ready.
-    pub fn start_remote_agents(&self, mut agents: Vec<TestAgent>) -> Vec<ChildHandle> {
-        let handles = agents
+    /// that any subsequent code knows the remotes are up and ready. Panics, naming the ports, if
+    /// any of them are not ready within the environment's agent ready timeout.
+    pub fn start_remote_agents(&self, agents: Vec<TestAgent>) -> Vec<ChildHandle> {
+        self.try_start_remote_agents(agents, self.agent_ready_timeout)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like start_remote_agents(), but waits at most `timeout` for the agents to be ready, and
+    /// returns an error naming the ports of the agents that were not. The agents that were started
+    /// are killed on error.
+    pub fn try_start_remote_agents(
+        &self,
+        mut agents: Vec<TestAgent>,
+        timeout: Duration,
+    ) -> io::Result<Vec<ChildHandle>> {
+        let handles: Vec<ChildHandle> = agents
             .iter()
             .map(|agent| ChildHandle {
                 handle: std::process::Command::new(&self.agent_binary_path)
@@ -194,45 +314,130 @@ impl TestEnvironment {
                     .env("OCF_ROOT", test_path("ocf_resources"))
                     .env("HALO_NET", "127.0.0.0/24")
                     .env("HALO_PORT", format!("{}", agent.port))
+                    .env("HALO_PID_FILE", self.agent_pid_file(agent))
                     .spawn()
                     .expect("could not launch process"),
             })
             .collect();
 
-        let mut counter = 20;
-        while !agents.is_empty() && counter > 0 {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
             // Try to connect to each port; when connecting to one succeeds, remove it from the list
             // but keep trying the others.
+            let mut unexpected = None;
             agents.retain(|agent| {
                 let addr: net::SocketAddr = format!("127.0.0.1:{}", agent.port).parse().unwrap();
-                match net::TcpStream::connect_timeout(&addr, std::time::Duration::from_millis(50)) {
+                match net::TcpStream::connect_timeout(&addr, Duration::from_millis(50)) {
                     Ok(_) => false,
                     Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => true,
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => true,
                     Err(e) => {
-                        panic!("Unexpected error attempting to connect to agent at {addr}: {e}")
+                        unexpected.get_or_insert(io::Error::new(
+                            e.kind(),
+                            format!(
+                                "Unexpected error attempting to connect to agent at {addr}: {e}"
+                            ),
+                        ));
+                        true
                     }
                 }
             });
+            if let Some(e) = unexpected {
+                return Err(e);
+            }
+
+            if agents.is_empty() {
+                return Ok(handles);
+            }
+            if std::time::Instant::now() >= deadline {
+                let ports: Vec<String> = agents.iter().map(|a| a.port.to_string()).collect();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "Remote agents on ports {} did not accept a connection within {:?}",
+                        ports.join(", "),
+                        timeout
+                    ),
+                ));
+            }
 
-            std::thread::sleep(std::time::Duration::from_millis(50));
-            counter -= 1;
+            std::thread::sleep(Duration::from_millis(50));
         }
+    }
+
+    /// The PID file of a test agent. Besides keeping two agents with the same ID from running at
+    /// once, it is how the test fence agent finds the agent to fence, and knows whether it is
+    /// "powered on": it is at `{private_test_directory}/{agent_id}.pid`.
+    fn agent_pid_file(&self, agent: &TestAgent) -> String {
+        let agent_id = agent.id.as_ref().unwrap_or(&self.test_id);
+        format!("{}/{agent_id}.pid", self.private_dir_path)
+    }
 
-        handles
+    /// Get the PID that the agent with ID `agent_id` recorded in its PID file, if it is running.
+    pub fn agent_pid(&self, agent_id: &str) -> Option<i32> {
+        fs::read_to_string(format!("{}/{agent_id}.pid", self.private_dir_path))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Take charge of the agent with ID `agent_id` after it was restarted outside of the test, so
+    /// that it is killed when the returned handle is dropped.
+    pub fn restarted_agent(&self, agent_id: &str) -> Option<RestartedAgent> {
+        self.agent_pid(agent_id).map(|pid| RestartedAgent { pid })
     }
 
     /// Reads a line from the shared file used for communication from the agent, and asserts that
     /// it equals the given expected `line`.
+    ///
+    /// If the agent has not finished writing the line yet, waits for it, for up to
+    /// AGENT_LINE_TIMEOUT.
     pub fn assert_agent_next_line(&mut self, line: &str) {
-        use io::{BufRead, Read};
-
-        let mut buf = vec![0u8; 4096];
+        use io::BufRead;
+
+        let deadline = std::time::Instant::now() + AGENT_LINE_TIMEOUT;
+        let mut contents = String::new();
+        while !contents.ends_with('\n') {
+            if self.log_file.read_line(&mut contents).unwrap() == 0 {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "timed out waiting for agent line \"{line}\"; got \"{contents}\""
+                );
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
 
-        let _n = self.log_file.read(&mut buf).unwrap();
+        assert_eq!(contents.trim_end_matches('\n'), line);
+    }
 
-        let contents = buf.lines().next().unwrap().unwrap();
+    /// Reads all of the lines that the agents have logged since the test last read from the log.
+    ///
+    /// A line which an agent has not finished writing yet is left in the log, to be read whole
+    /// later, rather than split in two.
+    pub fn agent_lines(&mut self) -> Vec<String> {
+        use io::BufRead;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let n = self.log_file.read_line(&mut line).unwrap();
+            if n == 0 {
+                break;
+            }
+            if !line.ends_with('\n') {
+                self.log_file.seek_relative(-(n as i64)).unwrap();
+                break;
+            }
+            lines.push(line.trim_end_matches('\n').to_string());
+        }
+        lines
+    }
 
-        assert_eq!(contents, line);
+    /// Asserts that the agents have not logged any actions that the test has not already read.
+    pub fn assert_no_agent_lines(&mut self) {
+        let lines = self.agent_lines();
+        assert!(lines.is_empty(), "unexpected agent lines: {lines:?}");
     }
 
     /// Stop over a given resource.
@@ -283,6 +488,15 @@ impl TestAgent {
     }
 }
 
+/// Split a status transition line, as made by Resource::status_update_string(), into the
+/// resource's parameters string and its old and new statuses.
+fn parse_status_update(line: &str) -> Option<(&str, &str, &str)> {
+    let rest = line.strip_prefix("Updating status of resource ")?;
+    let (rest, new) = rest.rsplit_once(" to ")?;
+    let (params, old) = rest.rsplit_once(" from ")?;
+    Some((params, old, new))
+}
+
 /// Given an operation `op` and a resource `res`, formats the line that we expect to see in the
 /// communication file for succesfully performing `op` on `res`.
 pub fn agent_expected_line(op: &str, res: &Resource) -> String {
@@ -298,39 +512,46 @@ pub fn agent_expected_line(op: &str, res: &Resource) -> String {
     }
 }
 
-/// When a remote agent is running in the test environment, it may need to be fenced. In order to
-/// enable fencing, the test fence program needs to know this agent's PID so that it can be killed
-/// with a signal.
-///
-/// This knowledge is shared with the test fence program by writing this agent's PID to a file in a
-/// location known to the test fence program. That location is:
-///
-///     `{private_test_directory}/{agent_id}.pid`
-///
-/// `private_test_directory` is typically `tests/test_output/{test_name}`, and this agent gets that
-/// directory from the environment variable `HALO_TEST_DIRECTORY`.
-///
-/// `agent_id` passed as an optional argument.
-///
-/// This function only writes to a file when both `private_test_directory` and `agent_id` are
-/// known. If one or both is not specified, it is assumed that the agent is either not running in
-/// the test environment, or it is running in a test that does not use fencing and does not need
-/// this shared information.
-pub fn maybe_identify_agent_for_test_fence(args: &crate::remote::Cli) {
-    let Some(ref agent_id) = args.test_id else {
-        return;
-    };
-
-    let Ok(test_directory) = std::env::var("HALO_TEST_DIRECTORY") else {
-        return;
-    };
-
-    // It is important that the remote agent NOT proceed if it is unable to write its PID to the
-    // required file. This is because the test fence agent uses the presence or absence of this
-    // file as a way to know whether a particular remote agent is "powered on". Thus, these
-    // `unwrap()`s are needed to maintain the test environment's invariants.
-    let pid_file = format!("{test_directory}/{agent_id}.pid");
-    let mut file = std::fs::File::create(pid_file).unwrap();
-    let me = format!("{}", std::process::id());
-    file.write_all(me.as_bytes()).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agent_lines_in_order() {
+        let mut env = TestEnvironment::new("agent_lines_in_order".to_string(), "/bin/true");
+        let mut log = fs::OpenOptions::new()
+            .append(true)
+            .open(&env.log_file_path)
+            .unwrap();
+
+        // Two lines written at once are read one at a time:
+        log.write_all(b"first\nsecond\n").unwrap();
+        log.flush().unwrap();
+        env.assert_agent_next_line("first");
+        env.assert_agent_next_line("second");
+
+        // A line written in pieces is only read once it is complete:
+        log.write_all(b"par").unwrap();
+        log.flush().unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            log.write_all(b"tial\nlast\n").unwrap();
+        });
+        env.assert_agent_next_line("partial");
+        writer.join().unwrap();
+        env.assert_agent_next_line("last");
+        env.assert_no_agent_lines();
+
+        // A line which is still being written is not returned in pieces:
+        let mut log = fs::OpenOptions::new()
+            .append(true)
+            .open(&env.log_file_path)
+            .unwrap();
+        log.write_all(b"done\nunfin").unwrap();
+        log.flush().unwrap();
+        assert_eq!(env.agent_lines(), vec!["done"]);
+        log.write_all(b"ished\n").unwrap();
+        log.flush().unwrap();
+        assert_eq!(env.agent_lines(), vec!["unfinished"]);
+    }
 }
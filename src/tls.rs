@@ -1,74 +1,470 @@
 // SPDX-License-Identifier: MIT
 // Copyright 2025. Triad National Security, LLC.
 
-use std::{fs::File, io::BufReader, path::PathBuf, sync::Arc};
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use {
+    log::{error, warn},
     rustls::pki_types::{CertificateDer, PrivateKeyDer},
     rustls::{server::WebPkiClientVerifier, ClientConfig, RootCertStore, ServerConfig},
-    rustls_pemfile::{certs, private_key},
+    rustls_pemfile::{certs, read_all, Item},
     tokio_rustls::{TlsAcceptor, TlsConnector},
 };
 
-fn load_private_key(path: PathBuf) -> Result<PrivateKeyDer<'static>, Box<dyn std::error::Error>> {
-    let key_file = File::open(path)?;
-    let mut reader = BufReader::new(key_file);
-    private_key(&mut reader)?.ok_or_else(|| "No private key found".into())
+/// Load a private key from the PEM file at `path`.
+///
+/// The file may hold other PEM blocks, such as certificates, which are ignored. If it holds keys in
+/// more than one format, a PKCS#8 key is preferred, then a SEC1 (EC) key, then a PKCS#1 (RSA) key.
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, Box<dyn Error>> {
+    let key_file = File::open(path)
+        .map_err(|e| format!("could not open key file \"{}\": {e}", path.display()))?;
+
+    let mut pkcs8 = None;
+    let mut sec1 = None;
+    let mut pkcs1 = None;
+    for item in read_all(&mut BufReader::new(key_file)) {
+        let item =
+            item.map_err(|e| format!("could not read key file \"{}\": {e}", path.display()))?;
+        match item {
+            Item::Pkcs8Key(key) => {
+                pkcs8.get_or_insert(PrivateKeyDer::Pkcs8(key));
+            }
+            Item::Sec1Key(key) => {
+                sec1.get_or_insert(PrivateKeyDer::Sec1(key));
+            }
+            Item::Pkcs1Key(key) => {
+                pkcs1.get_or_insert(PrivateKeyDer::Pkcs1(key));
+            }
+            _ => {}
+        }
+    }
+
+    pkcs8.or(sec1).or(pkcs1).ok_or_else(|| {
+        format!(
+            "no PKCS#8, EC or RSA private key found in \"{}\"",
+            path.display()
+        )
+        .into()
+    })
 }
 
-fn load_cert(path: PathBuf) -> Vec<CertificateDer<'static>> {
-    let cert_file = &mut BufReader::new(File::open(path).unwrap());
-    let certs: Vec<CertificateDer<'static>> = certs(cert_file)
+fn load_cert(path: &Path) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+    let cert_file = File::open(path).map_err(|e| {
+        format!(
+            "could not open certificate file \"{}\": {e}",
+            path.display()
+        )
+    })?;
+    let certs: Vec<CertificateDer<'static>> = certs(&mut BufReader::new(cert_file))
         .collect::<Result<_, _>>()
-        .expect("Issue getting certs");
-    certs
+        .map_err(|e| {
+            format!(
+                "could not read certificate file \"{}\": {e}",
+                path.display()
+            )
+        })?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in \"{}\"", path.display()).into());
+    }
+    Ok(certs)
 }
 
-pub fn get_acceptor() -> TlsAcceptor {
-    // Load server certificate and private key
-    let server_cert = load_cert(PathBuf::from(crate::default_server_cert()));
-    let server_key = load_private_key(PathBuf::from(crate::default_server_key()));
+/// How many days before a certificate expires to start warning about it.
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Get how many seconds remain, as of `now`, until `cert` expires. This is negative if the
+/// certificate has already expired.
+fn seconds_until_expiry(cert: &CertificateDer, now: SystemTime) -> Result<i64, Box<dyn Error>> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| format!("could not parse certificate: {e}"))?;
+    let not_after = parsed.validity().not_after.timestamp();
+    let now = now.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    Ok(not_after - now)
+}
+
+/// Get the number of whole days remaining, as of `now`, until `cert` expires. This is negative if
+/// the certificate has already expired.
+pub fn days_until_expiry(cert: &CertificateDer, now: SystemTime) -> Result<i64, Box<dyn Error>> {
+    Ok(seconds_until_expiry(cert, now)?.div_euclid(SECONDS_PER_DAY))
+}
+
+/// Check that the certificate loaded from `path` has not expired as of `now`, printing a warning
+/// if it will expire within EXPIRY_WARNING_DAYS.
+fn check_cert_expiry(
+    cert: &CertificateDer,
+    path: &Path,
+    now: SystemTime,
+) -> Result<(), Box<dyn Error>> {
+    if seconds_until_expiry(cert, now)? <= 0 {
+        return Err(format!("certificate \"{}\" has expired", path.display()).into());
+    }
+
+    let days = days_until_expiry(cert, now)?;
+    if days < EXPIRY_WARNING_DAYS {
+        warn!("certificate \"{}\" expires in {days} days", path.display());
+    }
 
-    // Load CA root certificate
-    let ca_cert = load_cert(PathBuf::from(crate::default_ca_cert()));
+    Ok(())
+}
+
+/// Check the expiry of the agent's server certificate, as it is about to start. An expired
+/// certificate is an error, and one that expires soon gets a warning.
+pub fn check_server_cert(paths: &TlsPaths) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(&paths.cert);
+    for cert in load_cert(path)?.iter() {
+        check_cert_expiry(cert, path, SystemTime::now())?;
+    }
+    Ok(())
+}
+
+/// Get the names that a client certificate identifies its holder by: its subject common names,
+/// followed by its DNS subject alternative names.
+pub fn client_names(cert: &CertificateDer) -> Result<Vec<String>, Box<dyn Error>> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| format!("could not parse certificate: {e}"))?;
+
+    let mut names = Vec::new();
+    for cn in parsed.subject().iter_common_name() {
+        names.push(cn.as_str()?.to_string());
+    }
+    if let Some(san) = parsed.subject_alternative_name()? {
+        for name in san.value.general_names.iter() {
+            if let x509_parser::extensions::GeneralName::DNSName(dns) = name {
+                names.push(dns.to_string());
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// Whether the client presenting `cert` may use the agent. When `allowed` is empty, any client with
+/// a certificate signed by the CA is allowed; otherwise, one of the certificate's names must be in
+/// `allowed`.
+pub fn is_client_allowed(cert: &CertificateDer, allowed: &[String]) -> bool {
+    if allowed.is_empty() {
+        return true;
+    }
+
+    match client_names(cert) {
+        Ok(names) => names.iter().any(|name| allowed.contains(name)),
+        Err(e) => {
+            error!("Could not get names from client certificate: {e}");
+            false
+        }
+    }
+}
 
-    // Load CA cert into root store, I.E. trust it
+/// Load the CA certificate at `path` into a root store, I.E. trust it.
+fn load_root_store(path: &Path) -> Result<RootCertStore, Box<dyn Error>> {
+    let ca_cert = load_cert(path)?;
     let mut root_store = RootCertStore::empty();
     root_store.add_parsable_certificates(ca_cert);
+    Ok(root_store)
+}
+
+/// How connections between the manager and the remote agents are secured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode {
+    /// Connections are not encrypted.
+    None,
+
+    /// The agent presents a certificate which the manager verifies against the CA, but the
+    /// manager does not need a certificate of its own.
+    Tls,
+
+    /// Both sides present certificates, and each verifies the other's against the CA.
+    Mtls,
+}
+
+impl TlsMode {
+    /// Pick the mode from the `--tls` and `--mtls` command line flags.
+    pub fn from_flags(tls: bool, mtls: bool) -> Self {
+        if mtls {
+            TlsMode::Mtls
+        } else if tls {
+            TlsMode::Tls
+        } else {
+            TlsMode::None
+        }
+    }
+}
+
+/// The files used to set up TLS: this side's certificate and private key, and the certificate of
+/// the CA used to verify the other side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsPaths {
+    pub cert: String,
+    pub key: String,
+    pub ca_cert: String,
+}
+
+/// Build the TLS acceptor used by the remote agent to accept mTLS connections from the manager.
+pub fn get_acceptor(paths: &TlsPaths) -> Result<TlsAcceptor, Box<dyn Error>> {
+    let cert = Path::new(&paths.cert);
+    let key = Path::new(&paths.key);
+
+    // Load server certificate and private key
+    let server_cert = load_cert(cert)?;
+    let server_key = load_private_key(key)?;
+
+    let root_store = load_root_store(Path::new(&paths.ca_cert))?;
 
     // Create a client certificiate verifier, mTLS part of the code
     let client_verifier = WebPkiClientVerifier::builder(Arc::new(root_store))
         .build()
-        .expect("Failure to build client verifier");
+        .map_err(|e| format!("could not build client verifier: {e}"))?;
 
     // Build server config
     let config = ServerConfig::builder()
         .with_client_cert_verifier(client_verifier)
-        .with_single_cert(server_cert, server_key.unwrap())
-        .unwrap();
+        .with_single_cert(server_cert, server_key)
+        .map_err(|e| {
+            format!(
+                "could not use server certificate \"{}\" with key \"{}\": {e}",
+                cert.display(),
+                key.display()
+            )
+        })?;
 
     // return TLS acceptor
-    TlsAcceptor::from(Arc::new(config))
+    Ok(TlsAcceptor::from(Arc::new(config)))
 }
 
-pub fn get_connector() -> TlsConnector {
-    // Load cient certificate adn private key
-    let client_cert = load_cert(PathBuf::from(crate::default_client_cert()));
-    let client_key = load_private_key(PathBuf::from(crate::default_client_key()));
+/// Build the TLS acceptor used by the remote agent to accept TLS connections which do not require a
+/// client certificate.
+pub fn get_acceptor_server_only(paths: &TlsPaths) -> Result<TlsAcceptor, Box<dyn Error>> {
+    let cert = Path::new(&paths.cert);
+    let key = Path::new(&paths.key);
 
-    // Load CA root certificate
-    let ca_cert = load_cert(PathBuf::from(crate::default_ca_cert()));
+    let server_cert = load_cert(cert)?;
+    let server_key = load_private_key(key)?;
 
-    // Load the CA cert into the root store, I.E. trust it
-    let mut root_store = RootCertStore::empty();
-    root_store.add_parsable_certificates(ca_cert);
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(server_cert, server_key)
+        .map_err(|e| {
+            format!(
+                "could not use server certificate \"{}\" with key \"{}\": {e}",
+                cert.display(),
+                key.display()
+            )
+        })?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Build the TLS connector used by the manager to make TLS connections to the remote agents
+/// without presenting a client certificate. Only the CA certificate in `paths` is used, to verify
+/// the agent.
+pub fn get_connector_server_only(paths: &TlsPaths) -> Result<TlsConnector, Box<dyn Error>> {
+    let root_store = load_root_store(Path::new(&paths.ca_cert))?;
+
+    let config = ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Build the TLS connector used by the manager to make mTLS connections to the remote agents.
+pub fn get_connector(paths: &TlsPaths) -> Result<TlsConnector, Box<dyn Error>> {
+    let cert = Path::new(&paths.cert);
+    let key = Path::new(&paths.key);
+
+    // Load cient certificate adn private key
+    let client_cert = load_cert(cert)?;
+    let client_key = load_private_key(key)?;
+
+    let root_store = load_root_store(Path::new(&paths.ca_cert))?;
 
     // Build client config
     let config = ClientConfig::builder()
         .with_root_certificates(root_store)
-        .with_client_auth_cert(client_cert, client_key.unwrap())
-        .unwrap();
+        .with_client_auth_cert(client_cert, client_key)
+        .map_err(|e| {
+            format!(
+                "could not use client certificate \"{}\" with key \"{}\": {e}",
+                cert.display(),
+                key.display()
+            )
+        })?;
 
     // Return TLS connector
-    TlsConnector::from(Arc::new(config))
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MISSING: &str = "/nonexistent/halo/missing.crt";
+
+    fn missing_paths() -> TlsPaths {
+        TlsPaths {
+            cert: MISSING.to_string(),
+            key: MISSING.to_string(),
+            ca_cert: MISSING.to_string(),
+        }
+    }
+
+    /// A self-signed certificate that is valid for one day, until 2025-01-02T00:00:00Z.
+    fn short_lived_cert() -> (CertificateDer<'static>, std::path::PathBuf) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs/short_lived.crt");
+        let cert = load_cert(&path).unwrap().remove(0);
+        (cert, path)
+    }
+
+    const SHORT_LIVED_NOT_AFTER: u64 = 1735776000;
+
+    #[test]
+    fn cert_expiry() {
+        use std::time::Duration;
+
+        let (cert, path) = short_lived_cert();
+        let not_after = UNIX_EPOCH + Duration::from_secs(SHORT_LIVED_NOT_AFTER);
+        let day = Duration::from_secs(SECONDS_PER_DAY as u64);
+
+        assert_eq!(days_until_expiry(&cert, not_after - day * 45).unwrap(), 45);
+        assert!(check_cert_expiry(&cert, &path, not_after - day * 45).is_ok());
+
+        // Expiring soon is only a warning:
+        assert_eq!(days_until_expiry(&cert, not_after - day * 10).unwrap(), 10);
+        assert!(check_cert_expiry(&cert, &path, not_after - day * 10).is_ok());
+
+        assert_eq!(days_until_expiry(&cert, not_after + day).unwrap(), -1);
+        assert!(check_cert_expiry(&cert, &path, not_after + day).is_err());
+
+        // The certificate has long since expired in real time:
+        let paths = TlsPaths {
+            cert: path.to_str().unwrap().to_string(),
+            key: MISSING.to_string(),
+            ca_cert: MISSING.to_string(),
+        };
+        assert!(check_server_cert(&paths).is_err());
+    }
+
+    fn test_cert(name: &str) -> CertificateDer<'static> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/certs/{name}.crt"));
+        load_cert(&path).unwrap().remove(0)
+    }
+
+    #[test]
+    fn allowed_clients() {
+        let allowed = vec!["halo-mgr-01".to_string(), "halo-mgr-02".to_string()];
+
+        assert!(is_client_allowed(
+            &test_cert("client_halo-mgr-01"),
+            &allowed
+        ));
+        assert!(!is_client_allowed(
+            &test_cert("client_compute-17"),
+            &allowed
+        ));
+
+        // A name given as a subject alternative name counts too:
+        assert!(is_client_allowed(&test_cert("client_san"), &allowed));
+
+        // With no allowlist, any client is allowed:
+        assert!(is_client_allowed(&test_cert("client_compute-17"), &[]));
+    }
+
+    fn cert_path(name: &str) -> String {
+        format!("{}/tests/certs/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn server_only_handshake() {
+        use rustls::pki_types::ServerName;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let paths = TlsPaths {
+            cert: cert_path("server.crt"),
+            key: cert_path("server.key"),
+            ca_cert: cert_path("ca.crt"),
+        };
+        let acceptor = get_acceptor_server_only(&paths).unwrap();
+        let connector = get_connector_server_only(&TlsPaths {
+            cert: MISSING.to_string(),
+            key: MISSING.to_string(),
+            ca_cert: cert_path("ca.crt"),
+        })
+        .unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (client, server) = tokio::io::duplex(4096);
+            let domain = ServerName::try_from("halo-agent").unwrap();
+            let (client, server) =
+                tokio::join!(connector.connect(domain, client), acceptor.accept(server));
+            let mut client = client.unwrap();
+            let mut server = server.unwrap();
+
+            // The client presented no certificate:
+            assert!(server.get_ref().1.peer_certificates().is_none());
+
+            client.write_all(b"ping").await.unwrap();
+            client.flush().await.unwrap();
+            let mut buf = [0; 4];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+        });
+    }
+
+    #[test]
+    fn tls_mode_from_flags() {
+        assert_eq!(TlsMode::from_flags(false, false), TlsMode::None);
+        assert_eq!(TlsMode::from_flags(true, false), TlsMode::Tls);
+        assert_eq!(TlsMode::from_flags(false, true), TlsMode::Mtls);
+    }
+
+    #[test]
+    fn private_key_formats() {
+        let key = load_private_key(Path::new(&cert_path("server.key"))).unwrap();
+        assert!(matches!(key, PrivateKeyDer::Pkcs8(_)));
+
+        let key = load_private_key(Path::new(&cert_path("ec.key"))).unwrap();
+        assert!(matches!(key, PrivateKeyDer::Sec1(_)));
+
+        let key = load_private_key(Path::new(&cert_path("rsa.key"))).unwrap();
+        assert!(matches!(key, PrivateKeyDer::Pkcs1(_)));
+
+        // The certificate ahead of the key is skipped over:
+        let key = load_private_key(Path::new(&cert_path("server_bundle.pem"))).unwrap();
+        assert!(matches!(key, PrivateKeyDer::Pkcs8(_)));
+
+        let path = cert_path("server.crt");
+        let Err(e) = load_private_key(Path::new(&path)) else {
+            panic!("expected an error for a file with no key");
+        };
+        assert!(e.to_string().contains(&path));
+    }
+
+    #[test]
+    fn acceptor_missing_cert() {
+        let Err(e) = get_acceptor(&missing_paths()) else {
+            panic!("expected an error for a missing certificate");
+        };
+        assert!(e.to_string().contains(MISSING));
+    }
+
+    #[test]
+    fn connector_missing_cert() {
+        let Err(e) = get_connector(&missing_paths()) else {
+            panic!("expected an error for a missing certificate");
+        };
+        assert!(e.to_string().contains(MISSING));
+    }
 }
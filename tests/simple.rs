@@ -6,9 +6,9 @@ mod tests {
     use std::sync::Arc;
     use tokio::runtime::Runtime;
 
-    use halo_lib::host::FenceCommand;
+    use halo_lib::host::{FenceCommand, HostStatus};
     use halo_lib::remote::ocf;
-    use halo_lib::resource::{Location, Resource, ResourceStatus};
+    use halo_lib::resource::{is_split_brain, Location, Resource, ResourceStatus};
     use halo_lib::Buffer;
 
     use halo_lib::test_env::*;
@@ -47,7 +47,10 @@ mod tests {
 
                 env.assert_agent_next_line(&agent_expected_line("monitor", res));
 
-                assert_eq!(res.stop().await.unwrap(), ocf::Status::Success);
+                assert_eq!(
+                    res.stop(Location::Home).await.unwrap(),
+                    ocf::Status::Success
+                );
                 env.assert_agent_next_line(&agent_expected_line("stop", res));
             }
         });
@@ -79,7 +82,10 @@ mod tests {
 
                 env.assert_agent_next_line(&agent_expected_line("monitor", res));
 
-                assert_eq!(res.stop().await.unwrap(), ocf::Status::Success);
+                assert_eq!(
+                    res.stop(Location::Home).await.unwrap(),
+                    ocf::Status::Success
+                );
                 env.assert_agent_next_line(&agent_expected_line("stop", res));
             }
         });
@@ -133,6 +139,621 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "slow_tests")]
+    fn cleanup() {
+        let mut env = test_env_helper("cleanup");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(8007, None)]);
+
+        let mut context = env.manager_context();
+        let mgr_stream = Buffer::new();
+        context.out_stream = halo_lib::LogStream::Buffer(mgr_stream);
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+
+        env.start_manager(Arc::clone(&context));
+
+        let resources: Vec<&Resource> = cluster.resources().collect();
+
+        // Wait for the manager to get all of the resources running:
+        for res in &resources {
+            env.assert_manager_next_line(
+                &context,
+                &res.status_update_string(ResourceStatus::Unknown, ResourceStatus::Stopped),
+            );
+        }
+        for res in &resources {
+            env.assert_manager_next_line(
+                &context,
+                &res.status_update_string(ResourceStatus::Stopped, ResourceStatus::RunningOnHome),
+            );
+        }
+
+        let res = cluster.get_resource("test_mgt").unwrap();
+        let socket = context.args.socket.clone().unwrap();
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(["cleanup", "--socket", &socket, "--resource", "test_mgt"])
+            .output()
+            .unwrap();
+        assert!(result.status.success());
+
+        // The cleaned up resource should be forgotten, and then monitored again right away:
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::RunningOnHome, ResourceStatus::Unknown),
+        );
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::Unknown, ResourceStatus::RunningOnHome),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "slow_tests")]
+    fn maintenance() {
+        let mut env = test_env_helper("maintenance");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(8014, None)]);
+
+        let mut context = env.manager_context();
+        let mgr_stream = Buffer::new();
+        context.out_stream = halo_lib::LogStream::Buffer(mgr_stream);
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+
+        env.start_manager(Arc::clone(&context));
+
+        let resources: Vec<&Resource> = cluster.resources().collect();
+
+        // Wait for the manager to get all of the resources running:
+        for res in &resources {
+            env.assert_manager_next_line(
+                &context,
+                &res.status_update_string(ResourceStatus::Unknown, ResourceStatus::Stopped),
+            );
+        }
+        for res in &resources {
+            env.assert_manager_next_line(
+                &context,
+                &res.status_update_string(ResourceStatus::Stopped, ResourceStatus::RunningOnHome),
+            );
+        }
+
+        let socket = context.args.socket.clone().unwrap();
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args([
+                "maintenance",
+                "--socket",
+                &socket,
+                "--host",
+                "127.0.0.1",
+                "--on",
+            ])
+            .output()
+            .unwrap();
+        assert!(result.status.success());
+
+        // The manager should still notice that the resource stopped...
+        let res = cluster.get_resource("test_mgt").unwrap();
+        env.stop_resource(res);
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::RunningOnHome, ResourceStatus::Stopped),
+        );
+
+        // ...but not start it again, even after several of its polling intervals:
+        std::thread::sleep(std::time::Duration::from_secs(10));
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            assert_eq!(
+                res.monitor(Location::Home).await.unwrap(),
+                ocf::Status::ErrNotRunning
+            );
+        });
+    }
+
+    #[test]
+    fn config_manage_mode() {
+        let mut env = test_env_helper("config_manage_mode");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(8042, None)]);
+
+        // The command line takes precedence over the config:
+        let mut context = env.manager_context();
+        context.args.manage_resources = false;
+        context.args.observe_resources = true;
+        assert!(!env.cluster(Some(Arc::new(context))).manage_resources());
+
+        // Without either flag, the config's mode is used, so the manager starts the resource
+        // without being asked to:
+        let mut context = env.manager_context();
+        context.args.manage_resources = false;
+        context.out_stream = halo_lib::LogStream::Buffer(Buffer::new());
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+        assert!(cluster.manage_resources());
+        env.start_manager(Arc::clone(&context));
+
+        let res = cluster.resources().next().unwrap();
+        let timeout = std::time::Duration::from_secs(10);
+        env.assert_resource_reaches(&context, res, ResourceStatus::RunningOnHome, timeout);
+    }
+
+    #[test]
+    fn disabled_resource() {
+        let mut env = test_env_helper("disabled_resource");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(8043, None)]);
+
+        let mut context = env.manager_context();
+        context.out_stream = halo_lib::LogStream::Buffer(Buffer::new());
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+        env.start_manager(Arc::clone(&context));
+
+        let zpool = cluster.get_resource("test_zpool").unwrap();
+        let mgt = cluster.get_resource("test_mgt").unwrap();
+        assert_eq!(mgt.get_status(), ResourceStatus::Disabled);
+
+        let timeout = std::time::Duration::from_secs(10);
+        env.assert_resource_reaches(&context, zpool, ResourceStatus::RunningOnHome, timeout);
+
+        // Give the manager a few more passes in which it might have started the target:
+        std::thread::sleep(std::time::Duration::from_secs(7));
+        let rt = Runtime::new().unwrap();
+        assert_eq!(
+            rt.block_on(mgt.monitor(Location::Home)).unwrap(),
+            ocf::Status::ErrNotRunning
+        );
+        assert_eq!(mgt.get_status(), ResourceStatus::Disabled);
+    }
+
+    #[test]
+    fn resource_rpc() {
+        let mut env = test_env_helper("resource_rpc");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(8017, None)]);
+
+        // In Observe mode, the manager only starts a resource when asked to:
+        let mut context = env.manager_context();
+        context.args.manage_resources = false;
+        let mgr_stream = Buffer::new();
+        context.out_stream = halo_lib::LogStream::Buffer(mgr_stream);
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+
+        env.start_manager(Arc::clone(&context));
+
+        let res = cluster.get_resource("test_zpool").unwrap();
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::Unknown, ResourceStatus::Stopped),
+        );
+
+        let socket = context.args.socket.clone().unwrap();
+        let halo = |action: &str| {
+            std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+                .args(["resource", action, "test_zpool", "--socket", &socket])
+                .output()
+                .unwrap()
+        };
+
+        assert!(halo("start").status.success());
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::Stopped, ResourceStatus::RunningOnHome),
+        );
+
+        // The agent really did start the resource:
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            assert_eq!(
+                res.monitor(Location::Home).await.unwrap(),
+                ocf::Status::Success
+            );
+        });
+
+        assert!(halo("stop").status.success());
+        env.assert_manager_next_line(
+            &context,
+            &res.status_update_string(ResourceStatus::RunningOnHome, ResourceStatus::Stopped),
+        );
+
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(["resource", "start", "no_such_resource", "--socket", &socket])
+            .output()
+            .unwrap();
+        assert!(!result.status.success());
+        assert!(String::from_utf8(result.stderr)
+            .unwrap()
+            .contains("no_such_resource"));
+    }
+
+    #[test]
+    fn dry_run() {
+        let mut env = test_env_helper("dry_run");
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(
+            8021,
+            Some("dry_run_mds00".to_string()),
+        )]);
+
+        let config = env.manager_context().args.config_path();
+        let halo = |args: &[&str]| {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+                .args(["--config", &config, "--dry-run"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        // The zpool is started before the target on it, and stopped after it:
+        let out = halo(&["start"]);
+        let zpool = out.find(r#"Would start resource "test_zpool""#).unwrap();
+        let mgt = out.find(r#"Would start resource "test_mgt""#).unwrap();
+        assert!(zpool < mgt);
+
+        let out = halo(&["stop"]);
+        let zpool = out.find(r#"Would stop resource "test_zpool""#).unwrap();
+        let mgt = out.find(r#"Would stop resource "test_mgt""#).unwrap();
+        assert!(mgt < zpool);
+
+        let out = halo(&["power", "off", "dry_run_mds00"]);
+        assert!(out.contains("Would fence host dry_run_mds00"));
+
+        // Nothing was actually done:
+        env.assert_no_agent_lines();
+        let cluster = env.cluster(None);
+        let host = cluster.hosts().next().unwrap();
+        assert!(host.is_powered_on().unwrap());
+    }
+
+    #[test]
+    fn move_group() {
+        let env = test_env_helper("move");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8008, Some("move_mds00".to_string())),
+            TestAgent::new(8009, Some("move_mds01".to_string())),
+        ]);
+
+        let cluster = env.cluster(None);
+        let group = cluster.get_resource_group("test_zpool").unwrap();
+
+        // Asserts that every resource in the group is running at `running`, and not at the other
+        // location:
+        let assert_location = |running: Location| async move {
+            for res in group.resources() {
+                assert_eq!(res.monitor(running).await.unwrap(), ocf::Status::Success);
+                assert_eq!(
+                    res.monitor(running.other()).await.unwrap(),
+                    ocf::Status::ErrNotRunning
+                );
+            }
+        };
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            group.relocate(Location::Home).await.unwrap();
+            assert_location(Location::Home).await;
+
+            group.relocate(Location::Away).await.unwrap();
+            assert_location(Location::Away).await;
+
+            group.relocate(Location::Home).await.unwrap();
+            assert_location(Location::Home).await;
+        });
+    }
+
+    #[test]
+    fn colocation() {
+        let env = test_env_helper("colocation");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8022, Some("colocation_mds00".to_string())),
+            TestAgent::new(8023, Some("colocation_mds01".to_string())),
+        ]);
+
+        let cluster = env.cluster(None);
+        let group_a = cluster.get_resource_group("test_zpool_a").unwrap();
+        let group_b = cluster.get_resource_group("test_zpool_b").unwrap();
+        assert_eq!(cluster.colocated_groups("test_zpool_a").len(), 1);
+
+        // Asserts that every resource in both groups is running at `running`, and not at the
+        // other location:
+        let assert_location = |running: Location| async move {
+            for res in group_a.resources().chain(group_b.resources()) {
+                assert_eq!(res.monitor(running).await.unwrap(), ocf::Status::Success);
+                assert_eq!(
+                    res.monitor(running.other()).await.unwrap(),
+                    ocf::Status::ErrNotRunning
+                );
+            }
+        };
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            cluster
+                .relocate_group("test_zpool_a", Location::Home)
+                .await
+                .unwrap();
+            assert_location(Location::Home).await;
+
+            // Failing over the group with the MGS takes the MDT along with it:
+            cluster
+                .relocate_group("test_zpool_a", Location::Away)
+                .await
+                .unwrap();
+            assert_location(Location::Away).await;
+
+            // And the other way around:
+            cluster
+                .relocate_group("test_zpool_b", Location::Home)
+                .await
+                .unwrap();
+            assert_location(Location::Home).await;
+        });
+    }
+
+    #[test]
+    fn anti_affinity() {
+        let env = test_env_helper("anti_affinity");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8024, Some("anti_affinity_mds00".to_string())),
+            TestAgent::new(8025, Some("anti_affinity_mds01".to_string())),
+        ]);
+
+        let cluster = env.cluster(None);
+        let group_a = cluster.get_resource_group("test_zpool_a").unwrap();
+        let group_b = cluster.get_resource_group("test_zpool_b").unwrap();
+        assert_eq!(cluster.anti_affine_groups("test_zpool_a").len(), 1);
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            cluster
+                .relocate_group("test_zpool_a", Location::Home)
+                .await
+                .unwrap();
+            cluster
+                .relocate_group("test_zpool_b", Location::Home)
+                .await
+                .unwrap();
+
+            // Each group is running on its own home host, so neither may fail over onto the
+            // other's:
+            assert!(cluster
+                .relocate_group("test_zpool_a", Location::Away)
+                .await
+                .is_err());
+            assert!(cluster
+                .relocate_group("test_zpool_b", Location::Away)
+                .await
+                .is_err());
+
+            for (group, loc) in [(group_a, Location::Home), (group_b, Location::Home)] {
+                let root = &group.root;
+                assert_eq!(root.monitor(loc).await.unwrap(), ocf::Status::Success);
+                assert_eq!(
+                    root.monitor(loc.other()).await.unwrap(),
+                    ocf::Status::ErrNotRunning
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn split_brain() {
+        let env = test_env_helper("split_brain");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8027, Some("split_brain_mds00".to_string())),
+            TestAgent::new(8028, Some("split_brain_mds01".to_string())),
+        ]);
+
+        let cluster = env.cluster(None);
+        let zpool = &cluster.get_resource_group("test_zpool").unwrap().root;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            zpool.start(Location::Home).await.unwrap();
+            let (home, away) = zpool.monitor_all_locations().await;
+            assert_eq!(home.as_ref().unwrap(), &ocf::Status::Success);
+            assert_eq!(away.as_ref().unwrap(), &ocf::Status::ErrNotRunning);
+            assert!(!is_split_brain(&home, &away));
+
+            // Starting the zpool on the failover host as well leaves it running on both:
+            zpool.start(Location::Away).await.unwrap();
+            let (home, away) = zpool.monitor_all_locations().await;
+            assert!(is_split_brain(&home, &away));
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "slow_tests")]
+    fn split_brain_refused() {
+        let mut env = test_env_helper("split_brain_refused");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8029, Some("split_brain_refused_mds00".to_string())),
+            TestAgent::new(8030, Some("split_brain_refused_mds01".to_string())),
+        ]);
+
+        let context = Arc::new(env.manager_context());
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+        let group = cluster.get_resource_group("test_zpool").unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            group.root.start(Location::Home).await.unwrap();
+            group.root.start(Location::Away).await.unwrap();
+        });
+        env.assert_agent_next_line("zfs start pool=test_zpool");
+        env.assert_agent_next_line("zfs start pool=test_zpool");
+
+        // Managing the group finds it running on both hosts, and goes no further than monitoring
+        // it:
+        let manage_for = |duration| {
+            rt.block_on(async {
+                let _ = tokio::time::timeout(duration, group.main_loop(&context.args)).await;
+            })
+        };
+        manage_for(std::time::Duration::from_secs(1));
+        assert_eq!(group.root.get_status(), ResourceStatus::SplitBrain);
+        assert_eq!(
+            env.agent_lines(),
+            vec!["zfs monitor pool=test_zpool", "zfs monitor pool=test_zpool"]
+        );
+
+        // From then on, the group is left alone, and can't be moved:
+        manage_for(std::time::Duration::from_secs(4));
+        assert!(rt
+            .block_on(cluster.relocate_group("test_zpool", Location::Home))
+            .is_err());
+        assert_eq!(group.root.get_status(), ResourceStatus::SplitBrain);
+        env.assert_no_agent_lines();
+    }
+
+    #[test]
+    fn validate_check_agents() {
+        let env = test_env_helper("check_agents");
+
+        // Only the first of the two hosts in the config has an agent running:
+        let _agent = env.start_remote_agents(vec![TestAgent::new(
+            8015,
+            Some("check_agents_up".to_string()),
+        )]);
+
+        let config_path = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/check_agents.toml"
+        );
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(["validate", "--config", &config_path, "--check-agents"])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let out_message = String::from_utf8(result.stdout).unwrap();
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(out_message.contains("check_agents_up (127.0.0.1:8015): reachable"));
+        assert!(err_message.contains("check_agents_down (127.0.0.1:8016): unreachable"));
+        assert!(!err_message.contains("check_agents_up"));
+    }
+
+    /// Launch the manager binary for a test, returning a handle to its process together with the
+    /// path of its socket once it is listening.
+    fn start_manager_process(env: &TestEnvironment) -> (ChildHandle, String) {
+        let config = env.manager_context().args.config_path();
+        start_manager_process_with_config(env, &config)
+    }
+
+    /// Like start_manager_process(), but using the given config file rather than the test's own.
+    fn start_manager_process_with_config(
+        env: &TestEnvironment,
+        config: &str,
+    ) -> (ChildHandle, String) {
+        let socket = env.manager_context().args.socket.unwrap();
+
+        let manager = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(["--config", config, "--socket", &socket])
+            .spawn()
+            .unwrap();
+
+        // Wait for the manager to start listening on its socket:
+        let mut counter = 100;
+        while !std::path::Path::new(&socket).exists() && counter > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            counter -= 1;
+        }
+        assert!(std::path::Path::new(&socket).exists());
+
+        (ChildHandle { handle: manager }, socket)
+    }
+
+    #[test]
+    fn manager_shutdown() {
+        use nix::{sys::signal, unistd::Pid};
+
+        let env = test_env_helper("manager_shutdown");
+        let (mut manager, socket) = start_manager_process(&env);
+
+        signal::kill(
+            Pid::from_raw(manager.handle.id() as i32),
+            signal::Signal::SIGTERM,
+        )
+        .unwrap();
+
+        // The manager should exit cleanly, and clean up its socket on the way out:
+        assert!(manager.handle.wait().unwrap().success());
+        assert!(!std::path::Path::new(&socket).exists());
+    }
+
+    #[test]
+    fn reload_config() {
+        use nix::{sys::signal, unistd::Pid};
+
+        let env = test_env_helper("reload");
+        let _agents =
+            env.start_remote_agents(vec![TestAgent::new(8018, None), TestAgent::new(8019, None)]);
+
+        // The config is rewritten during the test, so the manager is given a copy of it:
+        let test_config = |name: &str| {
+            format!(
+                "{}/tests/{name}.toml",
+                std::env::var("CARGO_MANIFEST_DIR").unwrap()
+            )
+        };
+        let config = std::env::temp_dir().join(format!("halo_reload_{}.toml", std::process::id()));
+        std::fs::copy(test_config("reload"), &config).unwrap();
+
+        let (manager, socket) = start_manager_process_with_config(&env, config.to_str().unwrap());
+
+        // Whether the manager reports that the added resource was monitored, and found stopped:
+        let added_monitored = || {
+            let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+                .args(["status", "--socket", &socket])
+                .output()
+                .unwrap();
+            String::from_utf8(result.stdout)
+                .unwrap()
+                .lines()
+                .any(|line| line.starts_with("Stopped") && line.contains("test_zpool_b"))
+        };
+        assert!(!added_monitored());
+
+        std::fs::copy(test_config("reload_added"), &config).unwrap();
+        signal::kill(
+            Pid::from_raw(manager.handle.id() as i32),
+            signal::Signal::SIGHUP,
+        )
+        .unwrap();
+
+        let mut counter = 100;
+        while !added_monitored() && counter > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            counter -= 1;
+        }
+        assert!(added_monitored());
+
+        std::fs::remove_file(&config).unwrap();
+    }
+
+    #[test]
+    fn socket_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let env = test_env_helper("socket_permissions");
+        let (_manager, socket) = start_manager_process(&env);
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+    }
+
     #[test]
     fn fencing() {
         let env = test_env_helper("fencing");
@@ -163,4 +784,233 @@ mod tests {
         let powered_on = host.is_powered_on().unwrap();
         assert!(powered_on);
     }
+
+    #[test]
+    fn fence_fallback() {
+        let env = test_env_helper("fence_fallback");
+
+        let cluster = env.cluster(None);
+        let host = cluster.hosts().nth(0).unwrap();
+
+        let _agent = env.start_remote_agents(vec![TestAgent::new(
+            8011,
+            Some("fallback_mds00".to_string()),
+        )]);
+
+        // The first fence agent fails every action, so these must be done by the second one:
+        assert!(host.is_powered_on().unwrap());
+        host.do_fence(FenceCommand::Off).unwrap();
+        assert_eq!(host.get_status(), HostStatus::Down);
+        assert!(!host.is_powered_on().unwrap());
+    }
+
+    #[test]
+    fn power_parallel() {
+        let env = test_env_helper("power_parallel");
+
+        let agents: Vec<TestAgent> = (0..3)
+            .map(|i| TestAgent::new(8039 + i, Some(format!("power_parallel_mds0{i}"))))
+            .collect();
+        let agent_ids: Vec<String> = agents.iter().map(|a| a.id.clone().unwrap()).collect();
+        let _agents = env.start_remote_agents(agents);
+        for id in agent_ids.iter() {
+            assert!(env.agent_pid(id).is_some());
+        }
+
+        let config = env.manager_context().args.config_path();
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(["--config", &config, "power", "off", "--parallel=2"])
+            .args(["127.0.0.1:8039", "127.0.0.1:8040", "127.0.0.1:8041"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        // Each host's result is reported on its own line, naming it:
+        let err = String::from_utf8(output.stderr).unwrap();
+        for (id, port) in agent_ids.iter().zip(8039..) {
+            assert!(err.contains(&format!("{id} (127.0.0.1:{port}) Fence: Success")));
+        }
+
+        // Each agent removes its PID file as it is shut down by the fence agent:
+        for id in agent_ids.iter() {
+            assert_eq!(env.agent_pid(id), None);
+        }
+    }
+
+    #[test]
+    fn reboot_on_failover() {
+        let env = test_env_helper("reboot_on_failover");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8031, Some("reboot_failover_mds00".to_string())),
+            TestAgent::new(8032, Some("reboot_failover_mds01".to_string())),
+        ]);
+
+        let mut context = env.manager_context();
+        context.args.reboot_on_failover = true;
+        let cluster = env.cluster(Some(Arc::new(context)));
+        let group = cluster.get_resource_group("test_zpool").unwrap();
+        let home = &group.root.home_node;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            group.relocate(Location::Home).await.unwrap();
+            home.set_status(HostStatus::Up);
+
+            let old_pid = env.agent_pid("reboot_failover_mds00").unwrap();
+            cluster
+                .fail_over_group("test_zpool", Location::Away)
+                .await
+                .unwrap();
+            let _restarted = env.restarted_agent("reboot_failover_mds00").unwrap();
+
+            // Powering the home host off would leave it Down; rebooting it leaves it Unknown until
+            // it is monitored again, though it has come back up:
+            assert_eq!(home.get_status(), HostStatus::Unknown);
+            assert_ne!(env.agent_pid("reboot_failover_mds00").unwrap(), old_pid);
+            assert!(home.is_powered_on_async().await.unwrap());
+            for res in group.resources() {
+                assert_eq!(res.get_status(), ResourceStatus::RunningOnAway);
+                assert_eq!(
+                    res.monitor(Location::Away).await.unwrap(),
+                    ocf::Status::Success
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn reboot_once_for_several_groups() {
+        let env = test_env_helper("reboot_once");
+
+        let _agents = env.start_remote_agents(vec![
+            TestAgent::new(8044, Some("reboot_once_oss00".to_string())),
+            TestAgent::new(8045, Some("reboot_once_oss01".to_string())),
+        ]);
+
+        let mut context = env.manager_context();
+        context.args.reboot_on_failover = true;
+        let cluster = env.cluster(Some(Arc::new(context)));
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            for id in ["test_zpool_a", "test_zpool_b"] {
+                let group = cluster.get_resource_group(id).unwrap();
+                group.relocate(Location::Home).await.unwrap();
+            }
+
+            // Failing the first group over reboots the home host:
+            let old_pid = env.agent_pid("reboot_once_oss00").unwrap();
+            cluster
+                .fail_over_group("test_zpool_a", Location::Away)
+                .await
+                .unwrap();
+            let _restarted = env.restarted_agent("reboot_once_oss00").unwrap();
+            let rebooted_pid = env.agent_pid("reboot_once_oss00").unwrap();
+            assert_ne!(rebooted_pid, old_pid);
+
+            // The second group, which isn't colocated with the first, fails over without
+            // rebooting the host again:
+            cluster
+                .fail_over_group("test_zpool_b", Location::Away)
+                .await
+                .unwrap();
+            assert_eq!(env.agent_pid("reboot_once_oss00").unwrap(), rebooted_pid);
+            for id in ["test_zpool_a", "test_zpool_b"] {
+                let root = &cluster.get_resource_group(id).unwrap().root;
+                assert_eq!(root.get_status(), ResourceStatus::RunningOnAway);
+            }
+        });
+    }
+
+    #[test]
+    fn fence_retry() {
+        let env = test_env_helper("fence_retry");
+
+        let cluster = env.cluster(None);
+        let host = cluster.hosts().nth(0).unwrap();
+
+        let _agent =
+            env.start_remote_agents(vec![TestAgent::new(8033, Some("retry_mds00".to_string()))]);
+
+        // The fence agent fails its first power action, so fencing only succeeds on the retry:
+        assert!(host.is_powered_on().unwrap());
+        host.do_fence(FenceCommand::Off).unwrap();
+        assert_eq!(host.get_status(), HostStatus::Down);
+        assert!(!host.is_powered_on().unwrap());
+    }
+
+    #[test]
+    fn fence_reboot() {
+        let env = test_env_helper("fence_reboot");
+
+        let cluster = env.cluster(None);
+        let host = cluster.hosts().nth(0).unwrap();
+
+        let _agent =
+            env.start_remote_agents(vec![TestAgent::new(8010, Some("reboot_mds00".to_string()))]);
+        assert!(host.is_powered_on().unwrap());
+        host.set_status(HostStatus::Up);
+        let old_pid = env.agent_pid("reboot_mds00").unwrap();
+
+        // Rebooting should succeed, leaving the host's status unknown until it is monitored again:
+        host.do_fence(FenceCommand::Reboot).unwrap();
+        assert_eq!(host.get_status(), HostStatus::Unknown);
+
+        // The test fence agent restarts the agent, which records its new PID:
+        let _restarted = env.restarted_agent("reboot_mds00").unwrap();
+        assert_ne!(env.agent_pid("reboot_mds00").unwrap(), old_pid);
+        assert!(host.is_powered_on().unwrap());
+    }
+
+    #[test]
+    fn agent_not_ready() {
+        // A "remote agent" which exits straight away, and so never listens on its port:
+        let env = TestEnvironment::new("agent_not_ready".to_string(), "/bin/true");
+
+        let start = std::time::Instant::now();
+        let result = env.try_start_remote_agents(
+            vec![TestAgent::new(8034, None)],
+            std::time::Duration::from_millis(500),
+        );
+        let Err(e) = result else {
+            panic!("expected the agent not to become ready");
+        };
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+        assert!(e.to_string().contains("8034"));
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    #[cfg(feature = "slow_tests")]
+    fn manager_failover() {
+        let mut env = test_env_helper("manager_failover");
+
+        let home_agent = env.start_remote_agents(vec![TestAgent::new(
+            8035,
+            Some("mgr_failover_mds00".to_string()),
+        )]);
+        let _away_agent = env.start_remote_agents(vec![TestAgent::new(
+            8036,
+            Some("mgr_failover_mds01".to_string()),
+        )]);
+
+        let mut context = env.manager_context();
+        context.out_stream = halo_lib::LogStream::Buffer(Buffer::new());
+        let context = Arc::new(context);
+        let cluster = env.cluster(Some(Arc::clone(&context)));
+        env.start_manager(Arc::clone(&context));
+
+        let timeout = std::time::Duration::from_secs(30);
+        for res in cluster.resources() {
+            env.assert_resource_reaches(&context, res, ResourceStatus::RunningOnHome, timeout);
+        }
+
+        // When the home host goes away, the manager fences it and moves the resources to the
+        // failover host, by way of whichever statuses it passes through on the way:
+        drop(home_agent);
+        for res in cluster.resources() {
+            env.assert_resource_reaches(&context, res, ResourceStatus::RunningOnAway, timeout);
+        }
+    }
 }
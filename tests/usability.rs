@@ -22,6 +22,98 @@ mod tests {
         assert!(err_message.contains(invalid_network));
     }
 
+    #[test]
+    fn remote_bind_address() {
+        let mut agent = std::process::Command::new(env!("CARGO_BIN_EXE_halo_remote"))
+            .args(vec!["--bind-address", "127.0.0.1:8020", "--no-pid-file"])
+            .spawn()
+            .unwrap();
+
+        let mut listening = false;
+        for _ in 0..50 {
+            if std::net::TcpStream::connect("127.0.0.1:8020").is_ok() {
+                listening = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        agent.kill().unwrap();
+        agent.wait().unwrap();
+        assert!(listening);
+    }
+
+    #[test]
+    fn remote_duplicate_pid_file() {
+        let pid_file = std::env::temp_dir().join(format!("halo_remote_{}.pid", std::process::id()));
+        let pid_file = pid_file.to_str().unwrap();
+        let agent = |address| {
+            let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_halo_remote"));
+            command.args(vec!["--bind-address", address, "--pid-file", pid_file]);
+            command
+        };
+
+        let mut first = agent("127.0.0.1:8037").spawn().unwrap();
+        for _ in 0..50 {
+            if std::net::TcpStream::connect("127.0.0.1:8037").is_ok() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        // A second agent with the same PID file refuses to start, even on another address:
+        let result = agent("127.0.0.1:8038").output().unwrap();
+        first.kill().unwrap();
+        first.wait().unwrap();
+        let _ = std::fs::remove_file(pid_file);
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("already running"));
+        assert!(err_message.contains(pid_file));
+    }
+
+    #[test]
+    fn remote_non_local_bind_address() {
+        // An address from TEST-NET-1, which no host should have:
+        let non_local = "192.0.2.1";
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo_remote"))
+            .args(vec!["--bind-address", non_local])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains(non_local));
+        assert!(err_message.contains("--bind-address"));
+    }
+
+    #[test]
+    fn remote_listen() {
+        // A port number is required, and there is no such address as 127.0.0.256:
+        for invalid_listen in ["127.0.0.1", "127.0.0.256:8000", "localhost:8000"] {
+            let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo_remote"))
+                .args(vec!["--listen", invalid_listen, "--no-pid-file"])
+                .output()
+                .unwrap();
+
+            assert!(!result.status.success());
+            let err_message = String::from_utf8(result.stderr).unwrap();
+            assert!(err_message.contains(invalid_listen));
+        }
+
+        // An address from TEST-NET-1, which no host should have:
+        let non_local = "192.0.2.1:8000";
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo_remote"))
+            .args(vec!["--listen", non_local, "--no-pid-file"])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("192.0.2.1"));
+        assert!(err_message.contains("--listen"));
+    }
+
     #[test]
     fn manager_config() {
         let invalid_config = "this_file_does_not_exist";
@@ -57,4 +149,123 @@ mod tests {
         let err_message = String::from_utf8(result.stderr).unwrap();
         assert!(err_message.contains(invalid_socket));
     }
+
+    #[test]
+    fn manager_metrics_addr_in_use() {
+        let good_config_path = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/simple.toml"
+        );
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let metrics_addr = taken.local_addr().unwrap().to_string();
+        let socket =
+            std::env::temp_dir().join(format!("halo_metrics_{}.socket", std::process::id()));
+        let socket = socket.to_str().unwrap();
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec![
+                "--config",
+                &good_config_path,
+                "--socket",
+                socket,
+                "--metrics-addr",
+                &metrics_addr,
+            ])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains(&metrics_addr));
+        // The manager gave up before creating its socket, so it left none behind:
+        assert!(!std::path::Path::new(socket).exists());
+    }
+
+    #[test]
+    fn validate_dangling_requires() {
+        let config_path = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/dangling_requires.toml"
+        );
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec!["validate", "--config", &config_path])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("test_mgt"));
+        assert!(err_message.contains("no_such_zpool"));
+    }
+
+    #[test]
+    fn validate_dependency_cycle() {
+        let config_path = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/dependency_cycle.toml"
+        );
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec!["validate", "--config", &config_path])
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("dependency cycle"));
+        assert!(!err_message.contains("panicked"));
+    }
+
+    #[test]
+    fn validate_unknown_kind() {
+        let config_path = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/unknown_kind.toml"
+        );
+
+        // An unknown kind is only a warning by default...
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec!["validate", "--config", &config_path])
+            .env_remove("OCF_ROOT")
+            .output()
+            .unwrap();
+
+        assert!(result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("warning"));
+        assert!(err_message.contains("test_mgt"));
+        assert!(err_message.contains("lustre/Lustr"));
+        assert!(!err_message.contains("test_zpool"));
+
+        // ...but an error when checking strictly:
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec!["validate", "--config", &config_path, "--strict"])
+            .env_remove("OCF_ROOT")
+            .output()
+            .unwrap();
+
+        assert!(!result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("error"));
+        assert!(err_message.contains("lustre/Lustr"));
+
+        // When OCF_ROOT is set, the missing script is reported too:
+        let ocf_root = format!(
+            "{}/{}",
+            std::env::var("CARGO_MANIFEST_DIR").unwrap(),
+            "tests/ocf_resources"
+        );
+        let result = std::process::Command::new(env!("CARGO_BIN_EXE_halo"))
+            .args(vec!["validate", "--config", &config_path])
+            .env("OCF_ROOT", &ocf_root)
+            .output()
+            .unwrap();
+
+        assert!(result.status.success());
+        let err_message = String::from_utf8(result.stderr).unwrap();
+        assert!(err_message.contains("resource.d/lustre/Lustr"));
+        assert!(!err_message.contains("resource.d/heartbeat/ZFS"));
+    }
 }